@@ -0,0 +1,46 @@
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+use fastslam::geometry::Point;
+use fastslam::odometry::Pose;
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::icp::{icp, nearest_neighbor};
+use fastslam::scanmatching::kdtree::KdTree;
+
+// a scan of a 10m x 6m room with one point per degree
+fn room_scan() -> PointCloud {
+    PointCloud::new(
+        (0..360)
+            .map(|i| {
+                let angle = (i as f64).to_radians();
+                let (dx, dy) = (angle.cos(), angle.sin());
+                let range = (5.0 / dx.abs()).min(3.0 / dy.abs());
+                Point::new(range * dx, range * dy)
+            })
+            .collect()
+    )
+}
+
+#[bench]
+fn bench_nearest_neighbor(b: &mut Bencher) {
+    let reference = room_scan();
+    let query = reference.transform(&Pose::new(Point::new(0.1, -0.05), 0.02));
+    b.iter(|| nearest_neighbor(&query, &reference))
+}
+
+#[bench]
+fn bench_kdtree_query(b: &mut Bencher) {
+    let reference = room_scan();
+    let query = reference.transform(&Pose::new(Point::new(0.1, -0.05), 0.02));
+    let tree = KdTree::new(&reference);
+    b.iter(|| tree.query(&query))
+}
+
+#[bench]
+fn bench_icp(b: &mut Bencher) {
+    let reference = room_scan();
+    let query = reference.transform(&Pose::new(Point::new(0.1, -0.05), 0.02));
+    b.iter(|| icp(&query, &reference, 20, 0.00000000001))
+}
@@ -0,0 +1,145 @@
+use crate::geometry::point::Point;
+use crate::gridmap::grid_map::{CellState, GridMap, MapBounds};
+use crate::math::scalar::Scalar;
+
+/// distances are saturated at this value [m] unless another range is requested
+pub const DEFAULT_MAX_DISTANCE: Scalar = 2.0;
+
+/// stand-in for infinity that keeps the parabola intersections finite
+const FAR: Scalar = 1e20;
+
+/// Euclidean distance transform of a GridMap: the distance from every cell to the
+/// nearest occupied cell, saturated at max_distance. Used as the likelihood field of
+/// the range finder model, so a lookup is O(1) per beam.
+///
+/// More info:
+///  - p.169 in probabilistic robotics, Sebastian Thrun et al.
+///  - Distance Transforms of Sampled Functions, P. Felzenszwalb and D. Huttenlocher
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    bounds: Option<MapBounds>,
+    cell_size: Scalar,
+    max_distance: Scalar,
+    distances: Vec<Scalar>, // row-major in x, in meter
+}
+
+impl DistanceField {
+    pub fn new(gridmap: &GridMap, max_distance: Scalar) -> DistanceField {
+        let cell_size = gridmap.cell_size();
+
+        // pad the explored area so cells just outside of it get their true distance
+        let bounds = gridmap.bounds().map(|b| {
+            let pad = (max_distance / cell_size).ceil() as i64;
+            MapBounds {
+                min_x: b.min_x - pad,
+                min_y: b.min_y - pad,
+                max_x: b.max_x + pad,
+                max_y: b.max_y + pad,
+            }
+        });
+
+        let distances = match bounds {
+            None => vec![],
+            Some(b) => {
+                let (width, height) = (b.width(), b.height());
+
+                // squared distance in cells, 0 for occupied cells
+                let mut grid: Vec<Scalar> = Vec::with_capacity(width * height);
+                for x in b.min_x..=b.max_x {
+                    for y in b.min_y..=b.max_y {
+                        grid.push(match gridmap.cell_state(x, y) {
+                            CellState::Occupied => 0.0,
+                            _ => FAR
+                        });
+                    }
+                }
+
+                // transform along y (each row of the vec), then along x (each column)
+                let mut f = vec![0.0; width.max(height)];
+                let mut d = vec![0.0; width.max(height)];
+                for x in 0..width {
+                    let row = &mut grid[x * height..(x + 1) * height];
+                    squared_distance_1d(row, &mut d[..height]);
+                    row.copy_from_slice(&d[..height]);
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        f[x] = grid[x * height + y];
+                    }
+                    squared_distance_1d(&f[..width], &mut d[..width]);
+                    for x in 0..width {
+                        grid[x * height + y] = d[x];
+                    }
+                }
+
+                grid.iter()
+                    .map(|&d2| (d2.sqrt() * cell_size).min(max_distance))
+                    .collect()
+            }
+        };
+
+        DistanceField { bounds, cell_size, max_distance, distances }
+    }
+
+    pub fn max_distance(&self) -> Scalar {
+        self.max_distance
+    }
+
+    /// distance [m] from the cell (x, y) to the nearest occupied cell
+    pub fn distance(&self, x: i64, y: i64) -> Scalar {
+        match self.bounds {
+            Some(b) if b.contains(x, y) => {
+                let index = (x - b.min_x) as usize * b.height() + (y - b.min_y) as usize;
+                self.distances[index]
+            }
+            _ => self.max_distance
+        }
+    }
+
+    /// distance [m] from the cell containing the world point p to the nearest occupied cell
+    pub fn distance_at(&self, p: Point) -> Scalar {
+        let x = (p.x / self.cell_size).floor();
+        let y = (p.y / self.cell_size).floor();
+        if x.is_finite() && y.is_finite() {
+            self.distance(x as i64, y as i64)
+        } else {
+            self.max_distance
+        }
+    }
+}
+
+/// one-dimensional squared distance transform of the sampled function f, written to d
+fn squared_distance_1d(f: &[Scalar], d: &mut [Scalar]) {
+    let n = f.len();
+    let mut v: Vec<usize> = vec![0; n]; // locations of the parabolas in the lower envelope
+    let mut z: Vec<Scalar> = vec![0.0; n + 1]; // boundaries between the parabolas
+    let mut k: usize = 0;
+    z[0] = Scalar::NEG_INFINITY;
+    z[1] = Scalar::INFINITY;
+
+    let intersection = |q: usize, p: usize| {
+        let (q_f, p_f) = (q as Scalar, p as Scalar);
+        ((f[q] + q_f * q_f) - (f[p] + p_f * p_f)) / (2.0 * q_f - 2.0 * p_f)
+    };
+
+    for q in 1..n {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = Scalar::INFINITY;
+    }
+
+    k = 0;
+    for (q, d_q) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as Scalar {
+            k += 1;
+        }
+        let dist = q as Scalar - v[k] as Scalar;
+        *d_q = dist * dist + f[v[k]];
+    }
+}
@@ -1,14 +1,21 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 use crate::geometry::point::Point;
+use crate::gridmap::distance_field::{DistanceField, DEFAULT_MAX_DISTANCE};
 use crate::math::scalar::Scalar;
+use crate::math::utils::{log_odds, sigmoid};
 use crate::odometry::pose::Pose;
 use crate::sensor::laserscanner::Scan;
-use crate::geometry::vector::Vector;
 use line_drawing::Bresenham;
 
+/// number of cells along each side of a square tile
+pub const TILE_SIZE: i64 = 32;
+
+/// Thresholded view of the occupancy belief of a single cell
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CellState {
-    Occupied(u32),
+    Occupied,
     Freespace,
     Void,
 }
@@ -19,132 +26,379 @@ impl Default for CellState {
     }
 }
 
+/// Parameters of the inverse sensor model used to update the per-cell log-odds
+///
+/// More info:
+///  - p.286 Table 9.1 in probabilistic robotics, Sebastian Thrun et al.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LogOddsParams {
+    pub hit: Scalar, // log-odds increment for the cell a beam ends in
+    pub miss: Scalar, // log-odds increment for the cells a beam passes through
+    pub min: Scalar, // lower clamping bound, keeps cells able to change their mind
+    pub max: Scalar, // upper clamping bound
+    pub occupied_threshold: Scalar, // probability above which a cell is considered occupied
+    pub free_threshold: Scalar, // probability below which a cell is considered free
+    pub max_range: Scalar, // [m] longer beams are not integrated into the map
+}
+
+impl Default for LogOddsParams {
+    fn default() -> LogOddsParams {
+        LogOddsParams::from_probabilities(0.85, 0.35, 0.12, 0.97).expect("the default probabilities are valid")
+    }
+}
+
+impl LogOddsParams {
+    /// create the log-odds parameters from the hit/miss probabilities of the inverse sensor
+    /// model and the probabilities the cells are clamped to, with the default thresholds of
+    /// the tri-state view. None unless all probabilities are in (0.0, 1.0) and p_min < p_max,
+    /// the log-odds of 0.0 and 1.0 are infinite
+    pub fn from_probabilities(p_hit: Scalar, p_miss: Scalar, p_min: Scalar, p_max: Scalar) -> Option<LogOddsParams> {
+        let valid = |p: Scalar| p > 0.0 && p < 1.0;
+        if !([p_hit, p_miss, p_min, p_max].iter().all(|&p| valid(p)) && p_min < p_max) {
+            return None
+        }
+
+        Some(LogOddsParams {
+            hit: log_odds(p_hit),
+            miss: log_odds(p_miss),
+            min: log_odds(p_min),
+            max: log_odds(p_max),
+            occupied_threshold: 0.65,
+            free_threshold: 0.4,
+            max_range: 1000.0, // far beyond the range of any laser scanner
+        })
+    }
+
+    /// the parameters with other probabilities at and below which a cell is free or at and
+    /// above which it is occupied, None unless 0.0 <= free_threshold < occupied_threshold <= 1.0
+    pub fn with_thresholds(self, free_threshold: Scalar, occupied_threshold: Scalar) -> Option<LogOddsParams> {
+        if free_threshold >= 0.0 && free_threshold < occupied_threshold && occupied_threshold <= 1.0 {
+            Some(LogOddsParams { occupied_threshold, free_threshold, ..self })
+        } else {
+            None
+        }
+    }
+
+    /// the parameters with another range [m] beyond which beams are not integrated into the
+    /// map, None unless it is positive
+    pub fn with_max_range(self, max_range: Scalar) -> Option<LogOddsParams> {
+        if max_range > 0.0 {
+            Some(LogOddsParams { max_range, ..self })
+        } else {
+            None
+        }
+    }
+}
+
+/// Inclusive range of cell indices that have been observed so far
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MapBounds {
+    pub min_x: i64,
+    pub min_y: i64,
+    pub max_x: i64,
+    pub max_y: i64,
+}
+
+impl MapBounds {
+    pub fn new(x: i64, y: i64) -> MapBounds {
+        MapBounds { min_x: x, min_y: y, max_x: x, max_y: y }
+    }
+
+    /// number of cells in x direction
+    pub fn width(&self) -> usize {
+        (self.max_x - self.min_x + 1) as usize
+    }
+
+    /// number of cells in y direction
+    pub fn height(&self) -> usize {
+        (self.max_y - self.min_y + 1) as usize
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.min_x && x <= self.max_x && y >= self.min_y && y <= self.max_y
+    }
+
+    /// grow the bounds to include the cell (x, y)
+    pub fn extend(&mut self, x: i64, y: i64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+}
+
+/// A square chunk of TILE_SIZE x TILE_SIZE cells, allocated the first time a beam reaches it
+#[derive(Clone)]
+struct Tile {
+    cells: Vec<Scalar>, // log-odds of each cell being occupied, 0.0 is unknown
+}
+
+impl Tile {
+    fn new() -> Tile {
+        Tile { cells: vec![0.0; (TILE_SIZE * TILE_SIZE) as usize] }
+    }
+}
+
+/// Occupancy grid map without fixed extent. Cell (0,0) covers the world coordinates
+/// [0, cell_size) x [0, cell_size), and cell indices may be negative.
+///
+/// Tiles are reference counted, so cloning a map (e.g. when resampling particles) only
+/// copies pointers. A tile is copied the first time a shared map writes to it.
 #[derive(Clone)]
 pub struct GridMap {
-    pub map_size: usize, // assuming quadratic map, with (0,0) in the middle
-    pub cells: Vec<Vec<CellState>>,
+    tiles: HashMap<(i64, i64), Arc<Tile>>,
+    bounds: Option<MapBounds>,
     cell_size: Scalar, // in meter
+    params: LogOddsParams,
+    distance_field: DistanceFieldCache, // shared by clones until one of them is written to
 }
 
+type DistanceFieldCache = Arc<Mutex<Option<Arc<DistanceField>>>>;
+
 /// decide how a Point should be displayed when formatting and printing
 impl fmt::Display for GridMap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "grid map size: x_dir: {}, y_dir: {}", self.map_size, self.map_size)
+        match self.bounds {
+            None => write!(f, "grid map size: empty"),
+            Some(b) => write!(f, "grid map size: x_dir: {}, y_dir: {}", b.width(), b.height())
+        }
     }
 }
 
 impl fmt::Debug for GridMap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "grid map size: x_dir: {}, y_dir: {}", self.map_size, self.map_size)
+        write!(f, "{}, tiles: {}", self, self.tiles.len())
     }
 }
 
 impl Default for GridMap {
     fn default() -> GridMap {
-        const SIZE: usize = 111;
         const CELL_SIZE: Scalar = 0.25;
-        GridMap {
-            map_size: SIZE,
-            cells: vec![vec![CellState::Void; SIZE]; SIZE],
-            cell_size: CELL_SIZE,
-        }
+        GridMap::new(CELL_SIZE)
     }
 }
 
 impl GridMap {
-    pub fn new(map_size: usize, cell_size: Scalar) -> GridMap {
+    pub fn new(cell_size: Scalar) -> GridMap {
+        GridMap::with_params(cell_size, LogOddsParams::default())
+    }
+
+    pub fn with_params(cell_size: Scalar, params: LogOddsParams) -> GridMap {
         GridMap {
-            map_size,
-            cells: vec![vec![CellState::Void; map_size]; map_size],
+            tiles: HashMap::new(),
+            bounds: None,
             cell_size,
+            params,
+            distance_field: DistanceFieldCache::default(),
         }
     }
 
     pub fn clear(&mut self) {
-        self.cells = vec![vec![CellState::Void; self.map_size]; self.map_size]
+        self.tiles.clear();
+        self.bounds = None;
+        self.invalidate_distance_field();
+    }
+
+    pub fn params(&self) -> &LogOddsParams {
+        &self.params
+    }
+
+    pub fn cell_size(&self) -> Scalar {
+        self.cell_size
+    }
+
+    /// number of allocated tiles
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// number of tiles whose storage is shared with another map
+    pub fn shared_tile_count(&self, other: &GridMap) -> usize {
+        self.tiles
+            .iter()
+            .filter(|(index, tile)| match other.tiles.get(index) {
+                Some(other_tile) => Arc::ptr_eq(tile, other_tile),
+                None => false
+            })
+            .count()
+    }
+
+    /// the cells observed so far, None if nothing has been observed yet
+    pub fn bounds(&self) -> Option<MapBounds> {
+        self.bounds
+    }
+
+    /// the explored area in world coordinates as (lower left corner, upper right corner)
+    pub fn world_bounds(&self) -> Option<(Point, Point)> {
+        self.bounds.map(|b| {
+            let lower = Point::new(b.min_x as Scalar, b.min_y as Scalar) * self.cell_size;
+            let upper = Point::new((b.max_x + 1) as Scalar, (b.max_y + 1) as Scalar) * self.cell_size;
+            (lower, upper)
+        })
     }
 
     pub fn get_all_occupied_cells(&self) -> Vec<Point> {
-        let mut occupied: Vec<Point> = vec![];
-        for (x, rows) in self.cells.iter().enumerate() {
-            for (y, _) in rows.iter().enumerate() {
-                match self.cell_state(x, y) {
-                    None => {}
-                    Some(cellstate) => {
-                        if (cellstate != &CellState::Freespace) && (cellstate != &CellState::Void) {
-                            occupied.push(Point::new(x as f64, y as f64));
-                        }
-                    }
+        self.get_all_cells_in_state(CellState::Occupied)
+    }
+
+    pub fn get_all_free_cells(&self) -> Vec<Point> {
+        self.get_all_cells_in_state(CellState::Freespace)
+    }
+
+    /// map coordinates of the cells in the state
+    fn get_all_cells_in_state(&self, state: CellState) -> Vec<Point> {
+        let mut cells: Vec<Point> = vec![];
+        for (&(tx, ty), tile) in self.tiles.iter() {
+            for (i, &l) in tile.cells.iter().enumerate() {
+                if self.state_from_log_odds(l) == state {
+                    let x = tx * TILE_SIZE + i as i64 / TILE_SIZE;
+                    let y = ty * TILE_SIZE + i as i64 % TILE_SIZE;
+                    cells.push(Point::new(x as f64, y as f64));
                 }
             }
         }
-        return occupied
+        cells
     }
 
+    /// integrate a scan taken from pose into the map using the inverse sensor model:
+    /// the cell each beam ends in is a hit, every other cell along the beam is a miss.
+    /// Beams longer than max_range of the parameters are skipped, an absurd reading would
+    /// walk through billions of cells
     pub fn update(&mut self, pose: &Pose, scan: &Scan) {
-        use self::CellState::*;
-        for &m in scan.iter() {
-            let p = m.to_point(pose);
+        let start = match self.world_to_map(pose.position) {
+            None => return,
+            Some(start) => start
+        };
+        self.invalidate_distance_field();
 
-            // register occupied space
-            match self.world_to_map(p) {
-                Some((x,y)) => {
-                    let cell: &mut CellState = &mut self.cells[x][y];
-                    *cell = match *cell {
-                        Occupied(count) => Occupied(count + 1),
-                        Freespace => Occupied(1),
-                        Void => Occupied(1),
-                    };
-                }
-                _ => {}
-            }
+        let max_range = self.params.max_range;
+        for m in scan.iter().filter(|m| m.distance <= max_range) {
+            let end = match self.world_to_map(m.to_point(pose)) {
+                None => continue,
+                Some(end) => end
+            };
 
             // register freespace
-            match self.world_to_map(pose.position) {
-                None => panic!("could not convert point!"),
-                Some(start) => {
-                    match self.world_to_map(pose.position + Vector::from_angle(pose.heading + m.angle) * m.distance) {
-                        None => panic!("could not convert point!"),
-                        Some(end) => {
-                            let freespace = Bresenham::new(
-                                (start.0 as i64, start.1 as i64),
-                                (end.0 as i64, end.1 as i64))
-                                .map(|(x, y)| (x as usize, y as usize))
-                                .collect::<Vec<_>>();
-
-                            for (x,y) in freespace {
-                                let cell: &mut CellState = &mut self.cells[x][y];
-                                *cell = match *cell {
-                                    Void => Freespace,
-                                    o => o,
-                                };
-                            }
-                        }
-                    }
-                }
+            let freespace = Bresenham::new(start, end)
+                .filter(|&cell| cell != end)
+                .collect::<Vec<_>>();
+
+            for (x, y) in freespace {
+                self.update_cell(x, y, self.params.miss);
             }
+
+            // register occupied space
+            self.update_cell(end.0, end.1, self.params.hit);
         }
     }
 
-    fn index_from_dist(&self, dist: Scalar) -> Option<usize> {
-        let map_offset = (self.map_size as Scalar) / 2.0;
-        let c = (dist / self.cell_size + map_offset) as i32;
+    fn update_cell(&mut self, x: i64, y: i64, delta: Scalar) {
+        let l = self.log_odds(x, y) + delta;
+        self.write_cell(x, y, l);
+    }
 
-        // if cell is outside grid, return None
-        if c < 0 || c > self.map_size as i32 {
-            println!("index c: {} is outside of map", c);
-            None
+    /// overwrite the log-odds of a cell, clamped to the bounds of the parameters
+    pub fn set_log_odds(&mut self, x: i64, y: i64, l: Scalar) {
+        self.invalidate_distance_field();
+        self.write_cell(x, y, l);
+    }
+
+    fn write_cell(&mut self, x: i64, y: i64, l: Scalar) {
+        let (min, max) = (self.params.min, self.params.max);
+        let (tile_index, cell_index) = Self::split_index(x, y);
+        let tile = self.tiles.entry(tile_index).or_insert_with(|| Arc::new(Tile::new()));
+        Arc::make_mut(tile).cells[cell_index] = l.max(min).min(max);
+
+        match self.bounds.as_mut() {
+            None => self.bounds = Some(MapBounds::new(x, y)),
+            Some(bounds) => bounds.extend(x, y)
+        }
+    }
+
+    /// distance transform of the current map saturated at DEFAULT_MAX_DISTANCE, computed on
+    /// demand and cached until the map changes
+    pub fn distance_field(&self) -> Arc<DistanceField> {
+        self.distance_field_up_to(DEFAULT_MAX_DISTANCE)
+    }
+
+    /// distance transform of the current map saturated at max_distance [m]. The field of
+    /// the last requested max_distance is cached until the map changes
+    pub fn distance_field_up_to(&self, max_distance: Scalar) -> Arc<DistanceField> {
+        let mut cache = self.distance_field.lock().unwrap();
+        match cache.as_ref() {
+            Some(field) if field.max_distance() == max_distance => field.clone(),
+            _ => {
+                let field = Arc::new(DistanceField::new(self, max_distance));
+                *cache = Some(field.clone());
+                field
+            }
+        }
+    }
+
+    /// detach from the cache shared with clones of this map, they might still be unchanged
+    fn invalidate_distance_field(&mut self) {
+        match Arc::get_mut(&mut self.distance_field) {
+            Some(cache) => *cache.get_mut().unwrap() = None,
+            None => self.distance_field = DistanceFieldCache::default()
+        }
+    }
+
+    /// split a cell index into the index of its tile and its offset within that tile
+    fn split_index(x: i64, y: i64) -> ((i64, i64), usize) {
+        let tile_index = (x.div_euclid(TILE_SIZE), y.div_euclid(TILE_SIZE));
+        let cell_index = x.rem_euclid(TILE_SIZE) * TILE_SIZE + y.rem_euclid(TILE_SIZE);
+        (tile_index, cell_index as usize)
+    }
+
+    fn index_from_dist(&self, dist: Scalar) -> Option<i64> {
+        let c = (dist / self.cell_size).floor();
+
+        // only non-finite coordinates can not be represented in the map
+        if c.is_finite() {
+            Some(c as i64)
         } else {
-            Some(c as usize)
+            None
         }
     }
+
     /// convert from continous world coordinates to map coordinates
-    pub fn world_to_map(&self, point: Point) -> Option<(usize, usize)> {
+    pub fn world_to_map(&self, point: Point) -> Option<(i64, i64)> {
         self.index_from_dist(point.x)
             .and_then(|x| self.index_from_dist(point.y).map(|y| (x, y)))
     }
 
-    pub fn cell_state(&self, x: usize, y: usize) -> Option<&CellState> {
-        self.cells.get(x).and_then(|north| north.get(y))
+    /// convert from map coordinates to the world coordinates of the cell centre
+    pub fn map_to_world(&self, x: i64, y: i64) -> Point {
+        Point::new(x as Scalar + 0.5, y as Scalar + 0.5) * self.cell_size
+    }
+
+    /// log-odds of the cell being occupied, 0.0 if the cell has never been observed
+    pub fn log_odds(&self, x: i64, y: i64) -> Scalar {
+        let (tile_index, cell_index) = Self::split_index(x, y);
+        self.tiles
+            .get(&tile_index)
+            .map(|tile| tile.cells[cell_index])
+            .unwrap_or(0.0)
+    }
+
+    /// probability of the cell being occupied, 0.5 if the cell has never been observed
+    pub fn occupancy(&self, x: i64, y: i64) -> Scalar {
+        sigmoid(self.log_odds(x, y))
+    }
+
+    /// thresholded view of the occupancy probability of the cell
+    pub fn cell_state(&self, x: i64, y: i64) -> CellState {
+        self.state_from_log_odds(self.log_odds(x, y))
+    }
+
+    fn state_from_log_odds(&self, l: Scalar) -> CellState {
+        let p = sigmoid(l);
+        if p >= self.params.occupied_threshold {
+            CellState::Occupied
+        } else if p <= self.params.free_threshold {
+            CellState::Freespace
+        } else {
+            CellState::Void
+        }
     }
 }
@@ -1,3 +1,5 @@
 pub mod load_map;
 pub mod grid_map;
 pub mod display_map;
+pub mod ros_map;
+pub mod distance_field;
@@ -0,0 +1,254 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::gridmap::grid_map::{CellState, GridMap};
+use crate::math::scalar::Scalar;
+
+/// grey levels written by the ROS map_saver for trinary maps
+const OCCUPIED_GREY: u8 = 0;
+const FREE_GREY: u8 = 254;
+const UNKNOWN_GREY: u8 = 205;
+
+/// The YAML metadata that accompanies a map_server image
+///
+/// More info:
+///  - http://wiki.ros.org/map_server#YAML_format
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapMetadata {
+    pub image: PathBuf,
+    pub resolution: Scalar, // [m/pixel]
+    pub origin: [Scalar; 3], // pose of the lower-left pixel (x, y, yaw)
+    pub negate: bool,
+    pub occupied_thresh: Scalar,
+    pub free_thresh: Scalar,
+}
+
+impl MapMetadata {
+    pub fn to_yaml(&self) -> String {
+        format!(
+            "image: {}\nresolution: {}\norigin: [{}, {}, {}]\nnegate: {}\noccupied_thresh: {}\nfree_thresh: {}\n",
+            self.image.display(),
+            self.resolution,
+            self.origin[0], self.origin[1], self.origin[2],
+            if self.negate { 1 } else { 0 },
+            self.occupied_thresh,
+            self.free_thresh
+        )
+    }
+
+    pub fn from_yaml(yaml: &str) -> io::Result<MapMetadata> {
+        let mut image = None;
+        let mut resolution = None;
+        let mut origin = None;
+        let mut negate = false;
+        let mut occupied_thresh = 0.65;
+        let mut free_thresh = 0.196;
+
+        for line in yaml.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.find(':') {
+                None => return Err(invalid_data(format!("malformed line in map yaml: {}", line))),
+                Some(i) => (line[..i].trim(), line[i + 1..].trim())
+            };
+            let value = value.trim_matches(|c| c == '"' || c == '\'');
+
+            match key {
+                "image" => image = Some(PathBuf::from(value)),
+                "resolution" => resolution = Some(parse_scalar(value)?),
+                "origin" => {
+                    let values = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|v| parse_scalar(v.trim()))
+                        .collect::<io::Result<Vec<Scalar>>>()?;
+                    if values.len() != 3 {
+                        return Err(invalid_data(format!("origin must have 3 elements, got {}", values.len())));
+                    }
+                    origin = Some([values[0], values[1], values[2]]);
+                }
+                "negate" => negate = parse_scalar(value)? != 0.0,
+                "occupied_thresh" => occupied_thresh = parse_scalar(value)?,
+                "free_thresh" => free_thresh = parse_scalar(value)?,
+                "mode" if value != "trinary" => {
+                    return Err(invalid_data(format!("unsupported map mode: {}", value)))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(MapMetadata {
+            image: image.ok_or_else(|| invalid_data("map yaml has no image".to_string()))?,
+            resolution: resolution.ok_or_else(|| invalid_data("map yaml has no resolution".to_string()))?,
+            origin: origin.ok_or_else(|| invalid_data("map yaml has no origin".to_string()))?,
+            negate,
+            occupied_thresh,
+            free_thresh,
+        })
+    }
+}
+
+/// Write the explored area of the map in the ROS map_server format: a PGM image next to
+/// a YAML file with the same stem. Occupied cells are black, free cells are white and
+/// unknown cells are grey.
+pub fn save_ros_map(gridmap: &GridMap, yaml_path: &Path) -> io::Result<()> {
+    let bounds = match gridmap.bounds() {
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot export an empty map")),
+        Some(bounds) => bounds
+    };
+
+    let (width, height) = (bounds.width(), bounds.height());
+    let mut pgm = format!("P5\n# fastslam grid map\n{} {}\n255\n", width, height).into_bytes();
+
+    // the first image row is the top of the map
+    for y in (bounds.min_y..=bounds.max_y).rev() {
+        for x in bounds.min_x..=bounds.max_x {
+            pgm.push(match gridmap.cell_state(x, y) {
+                CellState::Occupied => OCCUPIED_GREY,
+                CellState::Freespace => FREE_GREY,
+                CellState::Void => UNKNOWN_GREY,
+            });
+        }
+    }
+
+    let image_path = yaml_path.with_extension("pgm");
+    let image_name = image_path
+        .file_name()
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "yaml path has no file name"))?;
+
+    let (lower, _) = gridmap.world_bounds().unwrap();
+    let metadata = MapMetadata {
+        image: image_name,
+        resolution: gridmap.cell_size(),
+        origin: [lower.x, lower.y, 0.0],
+        negate: false,
+        occupied_thresh: 0.65,
+        free_thresh: 0.196,
+    };
+
+    fs::write(&image_path, pgm)?;
+    fs::write(yaml_path, metadata.to_yaml())
+}
+
+/// Read a map in the ROS map_server format. Pixels are classified with the thresholds
+/// from the YAML file and occupied/free cells are set to the clamping bounds of the
+/// log-odds, unknown cells are left unobserved. The image is a PGM or any other format
+/// the image crate reads, e.g. PNG, converted to grey levels.
+pub fn load_ros_map(yaml_path: &Path) -> io::Result<GridMap> {
+    let metadata = MapMetadata::from_yaml(&fs::read_to_string(yaml_path)?)?;
+    if metadata.origin[2] != 0.0 {
+        return Err(invalid_data("rotated map origins are not supported".to_string()));
+    }
+
+    // the image path is relative to the yaml file
+    let image_path = match yaml_path.parent() {
+        Some(dir) if metadata.image.is_relative() => dir.join(&metadata.image),
+        _ => metadata.image.clone()
+    };
+    let is_pgm = matches!(image_path.extension(), Some(ext) if ext.eq_ignore_ascii_case("pgm"));
+    let (width, height, max_value, pixels) = if is_pgm {
+        read_pgm(&fs::read(image_path)?)?
+    } else {
+        read_image(&image_path)?
+    };
+
+    let mut gridmap = GridMap::new(metadata.resolution);
+    let (occupied, free) = (gridmap.params().max, gridmap.params().min);
+    let min_x = (metadata.origin[0] / metadata.resolution).round() as i64;
+    let min_y = (metadata.origin[1] / metadata.resolution).round() as i64;
+
+    for (i, &value) in pixels.iter().enumerate() {
+        let (row, col) = (i / width, i % width);
+        let x = min_x + col as i64;
+        let y = min_y + (height - 1 - row) as i64;
+
+        let value = value as Scalar / max_value as Scalar;
+        let p = if metadata.negate { value } else { 1.0 - value };
+
+        if p > metadata.occupied_thresh {
+            gridmap.set_log_odds(x, y, occupied);
+        } else if p < metadata.free_thresh {
+            gridmap.set_log_odds(x, y, free);
+        }
+    }
+
+    Ok(gridmap)
+}
+
+/// parse a binary (P5) or plain (P2) PGM image into (width, height, max value, pixels)
+fn read_pgm(data: &[u8]) -> io::Result<(usize, usize, u32, Vec<u32>)> {
+    let mut pos = 0;
+    let mut next_token = || -> io::Result<String> {
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid_data("unexpected end of pgm image".to_string()));
+        }
+        Ok(String::from_utf8_lossy(&data[start..pos]).to_string())
+    };
+
+    let magic = next_token()?;
+    let parse = |token: String| token
+        .parse::<u32>()
+        .map_err(|_| invalid_data(format!("invalid number in pgm header: {}", token)));
+    let width = parse(next_token()?)? as usize;
+    let height = parse(next_token()?)? as usize;
+    let max_value = parse(next_token()?)?;
+
+    if max_value == 0 || max_value > 255 {
+        return Err(invalid_data(format!("unsupported pgm max value: {}", max_value)));
+    }
+
+    let pixels = match magic.as_str() {
+        "P5" => {
+            // a single whitespace character separates the header from the raster
+            let raster = data.get(pos + 1..pos + 1 + width * height)
+                .ok_or_else(|| invalid_data("pgm raster is too short".to_string()))?;
+            raster.iter().map(|&v| v as u32).collect()
+        }
+        "P2" => (0..width * height)
+            .map(|_| next_token().and_then(parse))
+            .collect::<io::Result<Vec<u32>>>()?,
+        _ => return Err(invalid_data(format!("unsupported pgm format: {}", magic)))
+    };
+
+    Ok((width, height, max_value, pixels))
+}
+
+/// read an image file (e.g. PNG) as grey levels into (width, height, max value, pixels)
+fn read_image(path: &Path) -> io::Result<(usize, usize, u32, Vec<u32>)> {
+    let image = image::open(path)
+        .map_err(|e| invalid_data(format!("could not read map image {}: {}", path.display(), e)))?
+        .to_luma8();
+    let pixels = image.pixels().map(|p| p.0[0] as u32).collect();
+    Ok((image.width() as usize, image.height() as usize, 255, pixels))
+}
+
+fn parse_scalar(value: &str) -> io::Result<Scalar> {
+    value
+        .parse::<Scalar>()
+        .map_err(|_| invalid_data(format!("invalid number in map yaml: {}", value)))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
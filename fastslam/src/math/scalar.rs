@@ -7,4 +7,14 @@ pub type Scalar = f64;
 pub const PI: Scalar = consts::PI;
 
 /// Type synonym for angles.
-pub type Angle = Scalar;
\ No newline at end of file
+pub type Angle = Scalar;
+
+/// wrap an angle to the interval (-PI, PI]
+pub fn wrap_angle(angle: Angle) -> Angle {
+    let wrapped = angle.rem_euclid(2.0 * PI);
+    if wrapped > PI {
+        wrapped - 2.0 * PI
+    } else {
+        wrapped
+    }
+}
\ No newline at end of file
@@ -28,4 +28,35 @@ pub fn compute_abs_diff(actual: Scalar, compared: Scalar) -> f64 {
 
 pub fn sigmoid(x: Scalar) -> Scalar {
     x.exp() / (1.0 + x.exp())
-}
\ No newline at end of file
+}
+
+/// inverse of the sigmoid, converts a probability to log-odds
+pub fn log_odds(p: Scalar) -> Scalar {
+    (p / (1.0 - p)).ln()
+}
+
+/// ln(sum(exp(values))) without under- or overflow, e.g. to add up probabilities kept in
+/// log space. Negative infinity for no values or if all values are negative infinity
+pub fn log_sum_exp(values: &[Scalar]) -> Scalar {
+    let max = values.iter().cloned().fold(Scalar::NEG_INFINITY, Scalar::max);
+    if !max.is_finite() {
+        return max
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<Scalar>().ln()
+}
+
+/// Quantile of the standard normal distribution, the z with P(Z <= z) = p for p in (0, 1).
+/// Rational approximation with an absolute error below 4.5e-4
+///
+/// More info:
+///  - 26.2.23 in Handbook of Mathematical Functions, M. Abramowitz and I. Stegun
+pub fn normal_quantile(p: Scalar) -> Scalar {
+    assert!(p > 0.0 && p < 1.0, "quantile of p = {} is not defined", p);
+
+    let tail = |q: Scalar| {
+        let t = (-2.0 * q.ln()).sqrt();
+        t - (2.515517 + 0.802853 * t + 0.010328 * t * t) / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+    };
+
+    if p < 0.5 { -tail(p) } else { tail(1.0 - p) }
+}
@@ -0,0 +1,112 @@
+use nalgebra as na;
+use crate::geometry::{Point, Vector};
+use crate::math::scalar::{wrap_angle, PI};
+use crate::odometry::{Odometry, Pose, PoseWithCovariance, Twist};
+use crate::sensor::encoder::EncoderTicks;
+
+/// Kinematics of a differential drive robot: two wheels on a common axle, the robot moves
+/// by the mean of the distances the wheels travel and turns by their difference.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DifferentialDrive {
+    pub wheel_radius: f64, // [m]
+    pub track_width: f64, // [m] distance between the contact points of the wheels
+    pub ticks_per_revolution: u32, // encoder resolution
+    pub wheel_noise: f64, // [m] variance of the distance a wheel travels per meter travelled (slip, uneven floor)
+}
+
+impl Default for DifferentialDrive {
+    fn default() -> DifferentialDrive {
+        DifferentialDrive {
+            wheel_radius: 0.05,
+            track_width: 0.3,
+            ticks_per_revolution: 1024,
+            wheel_noise: 0.001,
+        }
+    }
+}
+
+impl DifferentialDrive {
+    pub fn new(wheel_radius: f64, track_width: f64, ticks_per_revolution: u32) -> DifferentialDrive {
+        DifferentialDrive { wheel_radius, track_width, ticks_per_revolution, ..DifferentialDrive::default() }
+    }
+
+    /// [m] distance a wheel travels per encoder tick
+    pub fn distance_per_tick(&self) -> f64 {
+        2.0 * PI * self.wheel_radius / self.ticks_per_revolution as f64
+    }
+
+    /// [m] distances travelled by the (left, right) wheel for the ticks counted
+    pub fn wheel_distances(&self, ticks: &EncoderTicks) -> (f64, f64) {
+        let distance_per_tick = self.distance_per_tick();
+        (ticks.left as f64 * distance_per_tick, ticks.right as f64 * distance_per_tick)
+    }
+
+    /// [m, rad] distance driven by the centre of the axle and rotation of the robot when
+    /// the wheels travel the (left, right) distances
+    pub fn motion(&self, left: f64, right: f64) -> (f64, f64) {
+        (0.5 * (right + left), (right - left) / self.track_width)
+    }
+
+    /// [m] distances the (left, right) wheel travel to drive a distance and turn a rotation,
+    /// the inverse of motion
+    pub fn wheel_motion(&self, distance: f64, rotation: f64) -> (f64, f64) {
+        let half_difference = 0.5 * rotation * self.track_width;
+        (distance - half_difference, distance + half_difference)
+    }
+
+    /// Odometry after the wheels turned from the prev to the curr ticks in dt seconds. The
+    /// robot moves along the heading halfway through the rotation, and the velocity is the
+    /// mean velocity over dt (zero if dt is not positive).
+    ///
+    /// More info:
+    ///  - Section 5.2.4 in Introduction to Autonomous Mobile Robots, R. Siegwart et al.
+    pub fn integrate(&self, odom: &Odometry, prev: &EncoderTicks, curr: &EncoderTicks, dt: f64) -> Odometry {
+        let (left, right) = self.wheel_distances(&curr.since(prev));
+        let (distance, rotation) = self.motion(left, right);
+        let vel = if dt > 0.0 {
+            Twist::new(Vector::new(distance / dt, 0.0), rotation / dt)
+        } else {
+            Twist::default()
+        };
+
+        Odometry::new(self.step(&odom.pose, distance, rotation), vel)
+    }
+
+    /// integrate for a pose estimate: the pose and its covariance, the covariance of the
+    /// previous pose propagated through the jacobian of the motion plus the noise of each
+    /// wheel, which grows with the distance the wheel travelled
+    ///
+    /// More info:
+    ///  - Section 5.2.4 in Introduction to Autonomous Mobile Robots, R. Siegwart et al.
+    #[allow(non_snake_case)]
+    pub fn integrate_with_covariance(&self, estimate: &PoseWithCovariance, prev: &EncoderTicks, curr: &EncoderTicks) -> PoseWithCovariance {
+        let (left, right) = self.wheel_distances(&curr.since(prev));
+        let (distance, rotation) = self.motion(left, right);
+        let b = self.track_width;
+        let heading = estimate.pose.heading + 0.5 * rotation;
+        let (s, c) = (heading.sin(), heading.cos());
+
+        // jacobians with respect to the previous pose (F_p) and to the distances travelled
+        // by the left and the right wheel (F_lr)
+        let F_p = na::Matrix3::new(1.0, 0.0, -distance * s, 0.0, 1.0, distance * c, 0.0, 0.0, 1.0);
+        let F_lr = na::Matrix3x2::new(
+            0.5 * c + distance / (2.0 * b) * s, 0.5 * c - distance / (2.0 * b) * s,
+            0.5 * s - distance / (2.0 * b) * c, 0.5 * s + distance / (2.0 * b) * c,
+            -1.0 / b, 1.0 / b
+        );
+        let Sigma = na::Matrix2::new(self.wheel_noise * left.abs(), 0.0, 0.0, self.wheel_noise * right.abs());
+
+        PoseWithCovariance::new(
+            self.step(&estimate.pose, distance, rotation),
+            F_p * estimate.covariance * F_p.transpose() + F_lr * Sigma * F_lr.transpose()
+        )
+    }
+
+    fn step(&self, pose: &Pose, distance: f64, rotation: f64) -> Pose {
+        let heading = pose.heading + 0.5 * rotation;
+        Pose::new(
+            Point::new(pose.position.x + distance * heading.cos(), pose.position.y + distance * heading.sin()),
+            wrap_angle(pose.heading + rotation)
+        )
+    }
+}
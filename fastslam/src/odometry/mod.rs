@@ -2,9 +2,13 @@ pub mod odometry;
 pub mod pose;
 pub mod twist;
 pub mod motion_model;
+pub mod pose_with_covariance;
+pub mod differential_drive;
 
 // Re-export all base types
 pub use self::odometry::Odometry;
 pub use self::pose::Pose;
 pub use self::twist::Twist;
-pub use self::motion_model::MotionModel;
\ No newline at end of file
+pub use self::motion_model::MotionModel;
+pub use self::pose_with_covariance::{Covariance, PoseWithCovariance};
+pub use self::differential_drive::DifferentialDrive;
\ No newline at end of file
@@ -1,28 +1,94 @@
-use crate::odometry::{Pose, Twist};
+use nalgebra as na;
+use crate::odometry::{Covariance, Pose, PoseWithCovariance, Twist};
 use crate::geometry::Point;
 use crate::sensor::noise::gaussian;
-use crate::math::scalar::PI;
+use crate::math::scalar::{wrap_angle, PI};
 
-pub trait MotionModel {
-    fn wrap_heading(yaw: f64) -> f64 {
-        let mut angle = yaw;
+/// motion noise params of the velocity motion model
+/// alpha_1:2: translational error
+/// alpha_3:4: angular error
+/// alpha_5:6: error of the final rotation
+pub const DEFAULT_ALPHA: [f64; 6] = [0.01, 0.01, 0.01, 0.01, 0.01, 0.01]; // these values can be tuned
 
-        while angle < -PI {
-            angle += 2.0 * PI
-        }
-        while angle > PI {
-            angle -= 2.0 * PI
-        }
+/// motion noise params of the odometry motion model
+/// alpha_1: rotational error from rotation
+/// alpha_2: rotational error from translation
+/// alpha_3: translational error from translation
+/// alpha_4: translational error from rotation
+pub const DEFAULT_ODOMETRY_ALPHA: [f64; 4] = [0.01, 0.01, 0.01, 0.01];
+
+/// motion noise params of the gyro aided motion model
+/// alpha_1: translational error from translation (wheels)
+/// alpha_2: translational error from rotation (wheels)
+/// alpha_3: [rad^2/s] variance of the rotation per second of integrating the yaw rate (gyro)
+/// alpha_4: rotational error from rotation, e.g. a wrong scale factor (gyro)
+pub const DEFAULT_GYRO_ALPHA: [f64; 4] = [0.01, 0.01, 0.0001, 0.001];
+
+/// below this angular velocity [rad/s] the robot is considered to drive in a straight line
+const MIN_ANGULAR_VELOCITY: f64 = 1e-9;
+
+/// below this translation [m] between two odometry readings the robot turned on the spot
+const MIN_TRANSLATION: f64 = 1e-9;
+
+/// The motion between two odometry readings as a rotation towards the new position, a
+/// translation and a rotation to the new heading (rot1, trans, rot2). Driving backwards is
+/// a negative translation and not a half turn, so reversing does not look like a large
+/// rotation to the noise model.
+///
+/// More info:
+///  - p.134 Table 5.5 in probabilistic robotics, Sebastian Thrun et al.
+pub fn odometry_delta(prev_odom: &Pose, curr_odom: &Pose) -> (f64, f64, f64) {
+    let dx = curr_odom.position.x - prev_odom.position.x;
+    let dy = curr_odom.position.y - prev_odom.position.y;
+    let rotation = wrap_angle(curr_odom.heading - prev_odom.heading);
+
+    let mut trans = dx.hypot(dy);
+    if trans < MIN_TRANSLATION {
+        return (0.0, 0.0, rotation)
+    }
+
+    let mut rot1 = wrap_angle(dy.atan2(dx) - prev_odom.heading);
+    if rot1.abs() > 0.5 * PI {
+        rot1 = wrap_angle(rot1 + PI);
+        trans = -trans;
+    }
+    (rot1, trans, wrap_angle(rotation - rot1))
+}
+
+/// The pose after moving trans [m] along the heading halfway through the rotation rot [rad],
+/// without noise
+fn drive_arc(pose: &Pose, trans: f64, rot: f64) -> Pose {
+    let heading = pose.heading + 0.5 * rot;
+    Pose::new(
+        Point::new(pose.position.x + trans * heading.cos(), pose.position.y + trans * heading.sin()),
+        wrap_angle(pose.heading + rot)
+    )
+}
+
+/// drive_arc with the noise params alpha_1:4 of the gyro aided motion model, for a rotation
+/// integrated over dt
+fn sample_arc_with_noise(pose: &Pose, trans: f64, rot: f64, dt: f64, alpha: &[f64; 4]) -> Pose {
+    let rot_hat = gaussian(rot, (alpha[2] * dt.abs() + alpha[3] * rot.powi(2)).sqrt());
+    let std_dev_position = (alpha[0] * trans.powi(2) + alpha[1] * rot.powi(2)).sqrt();
 
-        angle
+    let pose = drive_arc(pose, trans, rot_hat);
+    Pose::new(
+        Point::new(gaussian(pose.position.x, std_dev_position), gaussian(pose.position.y, std_dev_position)),
+        pose.heading
+    )
+}
+
+pub trait MotionModel {
+    fn wrap_heading(yaw: f64) -> f64 {
+        wrap_angle(yaw)
     }
 
     fn sample_motion_model_velocity(pose: &Pose, gain: &Twist, dt: f64) -> Pose {
-        // motion noise params
-        // alpha_1:2: translational error
-        // alpha_3:4: angular error
-        let alpha = [0.01, 0.01, 0.01, 0.01, 0.01, 0.01]; // these values can be tuned
+        Self::sample_motion_model_velocity_with_noise(pose, gain, dt, &DEFAULT_ALPHA)
+    }
 
+    /// sample_motion_model_velocity with the motion noise params alpha_1:6
+    fn sample_motion_model_velocity_with_noise(pose: &Pose, gain: &Twist, dt: f64, alpha: &[f64; 6]) -> Pose {
         let x = pose.position.x;
         let y = pose.position.y;
         let theta = pose.heading;
@@ -52,6 +118,72 @@ pub trait MotionModel {
         pose
     }
 
+    /// The pose after the motion between two odometry readings, applied to pose without
+    /// noise
+    fn drive_odometry(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose) -> Pose {
+        pose.compose(&curr_odom.relative_to(prev_odom))
+    }
+
+    fn sample_motion_model_odometry(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose) -> Pose {
+        Self::sample_motion_model_odometry_with_noise(pose, prev_odom, curr_odom, &DEFAULT_ODOMETRY_ALPHA)
+    }
+
+    /// Samples the pose after the motion between two odometry readings, with noise on both
+    /// rotations and the translation in between.
+    ///
+    /// More info:
+    ///  - p.136 Table 5.6 in probabilistic robotics, Sebastian Thrun et al.
+    fn sample_motion_model_odometry_with_noise(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, alpha: &[f64; 4]) -> Pose {
+        let (rot1, trans, rot2) = odometry_delta(prev_odom, curr_odom);
+
+        let rot1_hat = gaussian(rot1, (alpha[0] * rot1.powi(2) + alpha[1] * trans.powi(2)).sqrt());
+        let trans_hat = gaussian(trans, (alpha[2] * trans.powi(2) + alpha[3] * (rot1.powi(2) + rot2.powi(2))).sqrt());
+        let rot2_hat = gaussian(rot2, (alpha[0] * rot2.powi(2) + alpha[1] * trans.powi(2)).sqrt());
+
+        let heading = pose.heading + rot1_hat;
+        Pose::new(
+            Point::new(pose.position.x + trans_hat * heading.cos(), pose.position.y + trans_hat * heading.sin()),
+            Self::wrap_heading(heading + rot2_hat)
+        )
+    }
+
+    /// The pose after driving the distance of the gain while turning at the yaw rate
+    /// measured by a gyro for dt, without noise. The rotation comes from the gyro only, the
+    /// robot moves along the heading halfway through it
+    fn drive_gyro(pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64) -> Pose {
+        drive_arc(pose, gain.velocity.x * dt, yaw_rate * dt)
+    }
+
+    fn sample_motion_model_gyro(pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64) -> Pose {
+        Self::sample_motion_model_gyro_with_noise(pose, gain, yaw_rate, dt, &DEFAULT_GYRO_ALPHA)
+    }
+
+    /// Samples the pose after drive_gyro with separate noise for the two sensors: the
+    /// rotation integrated from the gyro is perturbed by alpha_3:4, the position the wheels
+    /// moved the robot to by alpha_1:2 in every direction
+    fn sample_motion_model_gyro_with_noise(pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64, alpha: &[f64; 4]) -> Pose {
+        sample_arc_with_noise(pose, gain.velocity.x * dt, yaw_rate * dt, dt, alpha)
+    }
+
+    /// The pose after moving the distance between two odometry readings while turning at the
+    /// yaw rate measured by a gyro for dt, without noise. Like drive_gyro, but the distance
+    /// comes from the odometry (e.g. wheel encoders) instead of a gain
+    fn drive_odometry_gyro(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64) -> Pose {
+        let (_, trans, _) = odometry_delta(prev_odom, curr_odom);
+        drive_arc(pose, trans, yaw_rate * dt)
+    }
+
+    fn sample_motion_model_odometry_gyro(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64) -> Pose {
+        Self::sample_motion_model_odometry_gyro_with_noise(pose, prev_odom, curr_odom, yaw_rate, dt, &DEFAULT_GYRO_ALPHA)
+    }
+
+    /// Samples the pose after drive_odometry_gyro with the noise params alpha_1:4 of the gyro
+    /// aided motion model
+    fn sample_motion_model_odometry_gyro_with_noise(pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64, alpha: &[f64; 4]) -> Pose {
+        let (_, trans, _) = odometry_delta(prev_odom, curr_odom);
+        sample_arc_with_noise(pose, trans, yaw_rate * dt, dt, alpha)
+    }
+
     fn drive(pose: &Pose, gain: &Twist, dt: f64) -> Pose {
         let ds = gain.velocity.x * dt;
         let dyaw = gain.angular * dt;
@@ -72,4 +204,89 @@ pub trait MotionModel {
             heading
         }
     }
-}
\ No newline at end of file
+
+    /// EKF prediction with the velocity motion model: the mean pose after driving with the
+    /// noise free gain for dt and its covariance, the covariance of the previous pose
+    /// propagated through the jacobian of the motion plus the motion noise.
+    ///
+    /// More info:
+    ///  - p.204 in probabilistic robotics, Sebastian Thrun et al.
+    fn motion_model_velocity_with_covariance(estimate: &PoseWithCovariance, gain: &Twist, dt: f64) -> PoseWithCovariance {
+        Self::motion_model_velocity_with_covariance_with_noise(estimate, gain, dt, &DEFAULT_ALPHA)
+    }
+
+    /// motion_model_velocity_with_covariance with the motion noise params alpha_1:6
+    #[allow(non_snake_case)]
+    fn motion_model_velocity_with_covariance_with_noise(estimate: &PoseWithCovariance, gain: &Twist, dt: f64, alpha: &[f64; 6]) -> PoseWithCovariance {
+        let theta = estimate.pose.heading;
+        let v = gain.velocity.x;
+        let omega = gain.angular;
+        let (s, c) = (theta.sin(), theta.cos());
+        let (s_prime, c_prime) = ((theta + omega * dt).sin(), (theta + omega * dt).cos());
+
+        // motion (dx, dy) and its jacobians with respect to the heading (G) and to the
+        // gain (V)
+        let (dx, dy, G, V) = if omega.abs() < MIN_ANGULAR_VELOCITY {
+            let ds = v * dt;
+            (
+                ds * c,
+                ds * s,
+                na::Matrix3::new(1.0, 0.0, -ds * s, 0.0, 1.0, ds * c, 0.0, 0.0, 1.0),
+                na::Matrix3x2::new(dt * c, -0.5 * ds * dt * s, dt * s, 0.5 * ds * dt * c, 0.0, dt)
+            )
+        } else {
+            let r = v / omega;
+            (
+                -r * s + r * s_prime,
+                r * c - r * c_prime,
+                na::Matrix3::new(1.0, 0.0, -r * c + r * c_prime, 0.0, 1.0, -r * s + r * s_prime, 0.0, 0.0, 1.0),
+                na::Matrix3x2::new(
+                    (-s + s_prime) / omega, r * (s - s_prime) / omega + r * c_prime * dt,
+                    (c - c_prime) / omega, -r * (c - c_prime) / omega + r * s_prime * dt,
+                    0.0, dt
+                )
+            )
+        };
+
+        // noise of the gain and of the final rotation
+        let M = na::Matrix2::new(
+            alpha[0] * v.powi(2) + alpha[1] * omega.powi(2), 0.0,
+            0.0, alpha[2] * v.powi(2) + alpha[3] * omega.powi(2)
+        );
+        let mut R = V * M * V.transpose();
+        R[(2, 2)] += (alpha[4] * v.powi(2) + alpha[5] * omega.powi(2)) * dt.powi(2);
+
+        let pose = Pose::new(
+            Point::new(estimate.pose.position.x + dx, estimate.pose.position.y + dy),
+            Self::wrap_heading(theta + omega * dt)
+        );
+        PoseWithCovariance::new(pose, G * estimate.covariance * G.transpose() + R)
+    }
+
+    /// drive together with the propagated covariance, with the noise of the velocity
+    /// motion model on the distance and the rotation driven in dt
+    fn drive_with_covariance(estimate: &PoseWithCovariance, gain: &Twist, dt: f64) -> PoseWithCovariance {
+        Self::drive_with_covariance_with_noise(estimate, gain, dt, &DEFAULT_ALPHA)
+    }
+
+    /// drive_with_covariance with the motion noise params alpha_1:4 of the velocity motion
+    /// model, alpha_5:6 are not used
+    #[allow(non_snake_case)]
+    fn drive_with_covariance_with_noise(estimate: &PoseWithCovariance, gain: &Twist, dt: f64, alpha: &[f64; 6]) -> PoseWithCovariance {
+        let pose = Self::drive(&estimate.pose, gain, dt);
+        let ds = gain.velocity.x * dt;
+        let (v, omega) = (gain.velocity.x, gain.angular);
+        let (s, c) = (pose.heading.sin(), pose.heading.cos());
+
+        // drive turns first and then moves along the new heading
+        let G = na::Matrix3::new(1.0, 0.0, -ds * s, 0.0, 1.0, ds * c, 0.0, 0.0, 1.0);
+        let V = na::Matrix3x2::new(c, -ds * s, s, ds * c, 0.0, 1.0);
+        let M = na::Matrix2::new(
+            (alpha[0] * v.powi(2) + alpha[1] * omega.powi(2)) * dt.powi(2), 0.0,
+            0.0, (alpha[2] * v.powi(2) + alpha[3] * omega.powi(2)) * dt.powi(2)
+        );
+
+        let covariance: Covariance = G * estimate.covariance * G.transpose() + V * M * V.transpose();
+        PoseWithCovariance::new(pose, covariance)
+    }
+}
@@ -1,8 +1,9 @@
 use crate::geometry::point::Point;
-use crate::math::scalar::{Angle, Scalar};
+use crate::geometry::vector::Vector;
+use crate::math::scalar::{wrap_angle, Angle, Scalar};
+use crate::odometry::Twist;
 use std::{ops, cmp, fmt};
 use approx::{RelativeEq};
-use std::ops::{Sub, Mul};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Pose {
@@ -17,46 +18,34 @@ impl fmt::Display for Pose {
     }
 }
 
-/// overload Pose addition
+/// overload Pose addition, componentwise with the heading wrapped, and not a rigid body
+/// composition (see compose)
 impl ops::Add for Pose {
     type Output = Pose;
 
     fn add(self, rhs: Self) -> Self::Output {
         let position = self.position + rhs.position;
-        let heading = self.heading + rhs.heading;
+        let heading = wrap_angle(self.heading + rhs.heading);
         Pose::new(position, heading)
     }
 }
 
-/// overload Pose subtraction
+/// overload Pose subtraction, componentwise with the heading wrapped, and not a relative
+/// pose (see relative_to)
 impl ops::Sub for Pose {
     type Output = Pose;
 
     fn sub(self, rhs: Self) -> Self::Output {
         let position = self.position - rhs.position;
-        let heading = self.heading - rhs.heading;
+        let heading = wrap_angle(self.heading - rhs.heading);
         Pose::new(position, heading)
     }
 }
 
-/// overload Pose and Pose multiplication
-impl ops::Mul for Pose {
-    type Output = Pose;
-
-    fn mul(self, rhs: Self) -> Self::Output {
-        let position = self.position * rhs.position;
-        let heading = self.heading * rhs.heading;
-        Pose::new(position, heading)
-    }
-}
-
-/// overload Vector addition
+/// overload Pose addition assignment, componentwise with the heading wrapped
 impl ops::AddAssign for Pose {
     fn add_assign(&mut self, rhs: Self) {
-        *self = Self {
-            position: self.position + rhs.position,
-            heading: self.heading + rhs.heading
-        }
+        *self = *self + rhs
     }
 }
 
@@ -71,22 +60,22 @@ impl cmp::PartialEq for Pose {
     }
 }
 
-/// overload Pose multiplied by Scalar
+/// overload Pose multiplied by Scalar, the heading is wrapped
 impl ops::Mul<Scalar> for Pose {
     type Output = Pose;
 
     fn mul(self, s: Scalar) -> Self::Output {
-        Pose::new(self.position * s, self.heading * s)
+        Pose::new(self.position * s, wrap_angle(self.heading * s))
     }
 }
 
 
-/// overload Pose divided by Scalar
+/// overload Pose divided by Scalar, the heading is wrapped
 impl ops::Div<Scalar> for Pose {
     type Output = Pose;
 
     fn div(self, s: Scalar) -> Self::Output {
-        Pose::new(self.position / s, self.heading / s)
+        Pose::new(self.position / s, wrap_angle(self.heading / s))
     }
 }
 
@@ -97,11 +86,79 @@ impl Default for Pose {
     }
 }
 
+/// below this angle [rad] exp and log use the series expansions of sin(x) / x and
+/// (1 - cos(x)) / x to stay accurate
+const SMALL_ANGLE: Angle = 1e-6;
+
 impl Pose {
     pub fn new(position: Point, heading: Angle) -> Pose {
         Pose { position, heading }
     }
 
+    /// Rigid body composition self ⊕ other: the pose other, given in the frame of self, in
+    /// the frame self is given in. E.g. the robot pose after moving by an odometry delta
+    /// measured in the frame of the robot.
+    ///
+    /// More info:
+    ///  - A Tutorial on SE(2) Transformation Parameterizations and On-Manifold Optimization,
+    ///    J. L. Blanco
+    pub fn compose(&self, other: &Pose) -> Pose {
+        Pose::new(self.transform_point(other.position), wrap_angle(self.heading + other.heading))
+    }
+
+    /// the pose that composes with self to the identity
+    pub fn inverse(&self) -> Pose {
+        let position = self.position.to_vec().rotate(-self.heading);
+        Pose::new(Point::new(-position.x, -position.y), wrap_angle(-self.heading))
+    }
+
+    /// Relative pose self ⊖ base: self in the frame of base, so that
+    /// base.compose(&self.relative_to(base)) == self
+    pub fn relative_to(&self, base: &Pose) -> Pose {
+        base.inverse().compose(self)
+    }
+
+    /// map a point from the frame of self to the frame self is given in
+    pub fn transform_point(&self, p: Point) -> Point {
+        self.position + p.to_vec().rotate(self.heading)
+    }
+
+    /// Pose at fraction t of the way from self to other, moving along the constant velocity
+    /// arc between them (t = 0.0 gives self, t = 1.0 gives other)
+    pub fn interpolate(&self, other: &Pose, t: Scalar) -> Pose {
+        let delta = other.relative_to(self).log();
+        self.compose(&Pose::exp(&Twist::new(delta.velocity * t, delta.angular * t)))
+    }
+
+    /// Exponential map from the tangent space se(2) to SE(2): the pose reached from the
+    /// origin after moving for one second with the constant twist, given in the frame of the
+    /// robot
+    pub fn exp(twist: &Twist) -> Pose {
+        let (a, b) = Self::arc_coefficients(twist.angular);
+        let v = twist.velocity;
+        Pose::new(Point::new(a * v.x - b * v.y, b * v.x + a * v.y), wrap_angle(twist.angular))
+    }
+
+    /// Logarithmic map from SE(2) to the tangent space se(2), the inverse of exp. The
+    /// heading is wrapped first, so the twist takes the shortest turn to the pose
+    pub fn log(&self) -> Twist {
+        let heading = wrap_angle(self.heading);
+        let (a, b) = Self::arc_coefficients(heading);
+        let (x, y) = (self.position.x, self.position.y);
+        let d = a * a + b * b;
+        Twist::new(Vector::new((a * x + b * y) / d, (a * y - b * x) / d), heading)
+    }
+
+    /// sin(angle) / angle and (1 - cos(angle)) / angle, the translation of exp is
+    /// [a -b; b a] * velocity
+    fn arc_coefficients(angle: Angle) -> (Scalar, Scalar) {
+        if angle.abs() < SMALL_ANGLE {
+            (1.0 - angle * angle / 6.0, angle / 2.0 - angle.powi(3) / 24.0)
+        } else {
+            (angle.sin() / angle, (1.0 - angle.cos()) / angle)
+        }
+    }
+
     // TODO: Write a test to see if this works
     pub fn sqrt(&self) -> Pose {
         let x_sqrt = self.position.x.sqrt();
@@ -0,0 +1,69 @@
+use nalgebra as na;
+use crate::geometry::point::Point;
+use crate::math::scalar::{wrap_angle, Scalar};
+use crate::odometry::Pose;
+
+/// Covariance of a pose in (x, y, heading)
+pub type Covariance = na::Matrix3<Scalar>;
+
+/// A pose estimate together with its uncertainty
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseWithCovariance {
+    pub pose: Pose,
+    pub covariance: Covariance, // [m^2, rad^2] in (x, y, heading)
+}
+
+impl Default for PoseWithCovariance {
+    /// the origin, known exactly
+    fn default() -> PoseWithCovariance {
+        PoseWithCovariance::new(Pose::default(), Covariance::zeros())
+    }
+}
+
+impl PoseWithCovariance {
+    pub fn new(pose: Pose, covariance: Covariance) -> PoseWithCovariance {
+        PoseWithCovariance { pose, covariance }
+    }
+
+    /// Weighted mean and covariance of a set of poses, e.g. the particles of a filter. The
+    /// weights do not have to be normalized, if they sum to zero all poses count the same.
+    /// The mean heading is the circular mean and the heading deviations are wrapped, so poses
+    /// on both sides of +-PI do not average to a heading pointing the opposite way.
+    pub fn from_weighted_poses(poses: &[Pose], weights: &[Scalar]) -> PoseWithCovariance {
+        assert_eq!(poses.len(), weights.len());
+        if poses.is_empty() {
+            return PoseWithCovariance::default()
+        }
+
+        let weight_sum: Scalar = weights.iter().sum();
+        let weights: Vec<Scalar> = if weight_sum > 0.0 && weight_sum.is_finite() {
+            weights.iter().map(|w| w / weight_sum).collect()
+        } else {
+            vec![1.0 / poses.len() as Scalar; poses.len()]
+        };
+
+        let (mut x, mut y, mut sin, mut cos) = (0.0, 0.0, 0.0, 0.0);
+        for (pose, w) in poses.iter().zip(weights.iter()) {
+            x += w * pose.position.x;
+            y += w * pose.position.y;
+            sin += w * pose.heading.sin();
+            cos += w * pose.heading.cos();
+        }
+        let mean = Pose::new(Point::new(x, y), sin.atan2(cos));
+
+        let covariance = poses
+            .iter()
+            .zip(weights.iter())
+            .map(|(pose, w)| {
+                let d = na::Vector3::new(
+                    pose.position.x - mean.position.x,
+                    pose.position.y - mean.position.y,
+                    wrap_angle(pose.heading - mean.heading)
+                );
+                d * d.transpose() * *w
+            })
+            .fold(Covariance::zeros(), |sum, c| sum + c);
+
+        PoseWithCovariance::new(mean, covariance)
+    }
+}
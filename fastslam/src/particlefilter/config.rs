@@ -0,0 +1,224 @@
+use std::error::Error;
+use std::fmt;
+use crate::odometry::motion_model::{DEFAULT_ALPHA, DEFAULT_GYRO_ALPHA, DEFAULT_ODOMETRY_ALPHA};
+use crate::particlefilter::kld_sampling::KldParams;
+use crate::particlefilter::probabilistic_models::LikelihoodFieldParams;
+use crate::particlefilter::resampling::ResamplingScheme;
+use crate::scanmatching::icp::IcpParams;
+
+/// Tuning of the particle filter. Build it with FastSlamConfig::builder() to have it
+/// validated, or start from the defaults and check it with validate()
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FastSlamConfig {
+    pub n_particles: usize, // number of particles, the initial number with KLD-sampling
+    pub fixed_dt: Option<f64>, // [s] duration of every cycle (e.g. 1.0 in the simulator), None measures it with the wall clock
+    pub max_gain_age: f64, // [s] timestamped control inputs (gains, odometry, yaw rates) further from their scan are stale
+    pub icp: IcpParams, // scan matching of the latest scan against the previous one
+    pub icp_gate: IcpGate, // scan matches outside of it fall back to the motion model
+    pub n_pose_samples: usize, // poses sampled around the scan-matched pose to evaluate the proposal
+    pub sampling_range: f64, // standard deviation of the pose samples as a fraction of the motion in a cycle
+    pub resampling_threshold: f64, // resample when Neff drops below this fraction of the particles
+    pub resampling: ResamplingScheme, // how particles are drawn when resampling
+    pub kld: Option<KldParams>, // adapt the number of particles with KLD-sampling, None keeps it fixed
+    pub motion_noise: [f64; 6], // alpha_1:6 of the velocity motion model
+    pub odometry_noise: [f64; 4], // alpha_1:4 of the odometry motion model
+    pub gyro_noise: [f64; 4], // alpha_1:4 of the gyro aided motion model, wheels (1:2) and gyro (3:4)
+    pub likelihood_field: LikelihoodFieldParams, // measurement model of the laser scanner
+}
+
+impl Default for FastSlamConfig {
+    fn default() -> FastSlamConfig {
+        FastSlamConfig {
+            n_particles: 25,
+            fixed_dt: Some(1.0),
+            max_gain_age: 0.5,
+            icp: IcpParams::default(),
+            icp_gate: IcpGate::default(),
+            n_pose_samples: 50,
+            sampling_range: 0.05,
+            resampling_threshold: 0.5,
+            resampling: ResamplingScheme::default(),
+            kld: None,
+            motion_noise: DEFAULT_ALPHA,
+            odometry_noise: DEFAULT_ODOMETRY_ALPHA,
+            gyro_noise: DEFAULT_GYRO_ALPHA,
+            likelihood_field: LikelihoodFieldParams::default(),
+        }
+    }
+}
+
+impl FastSlamConfig {
+    pub fn builder() -> FastSlamConfigBuilder {
+        FastSlamConfigBuilder::default()
+    }
+
+    /// Defaults for localization in a known map: enough particles to cover the map, adapted
+    /// with KLD-sampling once they concentrate, and a likelihood field about as wide as the
+    /// cells of a map
+    pub fn localization() -> FastSlamConfig {
+        FastSlamConfig {
+            n_particles: 2000,
+            kld: Some(KldParams { min_particles: 500, max_particles: 5000, ..KldParams::default() }),
+            motion_noise: [0.1, 0.1, 0.1, 0.1, 0.05, 0.05],
+            likelihood_field: LikelihoodFieldParams { sigma_hit: 0.3, ..LikelihoodFieldParams::default() },
+            ..FastSlamConfig::default()
+        }
+    }
+
+    /// Ok if every parameter is in its valid range, otherwise the first one that is not
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let check = |valid: bool, parameter: &'static str, reason: &'static str| {
+            if valid { Ok(()) } else { Err(ConfigError { parameter, reason }) }
+        };
+        let positive = |v: f64| v > 0.0 && v.is_finite();
+        let non_negative = |v: f64| v >= 0.0 && v.is_finite();
+
+        check(self.n_particles > 0, "n_particles", "at least one particle is needed")?;
+        check(self.fixed_dt.into_iter().all(positive), "fixed_dt", "must be positive")?;
+        check(non_negative(self.max_gain_age), "max_gain_age", "must not be negative")?;
+        check(self.icp.max_iterations > 0, "icp.max_iterations", "at least one iteration is needed")?;
+        check(non_negative(self.icp.tolerance), "icp.tolerance", "must not be negative")?;
+        check(non_negative(self.icp_gate.max_residual), "icp_gate.max_residual", "must not be negative")?;
+        check(non_negative(self.icp_gate.max_translation), "icp_gate.max_translation", "must not be negative")?;
+        check(non_negative(self.icp_gate.max_rotation), "icp_gate.max_rotation", "must not be negative")?;
+        check(self.n_pose_samples > 0, "n_pose_samples", "at least one pose sample is needed")?;
+        check(non_negative(self.sampling_range), "sampling_range", "must not be negative")?;
+        check((0.0..=1.0).contains(&self.resampling_threshold), "resampling_threshold", "must be between 0.0 and 1.0")?;
+        check(self.motion_noise.iter().all(|&a| non_negative(a)), "motion_noise", "must not be negative")?;
+        check(self.odometry_noise.iter().all(|&a| non_negative(a)), "odometry_noise", "must not be negative")?;
+        check(self.gyro_noise.iter().all(|&a| non_negative(a)), "gyro_noise", "must not be negative")?;
+
+        let field = &self.likelihood_field;
+        check((0.0..=1.0).contains(&field.z_hit), "likelihood_field.z_hit", "must be between 0.0 and 1.0")?;
+        check(positive(field.z_max), "likelihood_field.z_max", "must be positive")?;
+        check(positive(field.sigma_hit), "likelihood_field.sigma_hit", "must be positive")?;
+        check(positive(field.max_distance), "likelihood_field.max_distance", "must be positive")?;
+
+        if let Some(kld) = &self.kld {
+            let bin = &kld.bin_size;
+            check(kld.min_particles > 0, "kld.min_particles", "at least one particle is needed")?;
+            check(kld.min_particles <= kld.max_particles, "kld.max_particles", "must not be below min_particles")?;
+            check(positive(bin.position.x) && positive(bin.position.y) && positive(bin.heading), "kld.bin_size", "must be positive")?;
+            check(positive(kld.epsilon), "kld.epsilon", "must be positive")?;
+            check(kld.delta > 0.0 && kld.delta < 1.0, "kld.delta", "must be between 0.0 and 1.0")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bounds within which a scan match is trusted over the motion model pose
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IcpGate {
+    pub max_residual: f64, // [m] scan matches leaving a larger mean distance between the scans are not trusted
+    pub max_translation: f64, // [m] scan matches moving the motion model pose further are not trusted
+    pub max_rotation: f64, // [rad] scan matches turning the motion model pose further are not trusted
+}
+
+impl Default for IcpGate {
+    fn default() -> IcpGate {
+        IcpGate {
+            max_residual: 0.1,
+            max_translation: 0.5,
+            max_rotation: 0.35,
+        }
+    }
+}
+
+/// A parameter of the configuration that is out of range
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub parameter: &'static str,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {}: {}", self.parameter, self.reason)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Builds a FastSlamConfig starting from the defaults
+#[derive(Debug, Clone, Default)]
+pub struct FastSlamConfigBuilder {
+    config: FastSlamConfig,
+}
+
+impl FastSlamConfigBuilder {
+    pub fn n_particles(mut self, n_particles: usize) -> Self {
+        self.config.n_particles = n_particles;
+        self
+    }
+
+    pub fn fixed_dt(mut self, fixed_dt: Option<f64>) -> Self {
+        self.config.fixed_dt = fixed_dt;
+        self
+    }
+
+    pub fn max_gain_age(mut self, max_gain_age: f64) -> Self {
+        self.config.max_gain_age = max_gain_age;
+        self
+    }
+
+    pub fn icp(mut self, icp: IcpParams) -> Self {
+        self.config.icp = icp;
+        self
+    }
+
+    pub fn icp_gate(mut self, icp_gate: IcpGate) -> Self {
+        self.config.icp_gate = icp_gate;
+        self
+    }
+
+    pub fn n_pose_samples(mut self, n_pose_samples: usize) -> Self {
+        self.config.n_pose_samples = n_pose_samples;
+        self
+    }
+
+    pub fn sampling_range(mut self, sampling_range: f64) -> Self {
+        self.config.sampling_range = sampling_range;
+        self
+    }
+
+    pub fn resampling_threshold(mut self, resampling_threshold: f64) -> Self {
+        self.config.resampling_threshold = resampling_threshold;
+        self
+    }
+
+    pub fn resampling(mut self, resampling: ResamplingScheme) -> Self {
+        self.config.resampling = resampling;
+        self
+    }
+
+    pub fn kld(mut self, kld: Option<KldParams>) -> Self {
+        self.config.kld = kld;
+        self
+    }
+
+    pub fn motion_noise(mut self, motion_noise: [f64; 6]) -> Self {
+        self.config.motion_noise = motion_noise;
+        self
+    }
+
+    pub fn odometry_noise(mut self, odometry_noise: [f64; 4]) -> Self {
+        self.config.odometry_noise = odometry_noise;
+        self
+    }
+
+    pub fn gyro_noise(mut self, gyro_noise: [f64; 4]) -> Self {
+        self.config.gyro_noise = gyro_noise;
+        self
+    }
+
+    pub fn likelihood_field(mut self, likelihood_field: LikelihoodFieldParams) -> Self {
+        self.config.likelihood_field = likelihood_field;
+        self
+    }
+
+    /// the configuration, if it is valid
+    pub fn build(self) -> Result<FastSlamConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::math::timer::Timer;
+use crate::odometry::{Odometry, Pose};
+use crate::particlefilter::config::FastSlamConfig;
+use crate::particlefilter::particle_filter::{Control, InputError};
+use crate::sensor::stamped::Timestamp;
+
+/// What a filter remembers about its control inputs from one cycle to the next: the clock of
+/// cycles without timestamps, the time of the previous stamped scan and the previous
+/// odometry reading. Shared by FastSLAM and MCL, so both take their inputs the same way
+#[derive(Debug, Clone)]
+pub struct ControlInputs {
+    timer: Timer,
+    last_timestamp: Option<Timestamp>, // time of the last scan passed to a stamped cycle
+    last_odometry: Option<Pose> // odometry pose of the last reading passed to a cycle with odometry
+}
+
+impl Default for ControlInputs {
+    fn default() -> ControlInputs {
+        ControlInputs { timer: Timer::init_time(), last_timestamp: None, last_odometry: None }
+    }
+}
+
+impl ControlInputs {
+    /// [s] time since the previous cycle, the fixed dt of the configuration or, without one,
+    /// measured with the wall clock
+    pub fn cycle_dt(&mut self, config: &FastSlamConfig) -> f64 {
+        match config.fixed_dt {
+            Some(dt) => dt, // 1.0s runs nicely with the simulator
+            None => self.timer.get_dt()
+        }
+    }
+
+    /// [s] time since the previous scan (0.0 for the first scan), if the timestamps of the
+    /// scan and of its control inputs are valid: the scan is newer than the previous one and
+    /// the inputs are no further than max_gain_age from it. The scan becomes the previous scan
+    pub fn stamped_dt(&mut self, scan: Timestamp, inputs: &[Timestamp], config: &FastSlamConfig) -> Result<f64, InputError> {
+        for &t in [scan].iter().chain(inputs.iter()) {
+            if !t.is_finite() {
+                return Err(InputError::InvalidTimestamp(t))
+            }
+        }
+        if let Some(previous) = self.last_timestamp {
+            if scan <= previous {
+                return Err(InputError::OutOfOrder { timestamp: scan, previous })
+            }
+        }
+        for &input in inputs.iter() {
+            if (scan - input).abs() > config.max_gain_age {
+                return Err(InputError::Stale { input, scan })
+            }
+        }
+
+        let dt = self.last_timestamp.map_or(0.0, |previous| scan - previous);
+        self.last_timestamp = Some(scan);
+        Ok(dt)
+    }
+
+    /// the control input from the previous to this odometry reading, with the rotation of
+    /// the gyro if there is one. The robot does not move at all for the first reading. The
+    /// reading becomes the previous reading
+    pub fn odometry(&mut self, odometry: &Odometry, yaw_rate: Option<f64>, dt: f64) -> Control {
+        let curr = odometry.pose;
+        match (self.last_odometry.replace(curr), yaw_rate) {
+            (Some(prev), Some(yaw_rate)) => Control::OdometryGyro { prev, curr, yaw_rate, dt },
+            (prev, _) => Control::Odometry { prev: prev.unwrap_or(curr), curr }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use rand::{Rng, RngCore};
+use crate::geometry::Point;
+use crate::math::scalar::wrap_angle;
+use crate::math::utils::normal_quantile;
+use crate::odometry::Pose;
+use crate::particlefilter::particle::Particle;
+use crate::particlefilter::resampling::{cumulative_weights, select};
+
+/// Parameters of KLD-sampling
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct KldParams {
+    pub min_particles: usize, // never fewer particles, also when the posterior is a single bin
+    pub max_particles: usize, // never more particles, however spread out the posterior is
+    pub bin_size: Pose, // [m, m, rad] size of the histogram bins in (x, y, heading)
+    pub epsilon: f64, // bound on the KL-divergence between the particles and the posterior
+    pub delta: f64, // probability that the KL-divergence exceeds epsilon
+}
+
+impl Default for KldParams {
+    fn default() -> KldParams {
+        KldParams {
+            min_particles: 10,
+            max_particles: 200,
+            bin_size: Pose::new(Point::new(0.5, 0.5), 10.0_f64.to_radians()),
+            epsilon: 0.05,
+            delta: 0.01,
+        }
+    }
+}
+
+/// Number of particles needed so that, with probability 1 - delta, the KL-divergence between
+/// the particles and the true posterior stays below epsilon, if the posterior covers k bins.
+/// The chi-square quantile is computed with the Wilson-Hilferty approximation. A posterior in
+/// a single bin needs no particles beyond the minimum.
+///
+/// More info:
+///  - Adapting the Sample Size in Particle Filters Through KLD-Sampling, D. Fox
+///  - p.264 Table 8.4 in probabilistic robotics, Sebastian Thrun et al.
+pub fn kld_sample_count(k: usize, epsilon: f64, delta: f64) -> usize {
+    if k <= 1 {
+        return 0
+    }
+
+    let k = (k - 1) as f64;
+    let a = 2.0 / (9.0 * k);
+    let z = normal_quantile(1.0 - delta);
+    (k / (2.0 * epsilon) * (1.0 - a + a.sqrt() * z).powi(3)).ceil() as usize
+}
+
+/// KLD-sampling: resamples the particles one at a time according to their weights and keeps
+/// count of the histogram bins the drawn poses fall into. Sampling stops once enough
+/// particles for the number of occupied bins have been drawn, so the particle set grows
+/// when the posterior is spread out (e.g. during global localization) and shrinks when it
+/// concentrates. All particles get the same weight.
+///
+/// Input:
+///     particles: current vector of particles, the weights do not have to be normalized
+///     params: bounds of the particle count, bin size and error bounds
+/// Returns:
+///     particles: new vector of resampled particles, between min_particles and max_particles
+pub fn kld_resample(particles: &[Particle], params: &KldParams, rng: &mut dyn RngCore) -> Vec<Particle> {
+    if particles.is_empty() {
+        return vec![]
+    }
+
+    let weights: Vec<f64> = particles.iter().map(|p| p.weight).collect();
+    let cumulative = cumulative_weights(&weights);
+    let target = |required: usize| required.max(params.min_particles).min(params.max_particles);
+
+    let mut bins = HashSet::new();
+    let mut required = 0;
+    let mut resampled: Vec<Particle> = vec![];
+    while resampled.len() < target(required) {
+        let p = &particles[select(&cumulative, rng.gen_range(0.0..1.0))];
+        if bins.insert(bin(&p.pose, &params.bin_size)) {
+            required = kld_sample_count(bins.len(), params.epsilon, params.delta);
+        }
+        resampled.push(p.clone());
+    }
+
+    let weight = 1.0 / resampled.len() as f64;
+    resampled.iter_mut().for_each(|p| p.set_weight(weight));
+    resampled
+}
+
+/// index of the histogram bin of a pose
+fn bin(pose: &Pose, bin_size: &Pose) -> (i64, i64, i64) {
+    (
+        (pose.position.x / bin_size.position.x).floor() as i64,
+        (pose.position.y / bin_size.position.y).floor() as i64,
+        (wrap_angle(pose.heading) / bin_size.heading).floor() as i64
+    )
+}
@@ -0,0 +1,294 @@
+use nalgebra as na;
+use rand::Rng;
+use rayon::prelude::*;
+use crate::geometry::Point;
+use crate::gridmap::grid_map::GridMap;
+use crate::math::scalar::{wrap_angle, PI};
+use crate::odometry::{Odometry, Pose, PoseWithCovariance, Twist};
+use crate::particlefilter::config::{ConfigError, FastSlamConfig};
+use crate::particlefilter::control_inputs::ControlInputs;
+use crate::particlefilter::kld_sampling::kld_resample;
+use crate::particlefilter::particle::Particle;
+use crate::particlefilter::particle_filter::{Control, InputError, ParticleFilter};
+use crate::particlefilter::probabilistic_models::log_likelihood_field_range_finder_model_with_params;
+use crate::particlefilter::resampling::Resampler;
+use crate::sensor::laserscanner::Scan;
+use crate::sensor::noise::gaussian;
+use crate::sensor::stamped::{Stamped, Timestamp};
+use std::error::Error;
+use std::fmt;
+
+/// Why a localization could not be set up
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalizationError {
+    Config(ConfigError), // the configuration is invalid
+    NoFreeSpace, // the map has no free cells to place the particles in
+}
+
+impl fmt::Display for LocalizationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LocalizationError::Config(error) => error.fmt(f),
+            LocalizationError::NoFreeSpace => write!(f, "the map has no free space to place particles in"),
+        }
+    }
+}
+
+impl Error for LocalizationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LocalizationError::Config(error) => Some(error),
+            LocalizationError::NoFreeSpace => None,
+        }
+    }
+}
+
+impl From<ConfigError> for LocalizationError {
+    fn from(error: ConfigError) -> LocalizationError {
+        LocalizationError::Config(error)
+    }
+}
+
+/// Monte Carlo localization (MCL) in a known map: the particles are pose hypotheses that
+/// move with samples of the motion model and are weighted with the likelihood field of the
+/// map. The map is never updated. With KLD-sampling in the configuration the number of
+/// particles adapts to the spread of the particles, as in AMCL.
+///
+/// The particles do not carry a map of their own, their grid maps stay empty.
+///
+/// More info:
+///  - p.252 Table 8.2 in probabilistic robotics, Sebastian Thrun et al.
+///  - p.264 Table 8.4 in probabilistic robotics, Sebastian Thrun et al.
+#[derive(Clone)]
+pub struct MonteCarloLocalization {
+    config: FastSlamConfig,
+    map: GridMap,
+    free_space: Vec<Point>, // world coordinates of the centres of the free cells of the map
+    inputs: ControlInputs,
+    particles: Vec<Particle>,
+    pub estimate: PoseWithCovariance, // weighted mean pose and covariance of the particles
+    pub neff: f64 // effective number of particles of the last cycle, before resampling
+}
+
+impl MonteCarloLocalization {
+    /// Global localization: the particles are spread uniformly over the free space of the
+    /// map with random headings. Fails if the configuration is invalid or the map has no
+    /// free space
+    pub fn new(config: FastSlamConfig, map: GridMap) -> Result<MonteCarloLocalization, LocalizationError> {
+        let mut mcl = MonteCarloLocalization::without_particles(config, map)?;
+        if mcl.free_space.is_empty() {
+            return Err(LocalizationError::NoFreeSpace)
+        }
+        mcl.initialize_global();
+        Ok(mcl)
+    }
+
+    /// Localization from a known initial pose: the particles are drawn from a normal
+    /// distribution around the pose with its covariance. Fails if the configuration is invalid
+    pub fn with_initial_pose(config: FastSlamConfig, map: GridMap, initial: &PoseWithCovariance) -> Result<MonteCarloLocalization, LocalizationError> {
+        let mut mcl = MonteCarloLocalization::without_particles(config, map)?;
+        mcl.initialize(initial);
+        Ok(mcl)
+    }
+
+    /// the localization with a validated configuration and the free space of the map, but no
+    /// particles yet
+    fn without_particles(config: FastSlamConfig, map: GridMap) -> Result<MonteCarloLocalization, LocalizationError> {
+        config.validate()?;
+
+        let free_space: Vec<Point> = map
+            .get_all_free_cells()
+            .iter()
+            .map(|c| map.map_to_world(c.x as i64, c.y as i64))
+            .collect();
+
+        Ok(MonteCarloLocalization {
+            config,
+            map,
+            free_space,
+            inputs: ControlInputs::default(),
+            particles: vec![],
+            estimate: PoseWithCovariance::default(),
+            neff: 0.0
+        })
+    }
+
+    /// spread the particles uniformly over the free space of the map, e.g. when the robot
+    /// got lost or was moved. Keeps the particles if the map has no free space
+    pub fn initialize_global(&mut self) {
+        if self.free_space.is_empty() {
+            return
+        }
+        let cell_size = self.map.cell_size();
+        let mut rng = rand::thread_rng();
+
+        let poses: Vec<Pose> = (0..self.config.n_particles)
+            .map(|_| {
+                let cell = self.free_space[rng.gen_range(0..self.free_space.len())];
+                Pose::new(
+                    Point::new(
+                        cell.x + cell_size * rng.gen_range(-0.5..0.5),
+                        cell.y + cell_size * rng.gen_range(-0.5..0.5)
+                    ),
+                    rng.gen_range(-PI..PI)
+                )
+            })
+            .collect();
+        self.reset_particles(poses);
+    }
+
+    /// draw the particles from a normal distribution around the pose with its covariance
+    pub fn initialize(&mut self, initial: &PoseWithCovariance) {
+        // covariance = V * diag(lambda) * V^T, so V * sqrt(lambda) * n is distributed with
+        // the covariance for standard normal n. Unlike a cholesky decomposition this works
+        // for singular covariances, e.g. an exactly known heading
+        let eigen = initial.covariance.symmetric_eigen();
+        let std_dev = eigen.eigenvalues.map(|lambda| lambda.max(0.0).sqrt());
+        let transform = eigen.eigenvectors * na::Matrix3::from_diagonal(&std_dev);
+
+        let poses: Vec<Pose> = (0..self.config.n_particles)
+            .map(|_| {
+                let n = na::Vector3::new(gaussian(0.0, 1.0), gaussian(0.0, 1.0), gaussian(0.0, 1.0));
+                let d = transform * n;
+                Pose::new(
+                    Point::new(initial.pose.position.x + d.x, initial.pose.position.y + d.y),
+                    wrap_angle(initial.pose.heading + d.z)
+                )
+            })
+            .collect();
+        self.reset_particles(poses);
+    }
+
+    fn reset_particles(&mut self, poses: Vec<Pose>) {
+        let weight = 1.0 / poses.len() as f64;
+        let empty_map = GridMap::new(self.map.cell_size());
+
+        self.particles = poses.into_iter().map(|pose| Particle::new(pose, weight, empty_map.clone())).collect();
+        self.estimate = ParticleFilter::compute_estimate(&self.particles);
+        self.neff = self.particles.len() as f64;
+    }
+
+    pub fn config(&self) -> &FastSlamConfig {
+        &self.config
+    }
+
+    /// the known map the robot localizes in
+    pub fn map(&self) -> &GridMap {
+        &self.map
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// current number of particles, it changes with KLD-sampling
+    pub fn n_particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// normalized weights of the particles, they sum to 1.0
+    pub fn weights(&self) -> Vec<f64> {
+        self.particles.iter().map(|p| p.weight).collect()
+    }
+
+    /// particle with the highest weight
+    pub fn best_particle(&self) -> &Particle {
+        self.particles
+            .iter()
+            .max_by(|a, b| a.weight.partial_cmp(&b.weight).unwrap_or(std::cmp::Ordering::Equal))
+            .expect("there is at least one particle")
+    }
+
+    /// scan: z_t - the most recent laser scan
+    /// gain: u_t-1 - the most recent gain, applied since the previous cycle
+    ///
+    /// The time since the previous cycle is the fixed dt of the configuration or, without
+    /// one, measured with the wall clock
+    pub fn cycle(&mut self, scan: &Scan, gain: &Twist) {
+        self.cycle_with_gyro(scan, gain, None);
+    }
+
+    /// cycle with the yaw rate [rad/s] of a gyro, if there is one, for the rotation
+    pub fn cycle_with_gyro(&mut self, scan: &Scan, gain: &Twist, yaw_rate: Option<f64>) {
+        let dt = self.inputs.cycle_dt(&self.config);
+        self.update(scan, &Control::from_gain(gain, yaw_rate, dt));
+    }
+
+    /// cycle with wheel odometry as the control input: the robot moved from the pose of the
+    /// previous reading to the pose of this one (not at all for the first reading)
+    pub fn cycle_odometry(&mut self, scan: &Scan, odometry: &Odometry) {
+        let control = self.inputs.odometry(odometry, None, 0.0);
+        self.update(scan, &control);
+    }
+
+    /// cycle_odometry with the yaw rate [rad/s] of a gyro, if there is one, for the rotation
+    pub fn cycle_odometry_with_gyro(&mut self, scan: &Scan, odometry: &Odometry, yaw_rate: Option<f64>) {
+        let dt = self.inputs.cycle_dt(&self.config);
+        let control = self.inputs.odometry(odometry, yaw_rate, dt);
+        self.update(scan, &control);
+    }
+
+    /// cycle with timestamped inputs, checked like in ParticleFilter::cycle_stamped: rejected
+    /// inputs leave the particles unchanged. Returns the time since the previous scan
+    pub fn cycle_stamped(&mut self, scan: &Stamped<Scan>, gain: &Stamped<Twist>) -> Result<f64, InputError> {
+        self.cycle_with_gyro_stamped(scan, gain, None)
+    }
+
+    /// cycle_odometry with timestamped inputs. Returns the time since the previous scan
+    pub fn cycle_odometry_stamped(&mut self, scan: &Stamped<Scan>, odometry: &Stamped<Odometry>) -> Result<f64, InputError> {
+        self.cycle_odometry_with_gyro_stamped(scan, odometry, None)
+    }
+
+    /// cycle_with_gyro with timestamped inputs. Returns the time since the previous scan
+    pub fn cycle_with_gyro_stamped(&mut self, scan: &Stamped<Scan>, gain: &Stamped<Twist>, yaw_rate: Option<&Stamped<f64>>) -> Result<f64, InputError> {
+        let inputs: Vec<Timestamp> = std::iter::once(gain.timestamp).chain(yaw_rate.map(|y| y.timestamp)).collect();
+        let dt = self.inputs.stamped_dt(scan.timestamp, &inputs, &self.config)?;
+        self.update(&scan.data, &Control::from_gain(&gain.data, yaw_rate.map(|y| y.data), dt));
+        Ok(dt)
+    }
+
+    /// cycle_odometry_with_gyro with timestamped inputs. Returns the time since the previous
+    /// scan
+    pub fn cycle_odometry_with_gyro_stamped(&mut self, scan: &Stamped<Scan>, odometry: &Stamped<Odometry>, yaw_rate: Option<&Stamped<f64>>) -> Result<f64, InputError> {
+        let inputs: Vec<Timestamp> = std::iter::once(odometry.timestamp).chain(yaw_rate.map(|y| y.timestamp)).collect();
+        let dt = self.inputs.stamped_dt(scan.timestamp, &inputs, &self.config)?;
+        let control = self.inputs.odometry(&odometry.data, yaw_rate.map(|y| y.data), dt);
+        self.update(&scan.data, &control);
+        Ok(dt)
+    }
+
+    /// one step of MCL: sample the motion, weight with the measurement and resample
+    #[allow(non_snake_case)]
+    fn update(&mut self, scan: &Scan, control: &Control) {
+        let config = &self.config;
+        let map = &self.map;
+
+        // step 1 & 2.)
+        // sample the pose x_t from p(x_t | x_t-1, u_t) and weight it with p(z_t | x_t, m)
+        self.particles
+            .par_iter_mut()
+            .for_each(|p: &mut Particle| {
+                p.pose = control.sample(&p.pose, config);
+                p.log_weight += log_likelihood_field_range_finder_model_with_params(scan, &p.pose, map, &config.likelihood_field);
+            });
+
+        ParticleFilter::normalize_weights(&mut self.particles);
+        self.estimate = ParticleFilter::compute_estimate(&self.particles);
+
+        let Neff = ParticleFilter::compute_neff(&self.particles);
+        self.neff = Neff;
+
+        // step 3.)
+        // resample, but only after the robot moved, so standing still does not wear down
+        // the diversity of the particles
+        if control.extent() == (0.0, 0.0) {
+            return
+        }
+
+        if let Some(kld) = &self.config.kld {
+            self.particles = kld_resample(&self.particles, kld, &mut rand::thread_rng());
+        } else if Neff < self.config.resampling_threshold * self.particles.len() as f64 {
+            self.particles = self.config.resampling.resample(&self.particles);
+        }
+    }
+}
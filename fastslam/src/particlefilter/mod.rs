@@ -1,3 +1,7 @@
+pub mod config;
+pub mod control_inputs;
+pub mod kld_sampling;
+pub mod localization;
 pub mod particle;
 pub mod particle_filter;
 pub mod probabilistic_models;
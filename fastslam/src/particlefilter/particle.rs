@@ -12,6 +12,7 @@ pub struct Particle {
     pub prev_pointcloud: PointCloud,
     pub pose: Pose, // particle's pose (x, y, theta)
     pub weight: Scalar, // particle's current weight
+    pub log_weight: Scalar, // natural logarithm of the weight, updated without under- or overflow
     pub gridmap: GridMap // particle's estimated grid map of the environment
 }
 
@@ -23,6 +24,7 @@ impl Default for Particle {
             prev_pointcloud: PointCloud::empty(),
             pose: Pose::default(),
             weight: 1.0,
+            log_weight: 0.0,
             gridmap: GridMap::default()
         }
     }
@@ -42,10 +44,17 @@ impl Particle {
             prev_pointcloud: PointCloud::empty(),
             pose,
             weight,
+            log_weight: weight.ln(),
             gridmap
         }
     }
 
+    /// set the weight and its logarithm
+    pub fn set_weight(&mut self, weight: Scalar) {
+        self.weight = weight;
+        self.log_weight = weight.ln();
+    }
+
     pub fn get_prev_observation(&self) -> PointCloud {
         self.prev_pointcloud.clone()
     }
@@ -1,47 +1,164 @@
 use crate::gridmap::grid_map::GridMap;
-use crate::odometry::{Pose, Twist, MotionModel};
+use crate::odometry::{Odometry, Pose, PoseWithCovariance, Twist, MotionModel};
+use crate::odometry::motion_model::odometry_delta;
+use crate::math::scalar::wrap_angle;
 use crate::sensor::laserscanner::Scan;
 use crate::particlefilter::particle::Particle;
 use rayon::prelude::*;
-use crate::math::timer::Timer;
-use crate::scanmatching::icp::icp;
+use crate::scanmatching::icp::{icp_with_guess, IcpResult};
 use crate::geometry::Point;
 use crate::sensor::noise::gaussian;
-use crate::particlefilter::probabilistic_models::{motion_model_velocity, likelihood_field_range_finder_model};
-use crate::particlefilter::resampling::{low_variance_sampler, resampler};
+use crate::particlefilter::probabilistic_models::{log_motion_model_velocity_with_noise, log_motion_model_odometry_with_noise, log_motion_model_gyro_with_noise, log_motion_model_odometry_gyro_with_noise, log_likelihood_field_range_finder_model_with_params};
+use crate::math::utils::log_sum_exp;
+use crate::particlefilter::resampling::Resampler;
+use crate::particlefilter::kld_sampling::kld_resample;
+use crate::particlefilter::config::{ConfigError, FastSlamConfig};
+use crate::particlefilter::control_inputs::ControlInputs;
+use crate::sensor::stamped::{Stamped, Timestamp};
+use std::error::Error;
+use std::fmt;
+
+/// Why a timestamped input was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    InvalidTimestamp(Timestamp), // not a finite number
+    OutOfOrder { timestamp: Timestamp, previous: Timestamp }, // scan not newer than the previous scan
+    Stale { input: Timestamp, scan: Timestamp }, // control input (gain, odometry or yaw rate) measured too long before or after the scan
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputError::InvalidTimestamp(t) => write!(f, "invalid timestamp {}", t),
+            InputError::OutOfOrder { timestamp, previous } =>
+                write!(f, "scan at {} s is not newer than the previous scan at {} s", timestamp, previous),
+            InputError::Stale { input, scan } =>
+                write!(f, "control input at {} s is {} s away from the scan at {} s", input, (scan - input).abs(), scan),
+        }
+    }
+}
+
+impl Error for InputError {}
+
+/// The control input u_t of a cycle
+#[derive(Debug, Clone)]
+pub enum Control {
+    Velocity { gain: Twist, dt: f64 }, // gain applied for dt
+    Odometry { prev: Pose, curr: Pose }, // motion between two odometry readings
+    Gyro { gain: Twist, yaw_rate: f64, dt: f64 }, // translation of the gain and rotation of the gyro for dt
+    OdometryGyro { prev: Pose, curr: Pose, yaw_rate: f64, dt: f64 }, // translation between two odometry readings and rotation of the gyro for dt
+}
+
+impl Control {
+    /// the gain applied for dt, turning as measured by the gyro if there is a yaw rate [rad/s]
+    pub fn from_gain(gain: &Twist, yaw_rate: Option<f64>, dt: f64) -> Control {
+        match yaw_rate {
+            Some(yaw_rate) => Control::Gyro { gain: gain.clone(), yaw_rate, dt },
+            None => Control::Velocity { gain: gain.clone(), dt }
+        }
+    }
+
+    /// [m, rad] distance and rotation of the motion
+    pub fn extent(&self) -> (f64, f64) {
+        match self {
+            Control::Velocity { gain, dt } => ((gain.velocity.x * dt).abs(), (gain.angular * dt).abs()),
+            Control::Odometry { prev, curr } => {
+                let (_, trans, _) = odometry_delta(prev, curr);
+                (trans.abs(), wrap_angle(curr.heading - prev.heading).abs())
+            }
+            Control::Gyro { gain, yaw_rate, dt } => ((gain.velocity.x * dt).abs(), (yaw_rate * dt).abs()),
+            Control::OdometryGyro { prev, curr, yaw_rate, dt } => {
+                let (_, trans, _) = odometry_delta(prev, curr);
+                (trans.abs(), (yaw_rate * dt).abs())
+            }
+        }
+    }
+
+    /// pose after applying the control to pose, without noise
+    pub fn drive(&self, pose: &Pose) -> Pose {
+        match self {
+            Control::Velocity { gain, dt } => ParticleFilter::drive(pose, gain, *dt),
+            Control::Odometry { prev, curr } => ParticleFilter::drive_odometry(pose, prev, curr),
+            Control::Gyro { gain, yaw_rate, dt } => ParticleFilter::drive_gyro(pose, gain, *yaw_rate, *dt),
+            Control::OdometryGyro { prev, curr, yaw_rate, dt } => ParticleFilter::drive_odometry_gyro(pose, prev, curr, *yaw_rate, *dt)
+        }
+    }
+
+    /// a pose x_t drawn from p(x_t | x_t-1, u_t) of the motion model that belongs to the control
+    pub fn sample(&self, pose: &Pose, config: &FastSlamConfig) -> Pose {
+        match self {
+            Control::Velocity { gain, dt } =>
+                ParticleFilter::sample_motion_model_velocity_with_noise(pose, gain, *dt, &config.motion_noise),
+            Control::Odometry { prev, curr } =>
+                ParticleFilter::sample_motion_model_odometry_with_noise(pose, prev, curr, &config.odometry_noise),
+            Control::Gyro { gain, yaw_rate, dt } =>
+                ParticleFilter::sample_motion_model_gyro_with_noise(pose, gain, *yaw_rate, *dt, &config.gyro_noise),
+            Control::OdometryGyro { prev, curr, yaw_rate, dt } =>
+                ParticleFilter::sample_motion_model_odometry_gyro_with_noise(pose, prev, curr, *yaw_rate, *dt, &config.gyro_noise),
+        }
+    }
 
+    /// log p(x_t | x_t-1, u_t) of the motion model that belongs to the control
+    pub fn log_probability(&self, curr_pose: &Pose, prev_pose: &Pose, config: &FastSlamConfig) -> f64 {
+        match self {
+            Control::Velocity { gain, dt } =>
+                log_motion_model_velocity_with_noise(curr_pose, prev_pose, gain, *dt, &config.motion_noise),
+            Control::Odometry { prev, curr } =>
+                log_motion_model_odometry_with_noise(curr_pose, prev_pose, prev, curr, &config.odometry_noise),
+            Control::Gyro { gain, yaw_rate, dt } =>
+                log_motion_model_gyro_with_noise(curr_pose, prev_pose, gain, *yaw_rate, *dt, &config.gyro_noise),
+            Control::OdometryGyro { prev, curr, yaw_rate, dt } =>
+                log_motion_model_odometry_gyro_with_noise(curr_pose, prev_pose, prev, curr, *yaw_rate, *dt, &config.gyro_noise),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct ParticleFilter {
-    simulation: bool,
-    timer: Timer,
-    n_particles: usize,
+    config: FastSlamConfig,
+    inputs: ControlInputs,
     particles: Vec<Particle>,
-    pub best_particle: Particle
+    pub best_particle: Particle,
+    pub estimate: PoseWithCovariance, // weighted mean pose and covariance of the particles
+    pub neff: f64 // effective number of particles of the last cycle, before resampling
 }
 
 impl Default for ParticleFilter {
     fn default() -> ParticleFilter {
-        let n_particles: usize = 25;
+        ParticleFilter::new(FastSlamConfig::default()).expect("the default configuration is valid")
+    }
+}
+
+impl ParticleFilter {
+    /// a filter with all particles at the origin, if the configuration is valid
+    pub fn new(config: FastSlamConfig) -> Result<ParticleFilter, ConfigError> {
+        config.validate()?;
+
+        let n_particles = config.n_particles;
         let mut particles: Vec<Particle> = vec![];
 
         // initialize particle list
         let init_gridmap = GridMap::default();
         let init_pose = Pose::default();
-        let init_particle = Particle::new(init_pose.clone(), 1.0, init_gridmap.clone());
+        let init_particle = Particle::new(init_pose.clone(), 1.0 / n_particles as f64, init_gridmap.clone());
         for _ in 0..n_particles {
             particles.push(init_particle.clone());
         }
 
         assert_eq!(particles.len(), n_particles);
 
-        ParticleFilter {
-            simulation: true,
-            timer: Timer::init_time(),
-            n_particles,
+        Ok(ParticleFilter {
+            config,
+            inputs: ControlInputs::default(),
             particles,
-            best_particle: init_particle
-        }
+            best_particle: init_particle,
+            estimate: PoseWithCovariance::default(),
+            neff: n_particles as f64
+        })
+    }
+
+    pub fn config(&self) -> &FastSlamConfig {
+        &self.config
     }
 }
 
@@ -53,14 +170,74 @@ impl ParticleFilter {
     /// particles: S_t-1 - the sample set of the previous step
     /// scan: z_t - the most recent laser scan
     /// gain: u_t-1 - the most recent gain, applied in the previous step
+    ///
+    /// The time since the previous cycle is the fixed dt of the configuration or, without
+    /// one, measured with the wall clock. Use cycle_stamped for recorded data.
     pub fn cycle(&mut self, scan: &Scan, gain: &Twist) {
+        self.cycle_with_gyro(scan, gain, None);
+    }
 
-        let dt = if self.simulation {
-            1.0 // 1.0s runs nicely with the simulator
-        } else {
-            self.timer.get_dt()
-        };
+    /// cycle with the yaw rate [rad/s] of a gyro, if there is one: the robot turns as
+    /// measured by the gyro and only the translation of the gain is used. The wheels drift
+    /// in heading much faster than a gyro, e.g. when they slip while turning
+    pub fn cycle_with_gyro(&mut self, scan: &Scan, gain: &Twist, yaw_rate: Option<f64>) {
+        let dt = self.inputs.cycle_dt(&self.config);
+        self.update(scan, &Control::from_gain(gain, yaw_rate, dt));
+    }
+
+    /// cycle with wheel odometry as the control input: the robot moved from the pose of the
+    /// previous reading to the pose of this one (not at all for the first reading)
+    pub fn cycle_odometry(&mut self, scan: &Scan, odometry: &Odometry) {
+        let control = self.inputs.odometry(odometry, None, 0.0);
+        self.update(scan, &control);
+    }
+
+    /// cycle_odometry with the yaw rate [rad/s] of a gyro, if there is one: the robot moves
+    /// the distance between the odometry readings and turns as measured by the gyro, like the
+    /// gain in cycle_with_gyro
+    pub fn cycle_odometry_with_gyro(&mut self, scan: &Scan, odometry: &Odometry, yaw_rate: Option<f64>) {
+        let dt = self.inputs.cycle_dt(&self.config);
+        let control = self.inputs.odometry(odometry, yaw_rate, dt);
+        self.update(scan, &control);
+    }
+
+    /// cycle with timestamped inputs, dt is the time between the scan and the previous one
+    /// (0.0 for the first scan). Scans that are not newer than the previous one and gains
+    /// further than max_gain_age from their scan are rejected and leave the filter unchanged.
+    /// Returns dt
+    pub fn cycle_stamped(&mut self, scan: &Stamped<Scan>, gain: &Stamped<Twist>) -> Result<f64, InputError> {
+        self.cycle_with_gyro_stamped(scan, gain, None)
+    }
+
+    /// cycle_odometry with timestamped inputs, the odometry reading is checked against its
+    /// scan like the gain in cycle_stamped. Returns the time since the previous scan
+    pub fn cycle_odometry_stamped(&mut self, scan: &Stamped<Scan>, odometry: &Stamped<Odometry>) -> Result<f64, InputError> {
+        self.cycle_odometry_with_gyro_stamped(scan, odometry, None)
+    }
 
+    /// cycle_with_gyro with timestamped inputs, the gain and the yaw rate are checked against
+    /// their scan like the gain in cycle_stamped. Returns the time since the previous scan
+    pub fn cycle_with_gyro_stamped(&mut self, scan: &Stamped<Scan>, gain: &Stamped<Twist>, yaw_rate: Option<&Stamped<f64>>) -> Result<f64, InputError> {
+        let inputs: Vec<Timestamp> = std::iter::once(gain.timestamp).chain(yaw_rate.map(|y| y.timestamp)).collect();
+        let dt = self.inputs.stamped_dt(scan.timestamp, &inputs, &self.config)?;
+        self.update(&scan.data, &Control::from_gain(&gain.data, yaw_rate.map(|y| y.data), dt));
+        Ok(dt)
+    }
+
+    /// cycle_odometry_with_gyro with timestamped inputs, the odometry reading and the yaw
+    /// rate are checked against their scan like the gain in cycle_stamped. Returns the time
+    /// since the previous scan
+    pub fn cycle_odometry_with_gyro_stamped(&mut self, scan: &Stamped<Scan>, odometry: &Stamped<Odometry>, yaw_rate: Option<&Stamped<f64>>) -> Result<f64, InputError> {
+        let inputs: Vec<Timestamp> = std::iter::once(odometry.timestamp).chain(yaw_rate.map(|y| y.timestamp)).collect();
+        let dt = self.inputs.stamped_dt(scan.timestamp, &inputs, &self.config)?;
+        let control = self.inputs.odometry(&odometry.data, yaw_rate.map(|y| y.data), dt);
+        self.update(&scan.data, &control);
+        Ok(dt)
+    }
+
+    /// one step of the filter
+    fn update(&mut self, scan: &Scan, control: &Control) {
+        let config = &self.config;
 
         // This is an iterator-like chain that potentially executes in parallel
         // we iterate over all particles in the filter and do the following
@@ -71,56 +248,67 @@ impl ParticleFilter {
                 // step 1.)
                 // initial guess of pose x'_ based on motion model
                 // let motion_model_pose = Self::sample_motion_model_velocity(&p.pose, &gain, dt);
-                let motion_model_pose = Self::drive(&p.pose, &gain, dt);
+                let motion_model_pose = control.drive(&p.pose);
 
                 // step 2.)
                 // scan-matching using the initial guess x'_t and the latest scan m_t
                 // to compute a pose estimate x*_t
-                let curr_pointcloud = scan.to_pointcloud(&motion_model_pose);
+                let curr_pointcloud = scan.to_pointcloud(&Pose::default());
                 let scan_match_pose = if p.prev_pointcloud.size() == 0 {
                     motion_model_pose
                 } else {
-                    let pose_correction = icp(&curr_pointcloud, &p.prev_pointcloud, 20, 0.00000000001);
-                    p.prev_pose_correction = pose_correction;
-                    // p.pose + pose_correction
-                    motion_model_pose
+                    let scan_match = icp_with_guess(&curr_pointcloud, &p.prev_pointcloud, &motion_model_pose, &config.icp);
+
+                    // fall back to the motion model if the scans could not be aligned
+                    if Self::accept_scan_match(&scan_match, config) {
+                        p.prev_pose_correction = scan_match.correction;
+                        Pose::new(scan_match.transform.position, Self::wrap_heading(scan_match.transform.heading))
+                    } else {
+                        p.prev_pose_correction = Pose::default();
+                        motion_model_pose
+                    }
                 };
 
                 // println!("scan match pose: {:?}", scan_match_pose);
 
                 // step 3.)
                 // sample points around the pose x*_t
-                let translational_range = &gain.velocity.x * dt * 0.05;
-                let angular_range = &gain.angular * dt * 0.05;
+                let (distance, rotation) = control.extent();
+                let translational_range = distance * config.sampling_range;
+                let angular_range = rotation * config.sampling_range;
                 // println!("trans range: {}", translational_range);
                 // println!("ang range: {}", angular_range);
                 let std_dev_sampling = Pose::new(Point::new(translational_range, translational_range), angular_range);
 
-                let pose_samples: Vec<Pose> = Self::sample_distribution(&scan_match_pose, std_dev_sampling, 50);
+                let pose_samples: Vec<Pose> = Self::sample_distribution(&scan_match_pose, std_dev_sampling, config.n_pose_samples);
 
                 // step 4.)
                 // compute new pose x_t drawn from the gaussian approximation of the
                 // improved proposal distribution
-                let (improved_pose, eta) = Self::improved_proposal(
+                let (improved_pose, log_eta) = Self::improved_proposal(
                     &pose_samples,
                     &scan_match_pose,
                     &p.pose,
                     &p.gridmap,
                     &scan,
-                    &gain,
-                    dt
+                    control,
+                    config
                 );
 
                 // step 5 & 6.)
                 // update the importance weights, pose and map for particle
-                p.weight = p.weight * eta;
+                p.log_weight += log_eta;
                 p.gridmap.update(&improved_pose, scan); // updating the map according to the drawn pose x_t and the observation z_t
                 p.pose = improved_pose;
-                p.prev_pointcloud = curr_pointcloud
+                p.prev_pointcloud = scan.to_pointcloud(&improved_pose)
             });
 
+        // normalize the weights, so they sum to 1.0 and stay in range
+        Self::normalize_weights(&mut self.particles);
+
         // Get highest weight particle before resampling
         self.best_particle = Self::get_highest_weight_particle(&self.particles);
+        self.estimate = Self::compute_estimate(&self.particles);
 
         println!("best pose: {:?}", self.best_particle.pose);
         println!("best weight: {:?}", self.best_particle.weight);
@@ -130,21 +318,88 @@ impl ParticleFilter {
         // compute efficient number of particles and resample based on
         // computed weights if Neff drops below threshold
         let Neff = Self::compute_neff(&self.particles);
+        self.neff = Neff;
 
         // TODO: do not perform resampling if robot hasn't moved since last step
         // could check if gain = 0.0
-        if Neff < (*&self.particles.len() as f64) / 2.0 {
+        if let Some(kld) = &self.config.kld {
+            // KLD-sampling resamples every cycle, so the particle count follows the spread
+            // of the posterior
+            self.particles = kld_resample(&self.particles, kld, &mut rand::thread_rng());
+        } else if Neff < self.config.resampling_threshold * self.particles.len() as f64 {
             println!("RESAMPLE!!");
-            // let resampled_particles = low_variance_sampler(&self.particles);
-            let resampled_particles = resampler(&self.particles);
+            let resampled_particles = self.config.resampling.resample(&self.particles);
             self.particles = resampled_particles;
         }
     }
 
+    /// decide whether a scan matching correction can be trusted: icp must have converged to
+    /// a correction within the icp gate of the configuration that leaves the scans closer
+    /// together. The correction is in the frame of the guess (the motion model pose), so it
+    /// does not grow with the distance of the robot from the origin
+    pub fn accept_scan_match(scan_match: &IcpResult, config: &FastSlamConfig) -> bool {
+        let gate = &config.icp_gate;
+        let translation = scan_match.correction.position.to_vec().length();
+        let rotation = Self::wrap_heading(scan_match.correction.heading).abs();
+
+        scan_match.converged
+            && translation <= gate.max_translation
+            && rotation <= gate.max_rotation
+            && scan_match.final_residual <= gate.max_residual
+            && scan_match.final_residual <= scan_match.initial_residual
+    }
+
+    /// weighted mean pose and covariance of the particles
+    pub fn compute_estimate(particles: &[Particle]) -> PoseWithCovariance {
+        let poses: Vec<Pose> = particles.iter().map(|p| p.pose).collect();
+        let weights: Vec<f64> = particles.iter().map(|p| p.weight).collect();
+        PoseWithCovariance::from_weighted_poses(&poses, &weights)
+    }
+
+    /// the estimate moved on by the gain for dt, with the covariance grown by the motion
+    /// noise of the configuration, e.g. for the pose between two scans
+    pub fn predict(&self, gain: &Twist, dt: f64) -> PoseWithCovariance {
+        Self::motion_model_velocity_with_covariance_with_noise(&self.estimate, gain, dt, &self.config.motion_noise)
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// current number of particles, it changes with KLD-sampling
+    pub fn n_particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// normalized weights of the particles, they sum to 1.0
+    pub fn weights(&self) -> Vec<f64> {
+        self.particles.iter().map(|p| p.weight).collect()
+    }
+
+    /// Normalize the weights of the particles from their log weights, so they sum to 1.0.
+    /// The log weights are shifted by the same amount and stay close to zero. If no particle
+    /// has a finite log weight, all get the same weight.
+    pub fn normalize_weights(particles: &mut [Particle]) {
+        let log_weights: Vec<f64> = particles.iter().map(|p| p.log_weight).collect();
+        let log_sum = log_sum_exp(&log_weights);
+
+        if log_sum.is_finite() {
+            for p in particles.iter_mut() {
+                p.log_weight -= log_sum;
+                p.weight = p.log_weight.exp();
+            }
+        } else {
+            let weight = 1.0 / particles.len() as f64;
+            particles.iter_mut().for_each(|p| p.set_weight(weight));
+        }
+    }
+
+    /// effective number of particles 1 / sum(w^2) of the normalized weights w
     pub fn compute_neff(particles: &Vec<Particle>) -> f64 {
+        let sum: f64 = particles.iter().map(|p| p.weight).sum();
         let squared_sum = particles
             .iter()
-            .map(|p| p.weight)
+            .map(|p| p.weight / sum)
             .fold(0.0, |sum, w| sum + w.powi(2));
 
         return 1.0 / squared_sum
@@ -186,63 +441,53 @@ impl ParticleFilter {
         samples
     }
 
+    /// Gaussian approximation N(mu, sigma) of the improved proposal distribution from the
+    /// poses x_j sampled around the scan-matched pose, each weighted with
+    /// p(z_t | x_j, m_t-1) * p(x_j | x_t-1, u_t). Returns a pose x_t drawn from it and the
+    /// logarithm of the normalization factor eta, the sum of the sample weights, by which the
+    /// weight of the particle is multiplied.
     fn improved_proposal(
         sampled_poses: &Vec<Pose>,
         curr_particle_pose: &Pose,
         prev_particle_pose: &Pose,
         prev_gridmap: &GridMap,
         scan: &Scan,
-        gain: &Twist,
-        dt: f64
+        control: &Control,
+        config: &FastSlamConfig
     ) -> (Pose, f64) {
-        // types are defined at compile-time, so this should not cause overhead
-        struct PoseWithDistribution {
-            pub pose: Pose,
-            pub p_z: f64,
-            pub p_x: f64
-        }
-
-        let mut mu = Pose::default(); // (0,0,0)
-        let mut eta = 0.0;
-
-        let mut poses_with_distribution: Vec<PoseWithDistribution> = vec![];
-
-        // TODO: Run this in parallel
-        sampled_poses.into_iter().for_each(|x_j: &Pose| {
-            let p_x = motion_model_velocity(&x_j, prev_particle_pose, gain, dt);
-            let p_z = 1.0; //likelihood_field_range_finder_model(scan, &x_j, prev_gridmap);
-
-            mu += *x_j * p_z * p_x;
-            eta += p_z * p_x;
-
-
-            // to avoid re-computing, keep a cache
-            poses_with_distribution.push(PoseWithDistribution { pose: *x_j, p_z, p_x });
-        });
-
-        // get final estimate of mean pose by normalizing the mean using normalization factor
-        if eta > 0.0 {
-            mu = mu / eta;
+        // log(p_z * p_x) of each sample, the products under- and overflow
+        let log_p: Vec<f64> = sampled_poses
+            .iter()
+            .map(|x_j: &Pose| {
+                let log_p_x = control.log_probability(x_j, prev_particle_pose, config);
+                let log_p_z = log_likelihood_field_range_finder_model_with_params(scan, x_j, prev_gridmap, &config.likelihood_field);
+                log_p_x + log_p_z
+            })
+            .collect();
+
+        // the samples could not be evaluated (e.g. the motion model is undefined), keep the
+        // scan-matched pose and leave the particle weight unchanged
+        let log_eta = log_sum_exp(&log_p);
+        if !log_eta.is_finite() || log_p.iter().any(|l| l.is_nan()) {
+            return (*curr_particle_pose, 0.0)
         }
 
-        let mut sigma = Pose::default(); // (0,0,0)
+        // weights of the samples normalized by eta, they sum to 1.0
+        let weights: Vec<f64> = log_p.iter().map(|l| (l - log_eta).exp()).collect();
 
-        poses_with_distribution.into_iter().for_each(|pwd: PoseWithDistribution| {
-            let (x_j, p_z, p_x) = (pwd.pose, pwd.p_z, pwd.p_x);
-            sigma = sigma + (x_j - mu) * (x_j - mu) * p_z * p_x;
-        });
-
-        // get final estimate of sigma (pose variance)
-        if eta > 0.0 {
-            sigma = sigma / eta;
-        }
+        // mu and sigma of the samples, with the circular mean and the wrapped deviations of
+        // the headings, so samples on both sides of +-PI do not average out to 0.0
+        let proposal = PoseWithCovariance::from_weighted_poses(sampled_poses, &weights);
+        let mu = proposal.pose;
+        let sigma = proposal.covariance;
+        let std_dev = Pose::new(Point::new(sigma[(0, 0)].sqrt(), sigma[(1, 1)].sqrt()), sigma[(2, 2)].sqrt());
 
         // sample final particle pose
-        let improved_pose = match Self::sample_distribution(&mu, sigma.sqrt(), 1).first() {
+        let improved_pose = match Self::sample_distribution(&mu, std_dev, 1).first() {
             None => panic!("could not sample new pose!"),
-            Some(p) => *p
+            Some(p) => Pose::new(p.position, Self::wrap_heading(p.heading))
         };
 
-        return (improved_pose, eta)
+        (improved_pose, log_eta)
     }
 }
\ No newline at end of file
@@ -1,8 +1,33 @@
 use crate::odometry::{Pose, Twist};
 use crate::sensor::laserscanner::{Scan, Measurement};
 use crate::gridmap::grid_map::GridMap;
-use crate::math::scalar::PI;
+use crate::gridmap::distance_field::DEFAULT_MAX_DISTANCE;
+use crate::math::scalar::{wrap_angle, PI};
 use crate::geometry::Point;
+use crate::odometry::motion_model::{odometry_delta, DEFAULT_ALPHA, DEFAULT_GYRO_ALPHA, DEFAULT_ODOMETRY_ALPHA};
+
+/// default probability that a beam ends close to an obstacle of the map rather than at random
+pub const DEFAULT_Z_HIT: f64 = 0.98;
+
+/// Intrinsic parameters of the likelihood field range finder model
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LikelihoodFieldParams {
+    pub z_hit: f64, // weight of the measurement noise, 1.0 - z_hit is the weight of random measurements
+    pub z_max: f64, // [m] maximum sensor range, readings at or beyond it are ignored
+    pub sigma_hit: f64, // [m] standard deviation of the distance of a beam end to the nearest obstacle
+    pub max_distance: f64, // [m] distances of beam ends to the nearest obstacle are saturated at this value
+}
+
+impl Default for LikelihoodFieldParams {
+    fn default() -> LikelihoodFieldParams {
+        LikelihoodFieldParams {
+            z_hit: DEFAULT_Z_HIT,
+            z_max: 30.0,
+            sigma_hit: 0.001,
+            max_distance: DEFAULT_MAX_DISTANCE,
+        }
+    }
+}
 
 /// Computes the motion model probability of a sampled pose.
 /// This is the probability p(x_t | x_t-1, u_t) of being at pose x_t after executing
@@ -20,11 +45,17 @@ use crate::geometry::Point;
 /// Returns:
 ///     p: probability (0.0 - 1.0+) does not need to be between 0-1
 pub fn motion_model_velocity(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, dt: f64) -> f64 {
-    // motion noise params
-    // alpha_1:2: angular error
-    // alpha_3:4: translational error
-    let alpha = [0.01, 0.01, 0.01, 0.01, 0.01, 0.01]; // these values can be tuned
+    log_motion_model_velocity(curr_sampled_pose, prev_particle_pose, gain, dt).exp()
+}
+
+/// natural logarithm of motion_model_velocity, which does not underflow for unlikely poses
+pub fn log_motion_model_velocity(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, dt: f64) -> f64 {
+    log_motion_model_velocity_with_noise(curr_sampled_pose, prev_particle_pose, gain, dt, &DEFAULT_ALPHA)
+}
 
+/// log_motion_model_velocity with the motion noise params alpha_1:6 of the velocity motion
+/// model
+pub fn log_motion_model_velocity_with_noise(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, dt: f64, alpha: &[f64; 6]) -> f64 {
     let v = gain.velocity.x;
     let omega = gain.angular;
 
@@ -51,10 +82,121 @@ pub fn motion_model_velocity(curr_sampled_pose: &Pose, prev_particle_pose: &Pose
     let omega_hat = delta_theta / dt;
     let gamma_hat = ((theta_prime - theta) / dt) - omega_hat;
 
-    let p1 = prob_normal_distribution(v - v_hat, alpha[0]*v.powi(2) + alpha[1] * omega.powi(2));
-    let p2 = prob_normal_distribution(omega - omega_hat, alpha[2]*v.powi(2) + alpha[3] * omega.powi(2));
-    let p3 = prob_normal_distribution(gamma_hat, alpha[4]*v.powi(2) + alpha[5]*omega.powi(2));
-    return p1 * p2 * p3
+    let p1 = log_prob_normal_distribution(v - v_hat, alpha[0]*v.powi(2) + alpha[1] * omega.powi(2));
+    let p2 = log_prob_normal_distribution(omega - omega_hat, alpha[2]*v.powi(2) + alpha[3] * omega.powi(2));
+    let p3 = log_prob_normal_distribution(gamma_hat, alpha[4]*v.powi(2) + alpha[5]*omega.powi(2));
+    return p1 + p2 + p3
+}
+
+/// Computes the odometry motion model probability.
+/// This is the probability p(x_t | x_t-1, u_t) of being at pose x_t after the robot moved
+/// from x_t-1, where the control u_t is the motion between two odometry readings
+///
+/// More info:
+///  - p.134 Table 5.5 in probabilistic robotics, Sebastian Thrun et al.
+///
+/// Input:
+///     curr_sampled_pose: the sampled pose after scan match correction
+///     prev_particle_pose: the last estimated pose for the particle
+///     prev_odom: odometry reading at the time of prev_particle_pose
+///     curr_odom: latest odometry reading
+/// Returns:
+///     p: probability (0.0 - 1.0+) does not need to be between 0-1
+pub fn motion_model_odometry(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose) -> f64 {
+    log_motion_model_odometry(curr_sampled_pose, prev_particle_pose, prev_odom, curr_odom).exp()
+}
+
+/// natural logarithm of motion_model_odometry, which does not underflow for unlikely poses
+pub fn log_motion_model_odometry(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose) -> f64 {
+    log_motion_model_odometry_with_noise(curr_sampled_pose, prev_particle_pose, prev_odom, curr_odom, &DEFAULT_ODOMETRY_ALPHA)
+}
+
+/// log_motion_model_odometry with the motion noise params alpha_1:4 of the odometry motion
+/// model
+pub fn log_motion_model_odometry_with_noise(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, alpha: &[f64; 4]) -> f64 {
+    // motion reported by the odometry and motion between the particle poses
+    let (rot1, trans, rot2) = odometry_delta(prev_odom, curr_odom);
+    let (rot1_hat, trans_hat, rot2_hat) = odometry_delta(prev_particle_pose, curr_sampled_pose);
+
+    let p1 = log_prob_normal_distribution(wrap_angle(rot1 - rot1_hat), alpha[0] * rot1_hat.powi(2) + alpha[1] * trans_hat.powi(2));
+    let p2 = log_prob_normal_distribution(trans - trans_hat, alpha[2] * trans_hat.powi(2) + alpha[3] * (rot1_hat.powi(2) + rot2_hat.powi(2)));
+    let p3 = log_prob_normal_distribution(wrap_angle(rot2 - rot2_hat), alpha[0] * rot2_hat.powi(2) + alpha[1] * trans_hat.powi(2));
+    p1 + p2 + p3
+}
+
+/// Computes the gyro aided motion model probability.
+/// This is the probability p(x_t | x_t-1, u_t) of being at pose x_t after the robot drove the
+/// distance of the gain (wheels) while turning at the yaw rate (gyro) for dt, the
+/// distribution that sample_motion_model_gyro draws from
+///
+/// Input:
+///     curr_sampled_pose: the sampled pose after scan match correction
+///     prev_particle_pose: the last estimated pose for the particle
+///     gain: most recent control input, only its translation is used
+///     yaw_rate: [rad/s] yaw rate measured by the gyro
+///     dt: duration of the control gain
+/// Returns:
+///     p: probability (0.0 - 1.0+) does not need to be between 0-1
+pub fn motion_model_gyro(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64) -> f64 {
+    log_motion_model_gyro(curr_sampled_pose, prev_particle_pose, gain, yaw_rate, dt).exp()
+}
+
+/// natural logarithm of motion_model_gyro, which does not underflow for unlikely poses
+pub fn log_motion_model_gyro(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64) -> f64 {
+    log_motion_model_gyro_with_noise(curr_sampled_pose, prev_particle_pose, gain, yaw_rate, dt, &DEFAULT_GYRO_ALPHA)
+}
+
+/// log_motion_model_gyro with the motion noise params alpha_1:4 of the gyro aided motion
+/// model
+pub fn log_motion_model_gyro_with_noise(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, gain: &Twist, yaw_rate: f64, dt: f64, alpha: &[f64; 4]) -> f64 {
+    log_motion_model_arc_with_noise(curr_sampled_pose, prev_particle_pose, gain.velocity.x * dt, yaw_rate * dt, dt, alpha)
+}
+
+/// Computes the odometry and gyro aided motion model probability.
+/// This is the probability p(x_t | x_t-1, u_t) of being at pose x_t after the robot moved the
+/// distance between two odometry readings while turning at the yaw rate (gyro) for dt, the
+/// distribution that sample_motion_model_odometry_gyro draws from
+///
+/// Input:
+///     curr_sampled_pose: the sampled pose after scan match correction
+///     prev_particle_pose: the last estimated pose for the particle
+///     prev_odom: odometry reading at the time of prev_particle_pose
+///     curr_odom: latest odometry reading, only the distance to prev_odom is used
+///     yaw_rate: [rad/s] yaw rate measured by the gyro
+///     dt: time between the odometry readings
+/// Returns:
+///     p: probability (0.0 - 1.0+) does not need to be between 0-1
+pub fn motion_model_odometry_gyro(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64) -> f64 {
+    log_motion_model_odometry_gyro(curr_sampled_pose, prev_particle_pose, prev_odom, curr_odom, yaw_rate, dt).exp()
+}
+
+/// natural logarithm of motion_model_odometry_gyro, which does not underflow for unlikely poses
+pub fn log_motion_model_odometry_gyro(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64) -> f64 {
+    log_motion_model_odometry_gyro_with_noise(curr_sampled_pose, prev_particle_pose, prev_odom, curr_odom, yaw_rate, dt, &DEFAULT_GYRO_ALPHA)
+}
+
+/// log_motion_model_odometry_gyro with the motion noise params alpha_1:4 of the gyro aided
+/// motion model
+pub fn log_motion_model_odometry_gyro_with_noise(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, prev_odom: &Pose, curr_odom: &Pose, yaw_rate: f64, dt: f64, alpha: &[f64; 4]) -> f64 {
+    let (_, trans, _) = odometry_delta(prev_odom, curr_odom);
+    log_motion_model_arc_with_noise(curr_sampled_pose, prev_particle_pose, trans, yaw_rate * dt, dt, alpha)
+}
+
+/// log p(x_t | x_t-1) of moving trans [m] while turning rot [rad] integrated over dt, with
+/// the noise of the gyro aided motion model
+fn log_motion_model_arc_with_noise(curr_sampled_pose: &Pose, prev_particle_pose: &Pose, trans: f64, rot: f64, dt: f64, alpha: &[f64; 4]) -> f64 {
+    // rotation between the particle poses and where the wheels would have moved the robot
+    // with that rotation
+    let rot_hat = wrap_angle(curr_sampled_pose.heading - prev_particle_pose.heading);
+    let heading = prev_particle_pose.heading + 0.5 * rot_hat;
+    let dx = curr_sampled_pose.position.x - (prev_particle_pose.position.x + trans * heading.cos());
+    let dy = curr_sampled_pose.position.y - (prev_particle_pose.position.y + trans * heading.sin());
+
+    let var_position = alpha[0] * trans.powi(2) + alpha[1] * rot.powi(2);
+    let p1 = log_prob_normal_distribution(wrap_angle(rot - rot_hat), alpha[2] * dt.abs() + alpha[3] * rot.powi(2));
+    let p2 = log_prob_normal_distribution(dx, var_position);
+    let p3 = log_prob_normal_distribution(dy, var_position);
+    p1 + p2 + p3
 }
 
 /// Computes the measurement model probability
@@ -77,12 +219,22 @@ pub fn motion_model_velocity(curr_sampled_pose: &Pose, prev_particle_pose: &Pose
 /// Returns:
 ///     q: probability (0.0 - 1.0+) does not need to be between 0-1
 pub fn likelihood_field_range_finder_model(scan: &Scan, curr_sampled_pose: &Pose, prev_gridmap: &GridMap) -> f64 {
-    // intrinsic parameters
-    let z_hit = 0.98; // range: (0.6-0.9)
-    let z_max = 30.0; // maximum allowed sensor value
+    log_likelihood_field_range_finder_model(scan, curr_sampled_pose, prev_gridmap).exp()
+}
+
+/// natural logarithm of likelihood_field_range_finder_model. The probability is a product
+/// over all beams and under- or overflows for a few hundred beams, its logarithm does not
+pub fn log_likelihood_field_range_finder_model(scan: &Scan, curr_sampled_pose: &Pose, prev_gridmap: &GridMap) -> f64 {
+    log_likelihood_field_range_finder_model_with_params(scan, curr_sampled_pose, prev_gridmap, &LikelihoodFieldParams::default())
+}
+
+/// log_likelihood_field_range_finder_model with the given intrinsic parameters
+pub fn log_likelihood_field_range_finder_model_with_params(scan: &Scan, curr_sampled_pose: &Pose, prev_gridmap: &GridMap, params: &LikelihoodFieldParams) -> f64 {
+    let z_hit = params.z_hit;
+    let z_max = params.z_max;
     let z_rand = 1.0 - z_hit; // random distance noise
-    let sigma_hit: f64 = 0.001; // [m] used in the z_hit part of the model.
-    let mut q = 1.0;
+    let sigma_hit = params.sigma_hit;
+    let mut log_q = 0.0;
 
     // filter out readings equal to or larger than max laser range
     let filtered_scan: Scan = scan
@@ -91,34 +243,19 @@ pub fn likelihood_field_range_finder_model(scan: &Scan, curr_sampled_pose: &Pose
         .cloned()
         .collect();
 
-    // collect all occupied cells in gridmap
-    let occupied_cells = prev_gridmap.get_all_occupied_cells();
+    // distance transform of the map, computed once and shared by all pose samples
+    let distance_field = prev_gridmap.distance_field_up_to(params.max_distance);
 
     // the Euclidean distance between the measurement coordinates (x_z, y_z)
     // and the nearest object in the map m
     filtered_scan.to_pointcloud(curr_sampled_pose)
         .iter()
         .for_each(|z_world: &Point| {
-        let mut min_dist = 9999.0;
-
-        let z_map = match prev_gridmap.world_to_map(*z_world) {
-            Some((x,y)) => Point::new(x as f64, y as f64),
-            _ => panic!("Could not convert point to map coordinates!")
-        };
-
-        // is the occupied cell in grid map coordinates??
-        occupied_cells.iter().for_each(|occupied_cell| {
-            // distance to the nearest obstacle in x-y-space is computed
-            let dist = occupied_cell.to_point_vec(z_map).length();
-            if dist < min_dist {
-                min_dist = dist
-            }
-        });
-
-        q = q * (z_hit * prob_normal_distribution(min_dist, sigma_hit.powi(2)) + z_rand / z_max)
+        let min_dist = distance_field.distance_at(*z_world);
+        log_q += (z_hit * prob_normal_distribution(min_dist, sigma_hit.powi(2)) + z_rand / z_max).ln()
     });
 
-    return q
+    return log_q
 }
 
 /// Computes the probability of its argument 'a' under a zero-centered (x - mu = x - 0.0 = x)
@@ -135,4 +272,9 @@ pub fn likelihood_field_range_finder_model(scan: &Scan, curr_sampled_pose: &Pose
 ///     p: probability (0.0 - 1.0+) does not need to be between 0-1
 fn prob_normal_distribution(a: f64, b_squared: f64) -> f64 {
     return (1.0 / (2.0 * PI * b_squared).sqrt()) * (-0.5 * a.powi(2) / b_squared).exp();
-}
\ No newline at end of file
+}
+
+/// natural logarithm of prob_normal_distribution
+fn log_prob_normal_distribution(a: f64, b_squared: f64) -> f64 {
+    -0.5 * (2.0 * PI * b_squared).ln() - 0.5 * a.powi(2) / b_squared
+}
@@ -1,7 +1,5 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 use crate::particlefilter::particle::Particle;
-use crate::odometry::Pose;
-use crate::gridmap::grid_map::GridMap;
 
 /// Resampling that refocuses the particle set to regions in state space with
 /// high posterior probability (statistical probability that a hypothesis is true calculated in the light of relevant observations)
@@ -13,96 +11,189 @@ use crate::gridmap::grid_map::GridMap;
 /// - Is dangerous, since important samples might get lost ("particle depletion")
 /// - Only makes sense if particle weights differ significantly (high variance)
 ///
+/// All schemes are unbiased: on average every particle is drawn n * w times, where w is its
+/// normalized weight. They differ in the variance of these counts. The weights do not have to
+/// be normalized, if they are all zero (or not finite) every particle is equally likely.
+///
 /// More info:
 ///  - p.110 Table 4.4 in probabilistic robotics, Sebastian Thrun et al.
-///
-/// Transforms a particle set of M particles into another particle set of the same size
-///
-/// Input:
-///     particles: current vector of particles
-/// Returns:
-///     particles: new vector of resampled particles
-#[allow(non_snake_case)]
-pub fn low_variance_sampler(particles: &Vec<Particle>) -> Vec<Particle> {
-
-    assert!(particles.len() > 0);
-    let mut resampled_particles: Vec<Particle> = vec![];
-    println!("particle len: {}", particles.len());
+///  - Comparison of Resampling Schemes for Particle Filtering, R. Douc, O. Cappé and E. Moulines
+pub trait Resampler {
+    /// Indices of the n particles drawn according to the weights, in ascending order. Nothing
+    /// is drawn if there are no weights
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize>;
+
+    /// Transforms a particle set of M particles into another particle set of the same size,
+    /// all with weight 1 / M
+    #[allow(non_snake_case)]
+    fn resample(&self, particles: &[Particle]) -> Vec<Particle> {
+        let weights: Vec<f64> = particles.iter().map(|p| p.weight).collect();
+        let M_inv = 1.0 / particles.len() as f64;
+
+        self.indices(&weights, particles.len(), &mut rand::thread_rng())
+            .into_iter()
+            .map(|i| {
+                let mut p = particles[i].clone();
+                p.set_weight(M_inv);
+                p
+            })
+            .collect()
+    }
+}
 
-    let M = particles.len() as i64;
-    let M_inv = 1.0 / (M as f64);
-    let r: f64 = rand::thread_rng().gen_range(0.0..M_inv);
-    let mut c: f64 = particles.first().unwrap().weight;
-    let mut i: usize = 0;
+/// Draws every particle independently from the weights, the highest variance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Multinomial;
 
-    for m in 0..M {
-        let U = r + (m as f64) * M_inv;
+/// Draws one particle from each of n equal strata of the cumulative weights
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stratified;
 
-        while U > c {
-            i += 1;
+/// Like Stratified, but with the same offset in every stratum: a particle with weight w is
+/// drawn floor(n * w) or ceil(n * w) times (the low variance sampler)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Systematic;
 
-            if i as i64 >= M {
-                panic!("trying to access particle that is out of bounds")
-            }
+/// Keeps floor(n * w) copies of every particle and draws the remaining particles from the
+/// residual weights
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Residual;
 
-            let w_i = particles.get(i).unwrap().weight;
-            c += w_i;
+impl Resampler for Multinomial {
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        if weights.is_empty() {
+            return vec![]
         }
-        let mut p = particles.get(i).unwrap().clone();
-        p.weight = M_inv;
-        resampled_particles.push(p);
+
+        let cumulative = cumulative_weights(weights);
+        let mut indices: Vec<usize> = (0..n)
+            .map(|_| select(&cumulative, rng.gen_range(0.0..1.0)))
+            .collect();
+        indices.sort_unstable();
+        indices
     }
+}
 
-    // assert_eq!(M, resampled_particles.len() as i64);
+impl Resampler for Stratified {
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        if weights.is_empty() {
+            return vec![]
+        }
 
-    return resampled_particles
+        let cumulative = cumulative_weights(weights);
+        (0..n)
+            .map(|m| select(&cumulative, (m as f64 + rng.gen_range(0.0..1.0)) / n as f64))
+            .collect()
+    }
 }
 
-#[allow(non_snake_case)]
-pub fn resampler(particles: &Vec<Particle>) -> Vec<Particle> {
-    let mut resampled_particles: Vec<Particle> = particles.clone();
-    let M = particles.len() as i64;
-    let M_inv = 1.0 / (M as f64);
-    let r: f64 = rand::thread_rng().gen_range(0.0..M_inv);
+impl Resampler for Systematic {
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        if weights.is_empty() {
+            return vec![]
+        }
 
-    // instantiate container for cumulative sum of weights and particles poses
-    let mut cum_sum: Vec<f64> = vec![];
+        let cumulative = cumulative_weights(weights);
+        let r: f64 = rng.gen_range(0.0..1.0);
+        (0..n)
+            .map(|m| select(&cumulative, (m as f64 + r) / n as f64))
+            .collect()
+    }
+}
 
-    // initialize the sum of weights to 0
-    let mut sum = 0.0;
+impl Resampler for Residual {
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        if weights.is_empty() {
+            return vec![]
+        }
 
-    // iterate over all particles, accumulate weights and append poses
-    for particle in particles {
-        sum += particle.weight;
-        cum_sum.push(sum);
-    }
+        // deterministic copies
+        let expected: Vec<f64> = normalized_weights(weights).iter().map(|w| w * n as f64).collect();
+        let mut indices: Vec<usize> = vec![];
+        for (i, e) in expected.iter().enumerate() {
+            indices.resize(indices.len() + e.floor() as usize, i);
+        }
 
-    // instantiate container
-    let mut particle_ids: Vec<usize> = vec![];
+        // the remaining particles from what is left of the weights
+        let remaining = n.saturating_sub(indices.len());
+        let residuals: Vec<f64> = expected.iter().map(|e| e - e.floor()).collect();
+        indices.extend(Multinomial.indices(&residuals, remaining, rng));
+        indices.sort_unstable();
+        indices
+    }
+}
 
-    // iterate over all particles
-    for (m, _) in particles.iter().enumerate() {
-        let ref_sum = r + (m as f64) * M_inv;
+/// The resampling schemes the particle filter can be configured with
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ResamplingScheme {
+    Multinomial,
+    Stratified,
+    #[default]
+    Systematic,
+    Residual,
+}
 
-        // select index of first particle for which cumulative sum exceeds reference threshold
-        for (i, c) in cum_sum.iter().enumerate() {
-            if c >= &ref_sum {
-                let particle_id = i;
-                particle_ids.push(particle_id);
-                break;
-            }
+impl Resampler for ResamplingScheme {
+    fn indices(&self, weights: &[f64], n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        match *self {
+            ResamplingScheme::Multinomial => Multinomial.indices(weights, n, rng),
+            ResamplingScheme::Stratified => Stratified.indices(weights, n, rng),
+            ResamplingScheme::Systematic => Systematic.indices(weights, n, rng),
+            ResamplingScheme::Residual => Residual.indices(weights, n, rng),
         }
     }
+}
 
-    // resample particles based on selected ids
-    for (m, mut p) in resampled_particles.iter_mut().enumerate() {
+/// weights scaled to sum to 1.0, negative and NaN weights count as 0.0. If no weight is left
+/// all are the same
+pub fn normalized_weights(weights: &[f64]) -> Vec<f64> {
+    let weights: Vec<f64> = weights.iter().map(|&w| if w > 0.0 { w } else { 0.0 }).collect();
+    let sum: f64 = weights.iter().sum();
 
-        // get id of sampled particle and assign corresponding pose
-        let particle_id = particle_ids[m];
-        let sampled_p = particles.get(particle_id).unwrap().clone();
-        *p = sampled_p;
-        p.weight = M_inv;
+    if sum > 0.0 && sum.is_finite() {
+        weights.iter().map(|w| w / sum).collect()
+    } else {
+        vec![1.0 / weights.len() as f64; weights.len()]
     }
+}
+
+/// cumulative sum of the normalized weights, the last entry is exactly 1.0
+pub fn cumulative_weights(weights: &[f64]) -> Vec<f64> {
+    let mut sum = 0.0;
+    let mut cumulative: Vec<f64> = normalized_weights(weights)
+        .iter()
+        .map(|w| {
+            sum += w;
+            sum
+        })
+        .collect();
+
+    // rounding could leave the last entries below 1.0, they belong to the last particle
+    // with a weight
+    if let Some(last) = cumulative.len().checked_sub(1) {
+        let first_of_last = cumulative.iter().position(|&c| c >= cumulative[last]).unwrap();
+        cumulative[first_of_last..].iter_mut().for_each(|c| *c = 1.0);
+    }
+    cumulative
+}
 
-    resampled_particles
-}
\ No newline at end of file
+/// index of the particle whose interval [c_i-1, c_i) of the cumulative weights contains u,
+/// particles with zero weight have an empty interval and are never selected
+pub fn select(cumulative: &[f64], u: f64) -> usize {
+    cumulative.partition_point(|&c| c <= u).min(cumulative.len() - 1)
+}
+
+/// The low variance sampler, systematic resampling
+///
+/// Input:
+///     particles: current vector of particles
+/// Returns:
+///     particles: new vector of resampled particles
+pub fn low_variance_sampler(particles: &[Particle]) -> Vec<Particle> {
+    Systematic.resample(particles)
+}
+
+/// systematic resampling, the same as low_variance_sampler
+pub fn resampler(particles: &[Particle]) -> Vec<Particle> {
+    Systematic.resample(particles)
+}
@@ -3,6 +3,7 @@ use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator};
 use rayon::slice;
 use crate::geometry::point::Point;
 use crate::math::scalar::Scalar;
+use crate::odometry::Pose;
 
 
 #[derive(Debug, Clone)]
@@ -62,4 +63,12 @@ impl PointCloud {
         let y_avg: Scalar = self.iter().map(|p: &Point| p.y).sum::<Scalar>() / self.size() as Scalar;
         Point::new(x_avg, y_avg)
     }
+
+    /// apply the rigid transform (rotation by transform.heading about the origin followed
+    /// by a translation of transform.position) to every point
+    pub fn transform(&self, transform: &Pose) -> PointCloud {
+        let (c, s) = (transform.heading.cos(), transform.heading.sin());
+        let t = transform.position;
+        PointCloud::new(self.iter().map(|p| Point::new(c * p.x - s * p.y + t.x, s * p.x + c * p.y + t.y)).collect())
+    }
 }
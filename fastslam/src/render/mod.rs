@@ -5,7 +5,6 @@ use opengl_graphics::GlGraphics;
 use graphics::math::Matrix2d;
 use graphics;
 use graphics::{DrawState, Rectangle, Transformed};
-use crate::gridmap::grid_map::CellState::{Occupied, Freespace};
 use crate::pointcloud::PointCloud;
 use rayon::iter::ParallelIterator;
 use crate::particlefilter::particle;
@@ -34,23 +33,27 @@ pub trait Draw {
 
 impl Draw for GridMap {
     fn draw(&self, config: &RenderConfig, transform: Matrix2d, gl: &mut GlGraphics) {
-        let size = self.map_size;
+        let bounds = match self.bounds() {
+            None => return,
+            Some(bounds) => bounds
+        };
         let cell_size = 0.1 * config.scale;
 
-        // draw background
+        // draw background covering the explored area
         let rect_bg = graphics::Rectangle::new(graphics::color::hex("333333"));
-        let width = cell_size * (size as f64);
-        let gui_x = -width;
-        let gui_y = -width;
+        let gui_x = -(bounds.max_y as f64) * cell_size;
+        let gui_y = -(bounds.max_x as f64) * cell_size;
+        let width = cell_size * (bounds.height() as f64);
+        let height = cell_size * (bounds.width() as f64);
         rect_bg.draw(
-            [gui_x, gui_y, width, width],
+            [gui_x, gui_y, width, height],
             &DrawState::default(),
             transform,
             gl
         );
 
         // draw cells
-        let mut draw_cell = |rect: Rectangle, r, c| {
+        let mut draw_cell = |rect: Rectangle, r: i64, c: i64| {
             // let x = (r as f64) * cell_size;
             // let y = -(c as f64) * cell_size; // this might be wrong sgn
             let gui_y = -(r as f64) * cell_size;
@@ -66,11 +69,11 @@ impl Draw for GridMap {
 
         let rect_occupied = Rectangle::new(WHITE);
         let rect_freespace = Rectangle::new(graphics::color::hex("525f49"));
-        for r in 0..size {
-            for c in 0..size {
+        for r in bounds.min_x..=bounds.max_x {
+            for c in bounds.min_y..=bounds.max_y {
                 match self.cell_state(r,c) {
-                    Some(&Occupied(_)) => draw_cell(rect_occupied, r, c),
-                    Some(&Freespace) => draw_cell(rect_freespace, r, c),
+                    CellState::Occupied => draw_cell(rect_occupied, r, c),
+                    CellState::Freespace => draw_cell(rect_freespace, r, c),
                     _ => {}
                 }
             }
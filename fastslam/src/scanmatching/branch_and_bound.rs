@@ -0,0 +1,288 @@
+use rayon::prelude::*;
+use crate::geometry::{Point, Vector};
+use crate::gridmap::grid_map::{GridMap, MapBounds};
+use crate::odometry::Pose;
+use crate::sensor::laserscanner::Scan;
+use crate::math::scalar::PI;
+
+/// Parameters of the branch and bound search
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BranchAndBoundParams {
+    pub linear_window: f64, // [m] search from -linear_window to +linear_window in x and y
+    pub angular_window: f64, // [rad] search from -angular_window to +angular_window in heading
+    pub depth: usize, // number of precomputation grids, the coarsest has cells of 2^(depth - 1) cells
+    pub min_score: f64, // matches with a lower mean score (0.0 to 1.0) are discarded
+    pub sigma: f64, // [m] standard deviation of the distance of a beam end to the nearest obstacle
+    pub max_range: f64, // [m] readings at or beyond this range are ignored
+}
+
+impl Default for BranchAndBoundParams {
+    fn default() -> BranchAndBoundParams {
+        BranchAndBoundParams {
+            linear_window: 7.0,
+            angular_window: PI,
+            depth: 7,
+            min_score: 0.5,
+            sigma: 0.1,
+            max_range: 30.0,
+        }
+    }
+}
+
+/// Outcome of a branch and bound scan match
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchAndBoundResult {
+    pub pose: Pose, // best pose in the search window
+    pub score: f64, // mean likelihood of the beam ends at the best pose, 0.0 to 1.0
+}
+
+/// A grid where every cell holds the maximum of the likelihood field over the
+/// 2^depth x 2^depth cells of the map starting at that cell, so the score of a scan on
+/// it is an upper bound for the score of the scan at any of these offsets
+#[derive(Debug, Clone)]
+struct PrecomputationGrid {
+    bounds: MapBounds,
+    values: Vec<f64>, // row-major in x
+    background: f64, // value of all cells outside the bounds
+}
+
+impl PrecomputationGrid {
+    fn value(&self, x: i64, y: i64) -> f64 {
+        if self.bounds.contains(x, y) {
+            let index = (x - self.bounds.min_x) as usize * self.bounds.height() + (y - self.bounds.min_y) as usize;
+            self.values[index]
+        } else {
+            self.background
+        }
+    }
+
+    /// the grid of the next depth, pooling cells that are width cells apart
+    fn pooled(&self, width: i64) -> PrecomputationGrid {
+        let mut values = Vec::with_capacity(self.values.len());
+        for x in self.bounds.min_x..=self.bounds.max_x {
+            for y in self.bounds.min_y..=self.bounds.max_y {
+                values.push(
+                    self.value(x, y)
+                        .max(self.value(x + width, y))
+                        .max(self.value(x, y + width))
+                        .max(self.value(x + width, y + width))
+                );
+            }
+        }
+        PrecomputationGrid { bounds: self.bounds, values, background: self.background }
+    }
+}
+
+/// A node of the search tree: the translations [x, x + 2^depth) x [y, y + 2^depth) in cells
+/// for the rotated scan with the given index
+#[derive(Debug, Copy, Clone)]
+struct Candidate {
+    rotation: usize,
+    x: i64,
+    y: i64,
+    depth: usize,
+    score: f64,
+}
+
+/// A scan rotated by one of the angles of the search, discretized to the cells of the map
+struct DiscreteScan {
+    heading: f64,
+    cells: Vec<(i64, i64)>,
+}
+
+/// Scan-to-map matching that finds the globally best pose in a large search window, e.g.
+/// for loop closure or to relocalize the robot in a known map. Instead of scoring every pose
+/// of the window at full resolution, the window is split recursively and branches whose
+/// upper bound (the score on a max-pooled grid) is below the best score found so far are
+/// skipped. The precomputation grids are built once per map and reused for all scans.
+///
+/// More info:
+///  - Real-Time Loop Closure in 2D LIDAR SLAM, W. Hess, D. Kohler, H. Rapp and D. Andor
+pub struct BranchAndBoundMatcher {
+    grids: Vec<PrecomputationGrid>, // grids[d] pools 2^d x 2^d cells
+    cell_size: f64,
+    map_bounds: Option<MapBounds>,
+    params: BranchAndBoundParams,
+}
+
+impl BranchAndBoundMatcher {
+    pub fn new(gridmap: &GridMap, params: BranchAndBoundParams) -> BranchAndBoundMatcher {
+        let depth = params.depth.max(1);
+        let distance_field = gridmap.distance_field();
+        let likelihood = |d: f64| (-0.5 * (d / params.sigma).powi(2)).exp();
+        let background = likelihood(distance_field.max_distance());
+
+        // the grids extend to the left and below the map, so cells whose pooled window
+        // overlaps the map get their value
+        let pad = (distance_field.max_distance() / gridmap.cell_size()).ceil() as i64;
+        let reach = (1 << (depth - 1)) - 1;
+        let grids = match gridmap.bounds() {
+            None => vec![],
+            Some(b) => {
+                let bounds = MapBounds {
+                    min_x: b.min_x - pad - reach,
+                    min_y: b.min_y - pad - reach,
+                    max_x: b.max_x + pad,
+                    max_y: b.max_y + pad,
+                };
+
+                let mut values = Vec::with_capacity(bounds.width() * bounds.height());
+                for x in bounds.min_x..=bounds.max_x {
+                    for y in bounds.min_y..=bounds.max_y {
+                        values.push(likelihood(distance_field.distance(x, y)));
+                    }
+                }
+
+                let mut grids = vec![PrecomputationGrid { bounds, values, background }];
+                for d in 1..depth {
+                    let pooled = grids[d - 1].pooled(1 << (d - 1));
+                    grids.push(pooled);
+                }
+                grids
+            }
+        };
+
+        BranchAndBoundMatcher {
+            grids,
+            cell_size: gridmap.cell_size(),
+            map_bounds: gridmap.bounds(),
+            params,
+        }
+    }
+
+    /// best pose within the search window around the guess, None if no pose reaches the
+    /// minimum score
+    pub fn match_scan(&self, scan: &Scan, guess: &Pose) -> Option<BranchAndBoundResult> {
+        let window = (self.params.linear_window / self.cell_size).ceil() as i64;
+        self.search(scan, guess, window, window, self.params.angular_window)
+    }
+
+    /// best pose anywhere in the map at any heading, None if no pose reaches the minimum
+    /// score
+    pub fn match_full_map(&self, scan: &Scan) -> Option<BranchAndBoundResult> {
+        let b = self.map_bounds?;
+        let centre = Point::new(
+            (b.min_x + b.max_x + 1) as f64 * 0.5 * self.cell_size,
+            (b.min_y + b.max_y + 1) as f64 * 0.5 * self.cell_size
+        );
+        let guess = Pose::new(centre, 0.0);
+        self.search(scan, &guess, (b.width() as i64 + 1) / 2, (b.height() as i64 + 1) / 2, PI)
+    }
+
+    fn search(&self, scan: &Scan, guess: &Pose, x_window: i64, y_window: i64, angular_window: f64) -> Option<BranchAndBoundResult> {
+        if self.grids.is_empty() {
+            return None
+        }
+
+        let beams: Vec<Vector> = scan
+            .iter()
+            .filter(|m| m.distance.is_finite() && m.distance < self.params.max_range)
+            .map(|m| Vector::from_angle(m.angle) * m.distance)
+            .collect();
+        if beams.is_empty() {
+            return None
+        }
+
+        // rotate in steps that move the farthest beam end by about one cell
+        let max_beam = beams.iter().map(|b| b.length()).fold(0.0, f64::max).max(self.cell_size);
+        let angular_step = (1.0 - self.cell_size.powi(2) / (2.0 * max_beam.powi(2))).acos();
+        let n_angular = (angular_window / angular_step).ceil() as i64;
+        let scans: Vec<DiscreteScan> = (-n_angular..=n_angular)
+            .map(|k| {
+                let heading = guess.heading + k as f64 * angular_step;
+                let cells = beams
+                    .iter()
+                    .map(|beam| {
+                        let p = guess.position + beam.rotate(heading);
+                        ((p.x / self.cell_size).floor() as i64, (p.y / self.cell_size).floor() as i64)
+                    })
+                    .collect();
+                DiscreteScan { heading, cells }
+            })
+            .collect();
+
+        // the coarsest candidates tile the window
+        let top = self.grids.len() - 1;
+        let width = 1 << top;
+        let mut candidates: Vec<Candidate> = (0..scans.len())
+            .into_par_iter()
+            .flat_map_iter(|rotation| {
+                let mut candidates = vec![];
+                let mut x = -x_window;
+                while x <= x_window {
+                    let mut y = -y_window;
+                    while y <= y_window {
+                        candidates.push(self.scored(&scans, Candidate { rotation, x, y, depth: top, score: 0.0 }));
+                        y += width;
+                    }
+                    x += width;
+                }
+                candidates
+            })
+            .collect();
+        Self::sort(&mut candidates);
+
+        let min_score = self.params.min_score * beams.len() as f64;
+        let best = self.branch(&scans, &candidates, (x_window, y_window), min_score)?;
+
+        Some(BranchAndBoundResult {
+            pose: Pose::new(
+                guess.position + Vector::new(best.x as f64, best.y as f64) * self.cell_size,
+                scans[best.rotation].heading
+            ),
+            score: best.score / beams.len() as f64,
+        })
+    }
+
+    /// depth first search through the candidates sorted by score, returns the best leaf
+    /// scoring above min_score
+    fn branch(&self, scans: &[DiscreteScan], candidates: &[Candidate], window: (i64, i64), mut min_score: f64) -> Option<Candidate> {
+        let mut best = None;
+        for candidate in candidates {
+            // candidates are sorted, so no later one scores higher. A NaN score is no match
+            if candidate.score.is_nan() || candidate.score <= min_score {
+                break
+            }
+
+            if candidate.depth == 0 {
+                min_score = candidate.score;
+                best = Some(*candidate);
+                continue
+            }
+
+            // split into four children, skipping those outside the window
+            let half = 1 << (candidate.depth - 1);
+            let mut children = vec![];
+            for &(dx, dy) in &[(0, 0), (half, 0), (0, half), (half, half)] {
+                let (x, y) = (candidate.x + dx, candidate.y + dy);
+                if x <= window.0 && y <= window.1 {
+                    let child = Candidate { x, y, depth: candidate.depth - 1, ..*candidate };
+                    children.push(self.scored(scans, child));
+                }
+            }
+            Self::sort(&mut children);
+
+            if let Some(leaf) = self.branch(scans, &children, window, min_score) {
+                min_score = leaf.score;
+                best = Some(leaf);
+            }
+        }
+        best
+    }
+
+    /// sum of the values of the grid of the candidate's depth at the cells of the beam ends
+    fn scored(&self, scans: &[DiscreteScan], candidate: Candidate) -> Candidate {
+        let grid = &self.grids[candidate.depth];
+        let score = scans[candidate.rotation]
+            .cells
+            .iter()
+            .map(|&(x, y)| grid.value(x + candidate.x, y + candidate.y))
+            .sum();
+        Candidate { score, ..candidate }
+    }
+
+    /// highest score first, NaN scores last
+    fn sort(candidates: &mut [Candidate]) {
+        candidates.sort_by(|a, b| a.score.is_nan().cmp(&b.score.is_nan()).then(b.score.total_cmp(&a.score)));
+    }
+}
@@ -0,0 +1,134 @@
+use nalgebra as na;
+use rayon::prelude::*;
+use crate::geometry::{Point, Vector};
+use crate::gridmap::grid_map::GridMap;
+use crate::odometry::Pose;
+use crate::particlefilter::probabilistic_models::DEFAULT_Z_HIT;
+use crate::sensor::laserscanner::Scan;
+
+type M3x3 = na::Matrix3<f64>;
+type V3 = na::Vector3<f64>;
+
+/// Parameters of the search window around the initial guess
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CorrelativeParams {
+    pub linear_window: f64, // [m] search from -linear_window to +linear_window in x and y
+    pub angular_window: f64, // [rad] search from -angular_window to +angular_window in heading
+    pub linear_step: f64, // [m] resolution of the search in x and y
+    pub angular_step: f64, // [rad] resolution of the search in heading
+    pub sigma: f64, // [m] standard deviation of the distance of a beam end to the nearest obstacle
+    pub z_hit: f64, // probability that a beam ends close to an obstacle of the map rather than at random
+    pub max_range: f64, // [m] readings at or beyond this range are ignored
+}
+
+impl Default for CorrelativeParams {
+    fn default() -> CorrelativeParams {
+        CorrelativeParams {
+            linear_window: 0.3,
+            angular_window: 0.2,
+            linear_step: 0.05,
+            angular_step: 0.01,
+            sigma: 0.1,
+            z_hit: DEFAULT_Z_HIT,
+            max_range: 30.0,
+        }
+    }
+}
+
+/// Outcome of a correlative scan match
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelativeResult {
+    pub pose: Pose, // best pose in the search window
+    pub score: f64, // log-likelihood of the scan at the best pose
+    pub covariance: M3x3, // covariance in (x, y, heading) derived from the scores of the window
+}
+
+/// Scan-to-map matching: evaluates every pose of a regular grid in x, y and heading around
+/// the guess and returns the one under which the scan is most likely given the map. Each
+/// beam end is scored with the likelihood field of the map. The covariance is computed
+/// from the likelihoods of all poses in the window, so it is large in directions the map
+/// does not constrain (e.g. along a corridor).
+///
+/// More info:
+///  - Real-Time Correlative Scan Matching, E. Olson
+///  - p.169 in probabilistic robotics, Sebastian Thrun et al.
+pub fn correlative_scan_match(gridmap: &GridMap, scan: &Scan, guess: &Pose, params: &CorrelativeParams) -> CorrelativeResult {
+    let distance_field = gridmap.distance_field();
+
+    // beam ends in the frame of the sensor
+    let beams: Vec<Vector> = scan
+        .iter()
+        .filter(|m| m.distance.is_finite() && m.distance < params.max_range)
+        .map(|m| Vector::from_angle(m.angle) * m.distance)
+        .collect();
+
+    let n_linear = (params.linear_window / params.linear_step).round() as i64;
+    let n_angular = (params.angular_window / params.angular_step).round() as i64;
+
+    // (offset from the guess in x, y and heading, log-likelihood) of every candidate pose
+    let candidates: Vec<(V3, f64)> = (-n_angular..=n_angular)
+        .into_par_iter()
+        .flat_map_iter(|k| {
+            let d_heading = k as f64 * params.angular_step;
+            let heading = guess.heading + d_heading;
+            let points: Vec<Point> = beams
+                .iter()
+                .map(|beam| guess.position + beam.rotate(heading))
+                .collect();
+
+            let mut scores = Vec::with_capacity(((2 * n_linear + 1) * (2 * n_linear + 1)) as usize);
+            for i in -n_linear..=n_linear {
+                for j in -n_linear..=n_linear {
+                    let offset = Vector::new(i as f64 * params.linear_step, j as f64 * params.linear_step);
+                    let score = points
+                        .iter()
+                        .map(|p| {
+                            let d = distance_field.distance_at(*p + offset);
+                            (params.z_hit * (-0.5 * (d / params.sigma).powi(2)).exp() + (1.0 - params.z_hit)).ln()
+                        })
+                        .sum::<f64>();
+                    scores.push((V3::new(offset.x, offset.y, d_heading), score));
+                }
+            }
+            scores
+        })
+        .collect();
+
+    // the best candidate, the one closest to the guess among equally good ones
+    let (best, best_score) = candidates
+        .iter()
+        .fold((V3::zeros(), f64::NEG_INFINITY), |(best, best_score), (x, score)| {
+            let closer = x.norm() < best.norm();
+            if *score > best_score || (*score == best_score && closer) {
+                (*x, *score)
+            } else {
+                (best, best_score)
+            }
+        });
+
+    // treat the scores as log-likelihoods and compute the moments of the distribution
+    let mut weight_sum = 0.0;
+    let mut mean = V3::zeros();
+    let mut second_moment = M3x3::zeros();
+    for (x, score) in candidates.iter() {
+        let w = (score - best_score).exp();
+        weight_sum += w;
+        mean += x * w;
+        second_moment += x * x.transpose() * w;
+    }
+    mean /= weight_sum;
+
+    // the optimum can lie anywhere within the resolution of the search
+    let resolution = M3x3::from_diagonal(&V3::new(
+        params.linear_step.powi(2) / 12.0,
+        params.linear_step.powi(2) / 12.0,
+        params.angular_step.powi(2) / 12.0
+    ));
+    let covariance = second_moment / weight_sum - mean * mean.transpose() + resolution;
+
+    CorrelativeResult {
+        pose: Pose::new(guess.position + Vector::new(best.x, best.y), guess.heading + best.z),
+        score: best_score,
+        covariance,
+    }
+}
@@ -2,9 +2,11 @@ use crate::pointcloud::PointCloud;
 use crate::geometry::{Point, Vector};
 use nalgebra as na;
 use std::ops::MulAssign;
-use na::{U1, U2, U3, Dynamic};
 use rand_distr::num_traits::abs;
 use crate::odometry::Pose;
+use crate::scanmatching::kdtree::KdTree;
+use crate::scanmatching::point_to_line::{estimate_normals, normal_equations, weighted_point_to_line_transform};
+use crate::scanmatching::robust::{OutlierRejection, RobustKernel};
 use std::borrow::Borrow;
 
 
@@ -22,27 +24,40 @@ type V2 = na::Vector2<f64>;
 ///     t: translation vector
 #[allow(non_snake_case)]
 pub fn best_fit_transform(A: &PointCloud, B: &PointCloud) -> (M3x3, M2x2, V2) {
+    weighted_best_fit_transform(A, B, &vec![1.0; A.size()])
+}
+
+/// best_fit_transform where the squared distance of each pair of points is scaled by its
+/// weight, pairs with weight 0.0 do not contribute
+#[allow(non_snake_case)]
+pub fn weighted_best_fit_transform(A: &PointCloud, B: &PointCloud, weights: &[f64]) -> (M3x3, M2x2, V2) {
 
     // make sure dimensions are the same
     assert_eq!(A.size(), B.size());
+    assert_eq!(A.size(), weights.len());
 
     // convert my type of point to nalgebra point
     let to_na_p2: fn(Point) -> na::Point2<f64> = |p: Point| na::Point2::new(p.x, p.y);
-    let to_na_v2: fn(Point) -> na::Vector2<f64> = |p: Point| na::Vector2::new(p.x, p.y);
 
-    // get centroid of each point cloud
-    let centroid_A: na::Vector2<f64> = to_na_v2(A.centroid());
-    let centroid_B: na::Vector2<f64> = to_na_v2(B.centroid());
-
-    // convert to nalgebra library and center point clouds
+    // convert to nalgebra library
     let A_: Vec<na::Point2<f64>> = A.iter().map(|p: &Point| to_na_p2(*p)).collect();
     let B_: Vec<na::Point2<f64>> = B.iter().map(|p: &Point| to_na_p2(*p)).collect();
 
-    // compute cross-covariance
+    // get weighted centroid of each point cloud
+    let weight_sum: f64 = weights.iter().sum();
+    let centroid = |points: &Vec<na::Point2<f64>>| points
+        .iter()
+        .zip(weights.iter())
+        .fold(na::Vector2::zeros(), |sum, (p, w)| sum + p.coords * *w) / weight_sum;
+    let centroid_A: na::Vector2<f64> = centroid(&A_);
+    let centroid_B: na::Vector2<f64> = centroid(&B_);
+
+    // compute weighted cross-covariance of the centered point clouds
     let H: na::Matrix2<f64> = A_
         .iter()
         .zip(B_.iter())
-        .map(|(a, b)| (a.clone().coords - centroid_A, b.clone().coords - centroid_B))
+        .zip(weights.iter())
+        .map(|((a, b), w)| (a.coords - centroid_A, (b.coords - centroid_B) * *w))
         .map(|(aa, bb)| bb * aa.transpose())
         .fold(na::Matrix2::zeros(), |sum, m| sum + m)
         .transpose();
@@ -97,7 +112,8 @@ pub fn handle_improper_rotation(mut R: M2x2, U: M2x2, S: V2, mut Vt: M2x2) -> M2
 
 
 /// For each point in A (in sequence), find the nearest neighbor by index and distance
-/// in the neighboring pointcloud B
+/// in the neighboring pointcloud B. Builds a KdTree of B for a single query, use the
+/// KdTree directly to search the same pointcloud repeatedly.
 /// Input:
 ///     A: pointcloud in previous step
 ///     B: pointcloud in current step
@@ -106,93 +122,130 @@ pub fn handle_improper_rotation(mut R: M2x2, U: M2x2, S: V2, mut Vt: M2x2) -> M2
 ///     indices: dst indices of the nearest neighbor
 #[allow(non_snake_case)]
 pub fn nearest_neighbor(A: &PointCloud, B: &PointCloud) -> (Vec<f64>, Vec<i64>) {
+    let (distances, indices) = KdTree::new(B).query(A);
+    (distances, indices.into_iter().map(|i| i as i64).collect())
+}
 
-    let mut distances: Vec<f64> = vec![];
-    let mut indices: Vec<i64> = vec![];
-
-    // iterate over all points in pointcloud pca
-    A.iter().for_each(|point| {
-
-        // initialize min_distance
-        let mut min_distance: f64 = 10000.0;
-
-        // container of nearest neighbor
-        let mut min_index: i64 = -1;
-
-        // iterate over all points in pointcloud pcb
-        B.iter().enumerate().for_each(|(ref_id, reference)| {
 
-            // get difference vector and euclidean disntance between points
-            let dif_vector: &Vector = &reference.to_point_vec(*point);
-            let distance: f64 = dif_vector.length();
+/// Error metric minimized in every iteration of icp
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IcpMethod {
+    PointToPoint, // distances between corresponding points, solved with best_fit_transform
+    PointToLine, // distances to the lines through the corresponding points, see point_to_line
+}
 
-            // set new minimum index if new minimum distance
-            if distance < min_distance {
-                min_distance = distance;
-                min_index = ref_id as i64;
-            }
-        });
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IcpParams {
+    pub method: IcpMethod,
+    pub max_iterations: usize, // exit algorithm after max_iterations
+    pub tolerance: f64, // convergence criteria on the change of the mean error
+    pub rejection: OutlierRejection, // which nearest neighbors are paired up
+    pub kernel: RobustKernel, // weighting of the pairs by their residual
+}
 
-        if min_index == -1 {
-            panic!("min_index has not been set!")
+impl Default for IcpParams {
+    fn default() -> IcpParams {
+        IcpParams {
+            method: IcpMethod::PointToPoint,
+            max_iterations: 20,
+            tolerance: 0.00000000001,
+            rejection: OutlierRejection::default(),
+            kernel: RobustKernel::default(),
         }
-
-        distances.push(min_distance);
-        indices.push(min_index);
-    });
-
-    return (distances, indices)
+    }
 }
 
+/// Outcome of a scan match with diagnostics to decide whether it can be trusted
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcpResult {
+    pub transform: Pose, // rigid transform that maps A on to B
+    pub correction: Pose, // change to the initial guess in the frame of the guess, transform = correct_pose(guess, correction)
+    pub iterations: usize, // number of iterations that were run
+    pub initial_residual: f64, // mean distance of the inliers before the first iteration
+    pub final_residual: f64, // mean distance of the inliers after applying the transform
+    pub inliers: usize, // number of correspondences that are not rejected as outliers
+    pub converged: bool, // false if max_iterations was reached before the error settled
+    pub covariance: M3x3, // estimated covariance of the transform in (x, y, heading)
+}
 
 /// The Iterative Closest Point method: finds best-fit transform that maps points A on to points B
 /// Input:
 ///     A: pointcloud in previous step
 ///     B: pointcloud in current step
-///     init_pose: (m+1)x(m+1) homogeneous transformation
 ///     max_iterations: exit algorithm after max_iterations
 ///     tolerance: convergence criteria
 /// Returns:
-///     T: final homogeneous transformation that maps A on to B
-///     distances: Euclidean distances (errors) of the nearest neighbor
-///     i: number of iterations to converge
+///     IcpResult: transform that maps A on to B, together with the number of iterations,
+///     the residuals, the inlier count, the convergence flag and the covariance
 #[allow(non_snake_case)]
-pub fn icp(A: &PointCloud, B: &PointCloud, max_iterations: usize, tolerance: f64) -> Pose {
-
-    let mut A_trans = A.clone();
+pub fn icp(A: &PointCloud, B: &PointCloud, max_iterations: usize, tolerance: f64) -> IcpResult {
+    let params = IcpParams { max_iterations, tolerance, ..IcpParams::default() };
+    icp_with_params(A, B, &params)
+}
 
-    // Homogeneous transformation matrix
-    //let mut T: na::Matrix3<f64> = na::Matrix3::identity();
+/// The Iterative Closest Point method with a selectable error metric, outlier rejection and
+/// robust kernel, see icp
+#[allow(non_snake_case)]
+pub fn icp_with_params(A: &PointCloud, B: &PointCloud, params: &IcpParams) -> IcpResult {
+    icp_with_guess(A, B, &Pose::default(), params)
+}
 
-    let mut prev_err = 0.0;
-    let mut mean_err = 0.0;
+/// The Iterative Closest Point method seeded with an initial guess of the transform that maps
+/// A on to B, e.g. the pose predicted by odometry when A is a scan in the frame of the sensor.
+/// The returned transform includes the guess, the correction is what icp added on top of it.
+#[allow(non_snake_case)]
+pub fn icp_with_guess(A: &PointCloud, B: &PointCloud, guess: &Pose, params: &IcpParams) -> IcpResult {
 
-    for i in 0..max_iterations {
+    // the reference cloud does not change, index it once for all iterations
+    let B_index = KdTree::new(B);
+    let B_normals = match params.method {
+        IcpMethod::PointToPoint => None,
+        IcpMethod::PointToLine => Some(estimate_normals(B, &B_index)),
+    };
 
-        // get neighbor information
-        let (distances, indices) = nearest_neighbor(&A_trans, &B);
+    let mut correction = Pose::default();
+    let mut transform = *guess;
+    let mut A_trans = A.transform(guess);
 
-        // Homogeneous version of A
-        let A_hom = to_na_homogeneous(&A_trans);
+    let mut prev_err = 0.0;
+    let mut initial_err = None;
+    let mut iterations = 0;
+    let mut converged = false;
 
-        // Re-arrange pointcloud B according to nearest neighbors in A
-        let mut B_ordered = PointCloud::empty();
+    for _ in 0..params.max_iterations {
+        iterations += 1;
 
-        indices.into_iter().for_each(|i| {
-            let point = B.get(i as usize);
-            B_ordered.add(point);
-        });
+        // get the pairs of neighbors that survive the outlier rejection
+        let pairs = Correspondences::find(&A_trans, B, &B_index, B_normals.as_deref(), &params.rejection);
+        let weights: Vec<f64> = pairs.residuals.iter().map(|r| params.kernel.weight(*r)).collect();
+        if weights.iter().sum::<f64>() <= 0.0 {
+            break;
+        }
 
-        // Get best transformation matrix for current pointclouds
-        let (T_t, _, _) = best_fit_transform(&A_trans, &B_ordered);
+        // Get best transformation for current pointclouds
+        let step = match &pairs.normals {
+            None => {
+                let (_, R, t) = weighted_best_fit_transform(&pairs.A, &pairs.B, &weights);
+                to_pose(R, t)
+            }
+            Some(normals) => {
+                match weighted_point_to_line_transform(&pairs.A, &pairs.B, normals, &weights) {
+                    None => break,
+                    Some(step) => step
+                }
+            }
+        };
 
-        // Get transformed point cloud A
-        let A_hom_trans: na::OMatrix<f64, Dynamic, U3> = (T_t * A_hom.transpose()).transpose();
-        A_trans = from_na_homogeneous(&A_hom_trans);
+        // Get transformed point cloud A, the step moves it in the frame of B
+        transform = step.compose(&transform);
+        correction = transform.relative_to(guess);
+        A_trans = A.transform(&transform);
 
         // compute mean error
-        mean_err = distances.iter().sum::<f64>() / distances.len() as f64;
-        if abs(prev_err - mean_err) < tolerance {
+        let mean_err = pairs.mean_distance();
+        initial_err.get_or_insert(mean_err);
+        if abs(prev_err - mean_err) < params.tolerance {
+            converged = true;
             break;
         }
 
@@ -200,22 +253,113 @@ pub fn icp(A: &PointCloud, B: &PointCloud, max_iterations: usize, tolerance: f64
         prev_err = mean_err;
     };
 
-    // Homogeneous transformation matrix
-    let (T, R, t) = best_fit_transform(A, &A_trans);
-    return to_pose(R, t);
+    // residuals of the final alignment
+    let pairs = Correspondences::find(&A_trans, B, &B_index, B_normals.as_deref(), &params.rejection);
+    let final_residual = pairs.mean_distance();
+
+    IcpResult {
+        transform,
+        correction,
+        iterations,
+        initial_residual: initial_err.unwrap_or(final_residual),
+        final_residual,
+        inliers: pairs.A.size(),
+        converged,
+        covariance: estimate_covariance(&pairs.A, &pairs.B, pairs.normals.as_deref()),
+    }
+}
+
+/// Pairs of nearest neighbors between a pointcloud and the reference cloud
+#[allow(non_snake_case)]
+struct Correspondences {
+    A: PointCloud,
+    B: PointCloud, // nearest neighbor in the reference cloud of each point in A
+    normals: Option<Vec<Option<Vector>>>, // normal of each point in B for point-to-line icp
+    distances: Vec<f64>, // distance between the points of each pair
+    residuals: Vec<f64>, // error of each pair in the metric that is minimized
 }
 
+impl Correspondences {
+    /// pair each point in A with its nearest neighbor in B and drop the pairs rejected as
+    /// outliers
+    #[allow(non_snake_case)]
+    fn find(
+        A: &PointCloud,
+        B: &PointCloud,
+        B_index: &KdTree,
+        B_normals: Option<&[Option<Vector>]>,
+        rejection: &OutlierRejection
+    ) -> Correspondences {
+        let (distances, indices) = B_index.query(A);
+
+        // a pair is reciprocal if the point in A is also the nearest neighbor of its partner
+        let reciprocal = if rejection.reciprocal {
+            let B_ordered = PointCloud::new(indices.iter().map(|&i| B.get(i)).collect());
+            let (_, back) = KdTree::new(A).query(&B_ordered);
+            back.iter().enumerate().map(|(i, &j)| i == j).collect()
+        } else {
+            vec![true; A.size()]
+        };
+
+        let selected = rejection.select(&distances, &reciprocal);
+        let normals = B_normals.map(|normals| selected.iter().map(|&i| normals[indices[i]]).collect::<Vec<_>>());
+        let A_selected = PointCloud::new(selected.iter().map(|&i| A.get(i)).collect());
+        let B_selected = PointCloud::new(selected.iter().map(|&i| B.get(indices[i])).collect());
+
+        let residuals = selected
+            .iter()
+            .enumerate()
+            .map(|(k, &i)| match normals.as_ref().and_then(|normals| normals[k]) {
+                None => distances[i],
+                Some(normal) => normal.dot(B_selected.get(k).to_point_vec(A_selected.get(k))).abs()
+            })
+            .collect();
+
+        Correspondences {
+            A: A_selected,
+            B: B_selected,
+            normals,
+            distances: selected.iter().map(|&i| distances[i]).collect(),
+            residuals,
+        }
+    }
+
+    /// mean distance between the points of each pair, infinite if there are no pairs
+    fn mean_distance(&self) -> f64 {
+        if self.distances.is_empty() {
+            f64::INFINITY
+        } else {
+            self.distances.iter().sum::<f64>() / self.distances.len() as f64
+        }
+    }
+}
+
+/// Estimate the covariance of an alignment from the corresponding points A and B as
+/// sigma^2 * (J^T J)^-1, where J is the jacobian of the residuals with respect to
+/// (x, y, heading) and sigma^2 is the residual variance. The residuals are point-to-line
+/// distances where normals are given and point-to-point offsets otherwise. A singular
+/// system (e.g. all points at the same spot) gets an infinite variance.
+///
+/// More info:
+///  - An accurate closed-form estimate of ICP's covariance, A. Censi
 #[allow(non_snake_case)]
-fn from_na_homogeneous(A_hom: &na::OMatrix<f64, Dynamic, U3>) -> PointCloud {
-    let mut A = PointCloud::empty();
-    let A_trans = A_hom.clone().remove_column(2);
+fn estimate_covariance(A: &PointCloud, B: &PointCloud, normals: Option<&[Option<Vector>]>) -> M3x3 {
+    let (JtJ, _, squared_error, n) = normal_equations(A, B, normals, None);
+    let dof = n as f64 - 3.0;
+    if dof <= 0.0 {
+        return M3x3::from_diagonal_element(f64::INFINITY)
+    }
 
-    A_trans.row_iter().for_each(|row| {
-        let r: Vec<f64> = row.iter().map(|i| i.clone() as f64).collect();
-        A.add(Point::new(r[0], r[1]));
-    });
+    let sigma2 = squared_error / dof;
+    match JtJ.try_inverse() {
+        Some(inverse) => inverse * sigma2,
+        None => M3x3::from_diagonal_element(f64::INFINITY)
+    }
+}
 
-    A
+/// Apply a correction found by icp (a rigid transform in the frame of the pose) to a pose
+pub fn correct_pose(pose: &Pose, correction: &Pose) -> Pose {
+    pose.compose(correction)
 }
 
 #[allow(non_snake_case)]
@@ -0,0 +1,133 @@
+use acap::coords::Coordinates;
+use acap::distance::{Distance, Proximity};
+use acap::euclid::{euclidean_distance, EuclideanDistance};
+use acap::kd::FlatKdTree;
+use acap::{Neighborhood, NearestNeighbors};
+use rayon::prelude::*;
+use crate::geometry::point::Point;
+use crate::pointcloud::PointCloud;
+
+/// A point of the reference cloud together with its index in that cloud
+#[derive(Debug, Copy, Clone)]
+struct IndexedPoint {
+    coords: [f64; 2],
+    index: usize,
+}
+
+impl Coordinates for IndexedPoint {
+    type Value = f64;
+
+    fn dims(&self) -> usize {
+        2
+    }
+
+    fn coord(&self, i: usize) -> f64 {
+        self.coords[i]
+    }
+}
+
+/// the query points are plain coordinates, the index is only needed on the tree side
+impl Proximity<IndexedPoint> for [f64; 2] {
+    type Distance = EuclideanDistance<f64>;
+
+    fn distance(&self, other: &IndexedPoint) -> Self::Distance {
+        euclidean_distance(self, other)
+    }
+}
+
+/// Search state for a single query that keeps the closest point and, among points at the
+/// same distance, the one with the lowest index (like a linear search would)
+struct LowestIndexNeighborhood<'k> {
+    target: &'k [f64; 2],
+    best: Option<(EuclideanDistance<f64>, usize)>,
+}
+
+impl<'k, 'v> Neighborhood<&'k [f64; 2], &'v IndexedPoint> for LowestIndexNeighborhood<'k> {
+    fn target(&self) -> &'k [f64; 2] {
+        self.target
+    }
+
+    fn contains<D>(&self, distance: D) -> bool
+    where
+        D: PartialOrd<EuclideanDistance<f64>>
+    {
+        match self.best {
+            None => true,
+            Some((best, _)) => distance <= best
+        }
+    }
+
+    fn consider(&mut self, item: &'v IndexedPoint) -> EuclideanDistance<f64> {
+        let distance = self.target.distance(item);
+        let closer = match self.best {
+            None => true,
+            Some((best, index)) => distance < best || (distance == best && item.index < index)
+        };
+        if closer {
+            self.best = Some((distance, item.index));
+        }
+        distance
+    }
+}
+
+/// Spatial index over a reference PointCloud for nearest neighbor queries in
+/// O(log n) instead of O(n). Build it once per reference cloud and query it in every
+/// iteration of the scan matcher.
+#[derive(Debug)]
+pub struct KdTree {
+    tree: FlatKdTree<IndexedPoint>,
+    size: usize,
+}
+
+impl KdTree {
+    pub fn new(reference: &PointCloud) -> KdTree {
+        let points = reference
+            .iter()
+            .enumerate()
+            .map(|(index, p)| IndexedPoint { coords: [p.x, p.y], index });
+
+        KdTree {
+            tree: FlatKdTree::balanced(points),
+            size: reference.size(),
+        }
+    }
+
+    /// number of points in the reference cloud
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Euclidean distance and index of the reference point closest to p, the lowest index
+    /// wins ties. None if the reference cloud is empty
+    pub fn nearest(&self, p: Point) -> Option<(f64, usize)> {
+        let target = [p.x, p.y];
+        self.tree
+            .search(LowestIndexNeighborhood { target: &target, best: None })
+            .best
+            .map(|(distance, index)| (distance.value(), index))
+    }
+
+    /// distances and indices of the up to k reference points closest to p, nearest first
+    pub fn k_nearest(&self, p: Point, k: usize) -> Vec<(f64, usize)> {
+        self.tree
+            .k_nearest(&[p.x, p.y], k)
+            .into_iter()
+            .map(|neighbor| (neighbor.distance.value(), neighbor.item.index))
+            .collect()
+    }
+
+    /// For each point in A (in sequence), find the nearest neighbor in the reference cloud.
+    /// The queries are executed in parallel.
+    /// Returns:
+    ///     distances: Euclidean distances of the nearest neighbor
+    ///     indices: reference indices of the nearest neighbor
+    #[allow(non_snake_case)]
+    pub fn query(&self, A: &PointCloud) -> (Vec<f64>, Vec<usize>) {
+        A.par_iter()
+            .map(|p| match self.nearest(*p) {
+                None => panic!("cannot search for nearest neighbors in an empty pointcloud"),
+                Some(neighbor) => neighbor
+            })
+            .unzip()
+    }
+}
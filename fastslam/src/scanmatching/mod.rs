@@ -1 +1,7 @@
-pub mod icp;
\ No newline at end of file
+pub mod icp;
+pub mod kdtree;
+pub mod point_to_line;
+pub mod robust;
+pub mod correlative;
+pub mod branch_and_bound;
+pub mod ndt;
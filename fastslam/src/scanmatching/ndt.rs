@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use nalgebra as na;
+use crate::geometry::Point;
+use crate::gridmap::grid_map::GridMap;
+use crate::odometry::Pose;
+use crate::pointcloud::PointCloud;
+
+type M2x2 = na::Matrix2<f64>;
+type M3x3 = na::Matrix3<f64>;
+type V2 = na::Vector2<f64>;
+type V3 = na::Vector3<f64>;
+
+/// cells with fewer reference points do not get a distribution
+const MIN_POINTS: usize = 3;
+
+/// the eigenvalues of a covariance are kept above this fraction of the largest one, so points
+/// on a straight wall do not give a singular covariance
+const MIN_EIGENVALUE_RATIO: f64 = 0.01;
+
+/// offsets of the four overlapping grids in cells, every point falls in four distributions
+const GRID_OFFSETS: [(f64, f64); 4] = [(0.0, 0.0), (0.5, 0.0), (0.0, 0.5), (0.5, 0.5)];
+
+/// times the damping of a newton step is increased before giving up on improving the score
+const MAX_DAMPING_STEPS: usize = 20;
+
+/// Normal distribution of the reference points in one cell
+#[derive(Debug, Copy, Clone)]
+struct Gaussian {
+    mean: V2,
+    inverse_covariance: M2x2,
+}
+
+impl Gaussian {
+    fn fit(points: &[V2]) -> Option<Gaussian> {
+        if points.len() < MIN_POINTS {
+            return None
+        }
+
+        let n = points.len() as f64;
+        let mean = points.iter().fold(V2::zeros(), |sum, p| sum + p) / n;
+        let covariance = points
+            .iter()
+            .fold(M2x2::zeros(), |sum, p| sum + (p - mean) * (p - mean).transpose()) / (n - 1.0);
+
+        let mut eigen = covariance.symmetric_eigen();
+        let largest = eigen.eigenvalues.max();
+        if largest <= 0.0 {
+            return None
+        }
+        eigen.eigenvalues.apply(|v| v.max(MIN_EIGENVALUE_RATIO * largest));
+
+        Some(Gaussian {
+            mean,
+            inverse_covariance: eigen.recompose().try_inverse()?,
+        })
+    }
+
+    /// unnormalized density at x, 1.0 at the mean
+    fn likelihood(&self, x: V2) -> f64 {
+        let q = x - self.mean;
+        (-0.5 * q.dot(&(self.inverse_covariance * q))).exp()
+    }
+}
+
+/// The reference scan of NDT: the plane is divided into square cells and the points in each
+/// cell are summarized by their mean and covariance. Four grids shifted by half a cell overlap
+/// to smooth the discretization. Build it once per reference and register every scan against it.
+#[derive(Debug, Clone)]
+pub struct NdtGrid {
+    cell_size: f64,
+    grids: Vec<HashMap<(i64, i64), Gaussian>>, // one map from cell index to distribution per offset
+}
+
+impl NdtGrid {
+    pub fn new(reference: &PointCloud, cell_size: f64) -> NdtGrid {
+        let grids = GRID_OFFSETS
+            .iter()
+            .map(|&offset| {
+                let mut cells: HashMap<(i64, i64), Vec<V2>> = HashMap::new();
+                for p in reference.iter() {
+                    cells
+                        .entry(Self::index(cell_size, offset, *p))
+                        .or_default()
+                        .push(V2::new(p.x, p.y));
+                }
+                cells
+                    .into_iter()
+                    .filter_map(|(index, points)| Gaussian::fit(&points).map(|g| (index, g)))
+                    .collect()
+            })
+            .collect();
+
+        NdtGrid { cell_size, grids }
+    }
+
+    /// distributions of the occupied cells of a gridmap, each occupied cell counts as a
+    /// reference point at its centre
+    pub fn from_gridmap(gridmap: &GridMap, cell_size: f64) -> NdtGrid {
+        let occupied = gridmap
+            .get_all_occupied_cells()
+            .into_iter()
+            .map(|cell| gridmap.map_to_world(cell.x as i64, cell.y as i64))
+            .collect();
+        NdtGrid::new(&PointCloud::new(occupied), cell_size)
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// number of cells with a distribution, summed over the overlapping grids
+    pub fn size(&self) -> usize {
+        self.grids.iter().map(|grid| grid.len()).sum()
+    }
+
+    fn index(cell_size: f64, offset: (f64, f64), p: Point) -> (i64, i64) {
+        ((p.x / cell_size - offset.0).floor() as i64, (p.y / cell_size - offset.1).floor() as i64)
+    }
+
+    /// the distributions of the cells p falls in, one per grid
+    fn distributions(&self, p: Point) -> impl Iterator<Item = &Gaussian> {
+        self.grids
+            .iter()
+            .zip(GRID_OFFSETS.iter())
+            .filter_map(move |(grid, &offset)| grid.get(&Self::index(self.cell_size, offset, p)))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NdtParams {
+    pub max_iterations: usize, // exit algorithm after max_iterations
+    pub tolerance: f64, // converged when the newton step in (x, y, heading) is shorter than this
+}
+
+impl Default for NdtParams {
+    fn default() -> NdtParams {
+        NdtParams {
+            max_iterations: 50,
+            tolerance: 0.000001,
+        }
+    }
+}
+
+/// Outcome of an NDT registration
+#[derive(Debug, Clone, PartialEq)]
+pub struct NdtResult {
+    pub transform: Pose, // rigid transform that maps A on to the reference
+    pub iterations: usize, // number of iterations that were run
+    pub score: f64, // mean likelihood of the transformed points under their best distribution, 0.0 to 1.0
+    pub converged: bool, // false if max_iterations was reached before the step settled
+    pub covariance: M3x3, // inverse of the hessian of the score in (x, y, heading)
+}
+
+/// The Normal Distributions Transform: finds the rigid transform that maps points A on to the
+/// reference by maximizing the sum of the likelihoods of the transformed points under the
+/// distributions of the grid with Newton's method. Unlike icp no correspondences are searched,
+/// which makes it robust for sparse scans. The covariance is the inverse of the hessian at
+/// the optimum, large in directions the reference does not constrain.
+/// Input:
+///     A: pointcloud to register
+///     grid: distributions of the reference
+/// Returns:
+///     NdtResult: transform that maps A on to the reference, together with the number of
+///     iterations, the score, the convergence flag and the covariance
+///
+/// More info:
+///  - The Normal Distributions Transform: A New Approach to Laser Scan Matching, P. Biber
+///    and W. Straßer
+#[allow(non_snake_case)]
+pub fn ndt(A: &PointCloud, grid: &NdtGrid, params: &NdtParams) -> NdtResult {
+    let mut p = V3::zeros();
+    let mut iterations = 0;
+    let mut converged = false;
+
+    for _ in 0..params.max_iterations {
+        iterations += 1;
+
+        let (score, gradient, hessian) = derivatives(A, grid, &p);
+        let step = match damped_newton_step(A, grid, &p, score, &gradient, &hessian) {
+            None => {
+                // no step improves the score, p is the optimum unless no point is close to
+                // any distribution
+                converged = gradient.iter().any(|v| *v != 0.0);
+                break
+            }
+            Some(step) => step
+        };
+
+        p += step;
+        if step.norm() < params.tolerance {
+            converged = true;
+            break;
+        }
+    }
+
+    let (_, _, hessian) = derivatives(A, grid, &p);
+    let covariance = if hessian.cholesky().is_some() {
+        hessian.try_inverse().unwrap_or_else(|| M3x3::from_diagonal_element(f64::INFINITY))
+    } else {
+        M3x3::from_diagonal_element(f64::INFINITY)
+    };
+
+    NdtResult {
+        transform: to_pose(&p),
+        iterations,
+        score: mean_likelihood(A, grid, &p),
+        converged,
+        covariance,
+    }
+}
+
+fn to_pose(p: &V3) -> Pose {
+    Pose::new(Point::new(p[0], p[1]), p[2])
+}
+
+/// sum of the likelihoods of the points A transformed by p under all their distributions,
+/// the score that is maximized
+#[allow(non_snake_case)]
+fn score_at(A: &PointCloud, grid: &NdtGrid, p: &V3) -> f64 {
+    A.transform(&to_pose(p))
+        .iter()
+        .map(|x| grid.distributions(*x).map(|g| g.likelihood(V2::new(x.x, x.y))).sum::<f64>())
+        .sum()
+}
+
+/// mean over the points A transformed by p of the likelihood under their best distribution
+#[allow(non_snake_case)]
+fn mean_likelihood(A: &PointCloud, grid: &NdtGrid, p: &V3) -> f64 {
+    if A.size() == 0 {
+        return 0.0
+    }
+
+    A.transform(&to_pose(p))
+        .iter()
+        .map(|x| grid.distributions(*x).map(|g| g.likelihood(V2::new(x.x, x.y))).fold(0.0, f64::max))
+        .sum::<f64>() / A.size() as f64
+}
+
+/// Score, gradient and hessian of the negated score with respect to (x, y, heading) at p
+#[allow(non_snake_case)]
+fn derivatives(A: &PointCloud, grid: &NdtGrid, p: &V3) -> (f64, V3, M3x3) {
+    let (c, s) = (p[2].cos(), p[2].sin());
+    let mut score = 0.0;
+    let mut gradient = V3::zeros();
+    let mut hessian = M3x3::zeros();
+
+    for a in A.iter() {
+        let x = V2::new(c * a.x - s * a.y + p[0], s * a.x + c * a.y + p[1]);
+
+        // first and second derivative of the transformed point with respect to the heading
+        let dx_dh = V2::new(-s * a.x - c * a.y, c * a.x - s * a.y);
+        let d2x_dh2 = V2::new(-c * a.x + s * a.y, -s * a.x - c * a.y);
+        let J = [V2::new(1.0, 0.0), V2::new(0.0, 1.0), dx_dh];
+
+        for g in grid.distributions(Point::new(x[0], x[1])) {
+            let q_inv = g.inverse_covariance * (x - g.mean);
+            let e = g.likelihood(x);
+            score += e;
+
+            let qJ = V3::new(q_inv.dot(&J[0]), q_inv.dot(&J[1]), q_inv.dot(&J[2]));
+            gradient += qJ * e;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let mut h = J[j].dot(&(g.inverse_covariance * J[i])) - qJ[i] * qJ[j];
+                    if i == 2 && j == 2 {
+                        h += q_inv.dot(&d2x_dh2);
+                    }
+                    hessian[(i, j)] += h * e;
+                }
+            }
+        }
+    }
+
+    (score, gradient, hessian)
+}
+
+/// Newton step -(H + damping I)^-1 g that improves the score at p. Negative eigenvalues of
+/// the hessian (away from the optimum the score is not convex) are flipped so the step points
+/// uphill. The damping grows until the score improves, so where the quadratic model of the
+/// score overshoots the step turns into a short step along the gradient. None if no step
+/// improves the score
+#[allow(non_snake_case)]
+fn damped_newton_step(A: &PointCloud, grid: &NdtGrid, p: &V3, score: f64, gradient: &V3, hessian: &M3x3) -> Option<V3> {
+    if !gradient.iter().all(|v| v.is_finite()) || gradient.norm() == 0.0 {
+        return None
+    }
+
+    let mut eigen = hessian.symmetric_eigen();
+    let scale = eigen.eigenvalues.amax().max(1e-9);
+    eigen.eigenvalues.apply(|v| v.abs().max(1e-6 * scale));
+    let hessian = eigen.recompose();
+
+    let mut damping = 0.0;
+    for _ in 0..MAX_DAMPING_STEPS {
+        if let Some(cholesky) = (hessian + M3x3::identity() * damping).cholesky() {
+            let step = -cholesky.solve(gradient);
+            if score_at(A, grid, &(p + step)) > score {
+                return Some(step)
+            }
+        }
+        damping = (damping * 10.0).max(1e-3 * scale);
+    }
+    None
+}
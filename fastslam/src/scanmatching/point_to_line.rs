@@ -0,0 +1,128 @@
+use nalgebra as na;
+use crate::geometry::{Point, Vector};
+use crate::odometry::Pose;
+use crate::pointcloud::PointCloud;
+use crate::scanmatching::kdtree::KdTree;
+
+type M3x3 = na::Matrix3<f64>;
+type V3 = na::Vector3<f64>;
+
+/// number of reference points (including the point itself) a normal is fitted to
+const NORMAL_NEIGHBORS: usize = 5;
+
+/// Surface normal at each point of a pointcloud, fitted to its nearest neighbors.
+/// None where the neighbors do not span a line (e.g. all at the same spot).
+pub fn estimate_normals(cloud: &PointCloud, index: &KdTree) -> Vec<Option<Vector>> {
+    cloud
+        .iter()
+        .map(|p| {
+            let neighbors: Vec<Point> = index
+                .k_nearest(*p, NORMAL_NEIGHBORS)
+                .into_iter()
+                .map(|(_, i)| cloud.get(i))
+                .collect();
+            fit_normal(&neighbors)
+        })
+        .collect()
+}
+
+/// normal of the line through points, i.e. the eigenvector of the scatter matrix
+/// with the smallest eigenvalue
+fn fit_normal(points: &[Point]) -> Option<Vector> {
+    let n = points.len() as f64;
+    let mean = points.iter().fold(Point::new(0.0, 0.0), |sum, p| sum + *p) / n;
+
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+    for p in points {
+        let d = mean.to_point_vec(*p);
+        xx += d.x * d.x;
+        xy += d.x * d.y;
+        yy += d.y * d.y;
+    }
+
+    let largest_eigenvalue = 0.5 * (xx + yy) + (0.25 * (xx - yy).powi(2) + xy * xy).sqrt();
+    if largest_eigenvalue < 1e-12 {
+        return None
+    }
+
+    let direction = 0.5 * (2.0 * xy).atan2(xx - yy);
+    Some(Vector::new(-direction.sin(), direction.cos()))
+}
+
+/// Linearize the residuals between corresponding points A and B around the identity
+/// transform. A point with a normal contributes its distance to the line through the
+/// corresponding point, a point without a normal its offset in x and y. The residuals
+/// of each point are scaled by its weight, if given.
+/// Returns:
+///     JtJ: J^T J of the jacobian J with respect to (x, y, heading)
+///     Jtr: J^T r of the residuals r
+///     squared_error: r^T W r
+///     n: number of residuals
+#[allow(non_snake_case)]
+pub fn normal_equations(
+    A: &PointCloud,
+    B: &PointCloud,
+    normals: Option<&[Option<Vector>]>,
+    weights: Option<&[f64]>
+) -> (M3x3, V3, f64, usize) {
+    let mut JtJ = M3x3::zeros();
+    let mut Jtr = V3::zeros();
+    let mut squared_error = 0.0;
+    let mut n = 0;
+
+    let mut add_row = |J: V3, r: f64, w: f64| {
+        JtJ += J * J.transpose() * w;
+        Jtr += J * r * w;
+        squared_error += r * r * w;
+        n += 1;
+    };
+
+    for (i, (a, b)) in A.iter().zip(B.iter()).enumerate() {
+        let d = b.to_point_vec(*a);
+        let w = weights.map_or(1.0, |weights| weights[i]);
+        match normals.and_then(|normals| normals[i]) {
+            Some(normal) => {
+                add_row(V3::new(normal.x, normal.y, normal.y * a.x - normal.x * a.y), normal.dot(d), w);
+            }
+            None => {
+                add_row(V3::new(1.0, 0.0, -a.y), d.x, w);
+                add_row(V3::new(0.0, 1.0, a.x), d.y, w);
+            }
+        }
+    }
+
+    (JtJ, Jtr, squared_error, n)
+}
+
+/// Calculates the transform that minimizes the distances from the points A to the lines
+/// through their corresponding points B with the given normals. The rotation is
+/// linearized, so the result is exact for small rotations only and has to be iterated.
+/// Returns None if the lines do not constrain the transform (e.g. all are parallel).
+///
+/// More info:
+///  - An ICP variant using a point-to-line metric, A. Censi
+#[allow(non_snake_case)]
+pub fn point_to_line_transform(A: &PointCloud, B: &PointCloud, normals: &[Option<Vector>]) -> Option<Pose> {
+    weighted_point_to_line_transform(A, B, normals, &vec![1.0; A.size()])
+}
+
+/// point_to_line_transform where the distance of each point is scaled by its weight
+#[allow(non_snake_case)]
+pub fn weighted_point_to_line_transform(
+    A: &PointCloud,
+    B: &PointCloud,
+    normals: &[Option<Vector>],
+    weights: &[f64]
+) -> Option<Pose> {
+    assert_eq!(A.size(), B.size());
+    assert_eq!(A.size(), normals.len());
+    assert_eq!(A.size(), weights.len());
+
+    let (JtJ, Jtr, _, _) = normal_equations(A, B, Some(normals), Some(weights));
+    let x = JtJ.lu().solve(&(-Jtr))?;
+    if x.iter().all(|v| v.is_finite()) {
+        Some(Pose::new(Point::new(x[0], x[1]), x[2]))
+    } else {
+        None
+    }
+}
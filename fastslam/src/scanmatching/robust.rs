@@ -0,0 +1,72 @@
+/// Rules to discard correspondences between two scans before they are aligned, e.g.
+/// points seen in only one of the scans or on objects that moved in between
+///
+/// More info:
+///  - Efficient Variants of the ICP Algorithm, S. Rusinkiewicz and M. Levoy
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OutlierRejection {
+    pub max_distance: f64, // [m] correspondences further apart are discarded
+    pub trim_ratio: f64, // fraction of the correspondences with the largest distances to discard
+    pub reciprocal: bool, // keep a pair only if both points are each other's nearest neighbor
+}
+
+impl Default for OutlierRejection {
+    /// keep all correspondences
+    fn default() -> OutlierRejection {
+        OutlierRejection {
+            max_distance: f64::INFINITY,
+            trim_ratio: 0.0,
+            reciprocal: false,
+        }
+    }
+}
+
+impl OutlierRejection {
+    /// Indices of the correspondences to keep, given the distance of each correspondence
+    /// and whether it is reciprocal. Correspondences without a finite distance (e.g. to a
+    /// NaN point) are never kept
+    pub fn select(&self, distances: &[f64], reciprocal: &[bool]) -> Vec<usize> {
+        let mut selected: Vec<usize> = (0..distances.len())
+            .filter(|&i| distances[i].is_finite() && distances[i] <= self.max_distance)
+            .filter(|&i| !self.reciprocal || reciprocal[i])
+            .collect();
+
+        if self.trim_ratio > 0.0 {
+            let keep = ((1.0 - self.trim_ratio.min(1.0)) * selected.len() as f64).ceil() as usize;
+            selected.sort_by(|&i, &j| distances[i].total_cmp(&distances[j]));
+            selected.truncate(keep);
+            selected.sort();
+        }
+
+        selected
+    }
+}
+
+/// M-estimators that reduce the influence of large residuals on the alignment. Each
+/// iteration of icp solves a weighted least squares problem (iteratively reweighted least
+/// squares) with the weights given by the kernel. The parameter is the scale [m] above
+/// which residuals are considered large.
+///
+/// More info:
+///  - Robust Statistics, P. Huber
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum RobustKernel {
+    #[default]
+    Quadratic, // plain least squares, all residuals have the same weight
+    Huber(f64), // quadratic up to the scale, linear above
+    Cauchy(f64), // weight decays smoothly with the residual
+    Tukey(f64), // residuals above the scale are ignored entirely
+}
+
+impl RobustKernel {
+    /// weight of a residual r in the least squares problem
+    pub fn weight(&self, r: f64) -> f64 {
+        let r = r.abs();
+        match *self {
+            RobustKernel::Quadratic => 1.0,
+            RobustKernel::Huber(k) => if r <= k { 1.0 } else { k / r },
+            RobustKernel::Cauchy(c) => 1.0 / (1.0 + (r / c).powi(2)),
+            RobustKernel::Tukey(c) => if r <= c { (1.0 - (r / c).powi(2)).powi(2) } else { 0.0 },
+        }
+    }
+}
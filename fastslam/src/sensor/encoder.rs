@@ -0,0 +1,17 @@
+/// Cumulative tick counts of the left and right wheel encoders of a differential drive robot
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct EncoderTicks {
+    pub left: i64,
+    pub right: i64,
+}
+
+impl EncoderTicks {
+    pub fn new(left: i64, right: i64) -> EncoderTicks {
+        EncoderTicks { left, right }
+    }
+
+    /// ticks counted since an earlier reading
+    pub fn since(&self, prev: &EncoderTicks) -> EncoderTicks {
+        EncoderTicks::new(self.left - prev.left, self.right - prev.right)
+    }
+}
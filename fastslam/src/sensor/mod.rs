@@ -1,2 +1,4 @@
+pub mod encoder;
 pub mod laserscanner;
 pub mod noise;
+pub mod stamped;
@@ -0,0 +1,17 @@
+/// [s] time a message was measured, e.g. seconds since the start of a recording
+pub type Timestamp = f64;
+
+/// A sensor reading or control input together with the time it was measured, so the filter
+/// can compute time differences from the data instead of the wall clock (e.g. when
+/// replaying a recording)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamped<T> {
+    pub timestamp: Timestamp,
+    pub data: T,
+}
+
+impl<T> Stamped<T> {
+    pub fn new(timestamp: Timestamp, data: T) -> Stamped<T> {
+        Stamped { timestamp, data }
+    }
+}
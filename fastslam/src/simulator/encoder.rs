@@ -0,0 +1,49 @@
+use crate::odometry::{DifferentialDrive, Twist};
+use crate::sensor::encoder::EncoderTicks;
+use crate::sensor::noise::gaussian;
+
+/// Simulated wheel encoders of a differential drive robot. They keep track of the distance
+/// each wheel travelled and count the whole ticks of it, like a real encoder the fraction
+/// of a tick is only counted once the wheel turned far enough.
+pub struct WheelEncoders {
+    pub drive: DifferentialDrive,
+    pub slip: f64, // [m] variance of the distance a wheel travels per meter, 0.0 for wheels that never slip
+    left: f64, // [m] distance travelled by the left wheel
+    right: f64, // [m] distance travelled by the right wheel
+}
+
+impl Default for WheelEncoders {
+    fn default() -> WheelEncoders {
+        WheelEncoders::new(DifferentialDrive::default())
+    }
+}
+
+impl WheelEncoders {
+    pub fn new(drive: DifferentialDrive) -> WheelEncoders {
+        WheelEncoders { drive, slip: 0.0, left: 0.0, right: 0.0 }
+    }
+
+    /// turns the wheels to drive with the gain for dt
+    pub fn update(&mut self, gain: &Twist, dt: f64) {
+        let (left, right) = self.drive.wheel_motion(gain.velocity.x * dt, gain.angular * dt);
+        self.left += self.slipped(left);
+        self.right += self.slipped(right);
+    }
+
+    /// ticks counted since the start
+    pub fn ticks(&self) -> EncoderTicks {
+        let distance_per_tick = self.drive.distance_per_tick();
+        EncoderTicks::new(
+            (self.left / distance_per_tick).floor() as i64,
+            (self.right / distance_per_tick).floor() as i64
+        )
+    }
+
+    fn slipped(&self, distance: f64) -> f64 {
+        if self.slip > 0.0 {
+            gaussian(distance, (self.slip * distance.abs()).sqrt())
+        } else {
+            distance
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::sensor::noise::gaussian;
+
+/// Simulated gyro measuring the yaw rate of the robot. Every reading is off by the bias and
+/// white noise, and the bias drifts as a random walk, so the heading integrated from the
+/// readings slowly drifts away.
+pub struct Gyro {
+    pub bias: f64, // [rad/s] current bias of the readings
+    pub bias_walk: f64, // [rad/s/sqrt(s)] standard deviation of the change of the bias per sqrt second
+    pub noise: f64, // [rad/s] standard deviation of the white noise of a reading
+}
+
+impl Default for Gyro {
+    fn default() -> Gyro {
+        Gyro {
+            bias: 0.0,
+            bias_walk: 0.0001,
+            noise: 0.001,
+        }
+    }
+}
+
+impl Gyro {
+    /// the reading after turning at the yaw rate [rad/s] for dt, the bias drifts meanwhile
+    pub fn measure(&mut self, yaw_rate: f64, dt: f64) -> f64 {
+        self.bias += gaussian(0.0, self.bias_walk * dt.abs().sqrt());
+        gaussian(yaw_rate + self.bias, self.noise)
+    }
+}
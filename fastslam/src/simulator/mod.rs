@@ -1,5 +1,7 @@
 pub use crate::sensor::noise::Noise;
 
+pub use self::encoder::WheelEncoders;
+pub use self::gyro::Gyro;
 pub use self::laserscanner::LaserScanner;
 pub use self::robot::Direction;
 // Re-export all base-types.
@@ -7,4 +9,6 @@ pub use self::robot::Robot;
 
 pub mod robot;
 pub mod laserscanner;
+pub mod encoder;
+pub mod gyro;
 
@@ -1,4 +1,6 @@
 use crate::odometry::{Odometry, Twist, MotionModel};
+use crate::simulator::encoder::WheelEncoders;
+use crate::simulator::gyro::Gyro;
 use crate::simulator::laserscanner::LaserScanner;
 use crate::geometry::{Vector};
 
@@ -16,6 +18,9 @@ pub struct Robot {
     pub odom: Odometry,
     pub latest_gain: Twist,
     pub laser_scanner: LaserScanner,
+    pub encoders: WheelEncoders,
+    pub gyro: Gyro,
+    pub latest_yaw_rate: f64, // [rad/s] gyro reading of the latest move
 }
 
 impl Default for Robot {
@@ -25,7 +30,10 @@ impl Default for Robot {
             w: 0.08,
             odom: Odometry::default(),
             latest_gain: Twist::default(),
-            laser_scanner: LaserScanner { num_columns: 100}
+            laser_scanner: LaserScanner { num_columns: 100},
+            encoders: WheelEncoders::default(),
+            gyro: Gyro::default(),
+            latest_yaw_rate: 0.0,
         }
     }
 }
@@ -50,6 +58,8 @@ impl Robot {
                 self.latest_gain = gain.clone();
                 // self.odom.pose = Self::sample_motion_model_velocity(&self.odom.pose, &gain, 1.0);
                 self.odom.pose = Self::drive(&self.odom.pose, &gain, 1.0);
+                self.encoders.update(&gain, 1.0);
+                self.latest_yaw_rate = self.gyro.measure(gain.angular, 1.0);
             },
             None => (),
         }
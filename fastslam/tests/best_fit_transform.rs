@@ -1,6 +1,6 @@
 use fastslam::geometry::{Point, Vector};
 use fastslam::pointcloud::PointCloud;
-use fastslam::scanmatching::icp::{best_fit_transform, handle_improper_rotation};
+use fastslam::scanmatching::icp::{best_fit_transform, weighted_best_fit_transform, handle_improper_rotation};
 use nalgebra as na;
 use approx::*;
 
@@ -427,4 +427,25 @@ fn improper_rotation_case_2()  {
     if R.determinant() < 0.0 {
         R = handle_improper_rotation(R, U, S, Vt);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_weighted_best_fit_transform_ignores_zero_weights() {
+    let a = vec![Point::new(1.0, 2.0), Point::new(2.0, 2.0), Point::new(3.0, 3.0), Point::new(-1.0, -2.0)];
+    let offset = Vector::new(0.5, -0.25);
+    let mut b: Vec<Point> = a.iter().map(|p| *p + offset).collect();
+    b.push(Point::new(40.0, -30.0));
+
+    let A = PointCloud::new(a.iter().cloned().chain(vec![Point::new(0.0, 0.0)]).collect());
+    let B = PointCloud::new(b);
+
+    let (_, R, t) = weighted_best_fit_transform(&A, &B, &[1.0, 1.0, 1.0, 1.0, 0.0]);
+
+    assert_eq!(relative_eq!(R, na::Matrix2::identity(), epsilon = 1.0e-9), true);
+    assert_eq!(relative_eq!(t, na::Vector2::new(0.5, -0.25), epsilon = 1.0e-9), true);
+
+    // with equal weights the outlier drags the estimate
+    let (_, _, t) = weighted_best_fit_transform(&A, &B, &[1.0; 5]);
+    assert_eq!(relative_eq!(t, na::Vector2::new(0.5, -0.25), epsilon = 1.0e-1), false);
+}
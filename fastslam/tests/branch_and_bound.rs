@@ -0,0 +1,102 @@
+use fastslam::geometry::{Line, Point};
+use fastslam::gridmap::grid_map::GridMap;
+use fastslam::odometry::Pose;
+use fastslam::scanmatching::branch_and_bound::{BranchAndBoundMatcher, BranchAndBoundParams};
+use fastslam::simulator::LaserScanner;
+use fastslam::math::scalar::PI;
+
+mod common;
+use common::polygon;
+
+/// an L-shaped room with a box in it, so every pose looks different
+fn room() -> Vec<Line> {
+    let mut walls = polygon(&[
+        Point::new(0.0, 0.0), Point::new(8.0, 0.0), Point::new(8.0, 6.0),
+        Point::new(3.0, 6.0), Point::new(3.0, 4.0), Point::new(0.0, 4.0),
+    ]);
+    walls.extend(polygon(&[Point::new(5.0, 1.0), Point::new(6.0, 1.0), Point::new(6.0, 2.5), Point::new(5.0, 2.5)]));
+    walls
+}
+
+fn mapped_room() -> GridMap {
+    let laser_scanner = LaserScanner { num_columns: 720 };
+    let mut gridmap = GridMap::new(0.1);
+    for &(x, y) in &[(1.5, 2.0), (4.0, 3.5), (7.0, 3.5), (7.0, 0.5), (4.5, 5.0)] {
+        let pose = Pose::new(Point::new(x, y), 0.0);
+        gridmap.update(&pose, &laser_scanner.scan(&pose, &room()));
+    }
+    gridmap
+}
+
+fn heading_error(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(2.0 * PI);
+    d.min(2.0 * PI - d)
+}
+
+#[test]
+fn test_branch_and_bound_local_match() {
+    let gridmap = mapped_room();
+    let laser_scanner = LaserScanner { num_columns: 360 };
+
+    let true_pose = Pose::new(Point::new(2.2, 1.4), 0.3);
+    let scan = laser_scanner.scan(&true_pose, &room());
+    let guess = Pose::new(Point::new(2.6, 1.1), 0.15);
+
+    let params = BranchAndBoundParams { linear_window: 1.0, angular_window: 0.3, ..BranchAndBoundParams::default() };
+    let result = BranchAndBoundMatcher::new(&gridmap, params).match_scan(&scan, &guess).unwrap();
+
+    assert!(result.pose.position.dist_to_point(true_pose.position) <= 0.1);
+    assert!(heading_error(result.pose.heading, true_pose.heading) <= 0.03);
+    assert!(result.score > 0.8 && result.score <= 1.0);
+}
+
+#[test]
+fn test_branch_and_bound_is_exhaustive() {
+    let gridmap = mapped_room();
+    let laser_scanner = LaserScanner { num_columns: 180 };
+
+    let scan = laser_scanner.scan(&Pose::new(Point::new(6.8, 4.1), -1.0), &room());
+    let guess = Pose::new(Point::new(6.5, 4.5), -1.2);
+    let params = BranchAndBoundParams { linear_window: 0.8, angular_window: 0.3, min_score: 0.0, ..BranchAndBoundParams::default() };
+
+    // with a single grid every pose of the window is scored
+    let exhaustive = BranchAndBoundMatcher::new(&gridmap, BranchAndBoundParams { depth: 1, ..params });
+    let pruned = BranchAndBoundMatcher::new(&gridmap, BranchAndBoundParams { depth: 5, ..params });
+
+    let expected = exhaustive.match_scan(&scan, &guess).unwrap();
+    let result = pruned.match_scan(&scan, &guess).unwrap();
+    assert!((result.score - expected.score).abs() < 1e-12);
+}
+
+#[test]
+fn test_branch_and_bound_global_relocalisation() {
+    let gridmap = mapped_room();
+    let laser_scanner = LaserScanner { num_columns: 360 };
+    let matcher = BranchAndBoundMatcher::new(&gridmap, BranchAndBoundParams::default());
+
+    for true_pose in &[Pose::new(Point::new(6.5, 4.5), 2.0), Pose::new(Point::new(1.0, 3.0), -2.5)] {
+        let scan = laser_scanner.scan(true_pose, &room());
+        let result = matcher.match_full_map(&scan).unwrap();
+
+        assert!(result.pose.position.dist_to_point(true_pose.position) <= 0.15);
+        assert!(heading_error(result.pose.heading, true_pose.heading) <= 0.03);
+    }
+
+    // nothing to match against in an empty map
+    let empty = BranchAndBoundMatcher::new(&GridMap::new(0.1), BranchAndBoundParams::default());
+    assert!(empty.match_full_map(&laser_scanner.scan(&Pose::default(), &room())).is_none());
+}
+
+#[test]
+fn test_branch_and_bound_with_nan_scores() {
+    let gridmap = mapped_room();
+    let laser_scanner = LaserScanner { num_columns: 90 };
+    let scan = laser_scanner.scan(&Pose::new(Point::new(2.2, 1.4), 0.3), &room());
+
+    // without a standard deviation beam ends on an obstacle score 0.0 / 0.0, the poses
+    // with such a beam are skipped instead of being compared
+    let params = BranchAndBoundParams { linear_window: 0.5, angular_window: 0.1, sigma: 0.0, min_score: -1.0, ..BranchAndBoundParams::default() };
+    let matcher = BranchAndBoundMatcher::new(&gridmap, params);
+    let result = matcher.match_scan(&scan, &Pose::new(Point::new(2.3, 1.3), 0.25)).unwrap();
+    assert_eq!(result.score, 0.0);
+}
@@ -0,0 +1,68 @@
+// every test crate uses only some of the fixtures
+#![allow(dead_code)]
+
+use fastslam::geometry::{Line, Point};
+use fastslam::odometry::Pose;
+use fastslam::sensor::laserscanner::{Measurement, Scan};
+
+/// walls along the closed polygon through the corners
+pub fn polygon(corners: &[Point]) -> Vec<Line> {
+    corners.iter().zip(corners.iter().cycle().skip(1)).map(|(a, b)| Line::new(*a, *b)).collect()
+}
+
+/// the 10m x 7m room [-4, 6] x [-3, 4]
+pub fn rectangular_room() -> Vec<Line> {
+    polygon(&[Point::new(-4.0, -3.0), Point::new(6.0, -3.0), Point::new(6.0, 4.0), Point::new(-4.0, 4.0)])
+}
+
+/// an L-shaped room: [-4, 8] x [-3, 3.5] and the alcove [2, 8] x [3.5, 5]
+pub fn l_shaped_room() -> Vec<Line> {
+    polygon(&[
+        Point::new(-4.0, -3.0), Point::new(8.0, -3.0), Point::new(8.0, 5.0),
+        Point::new(2.0, 5.0), Point::new(2.0, 3.5), Point::new(-4.0, 3.5),
+    ])
+}
+
+/// the L-shaped room with the box [4, 5] x [0, 1.5] in the middle
+pub fn l_shaped_room_with_box() -> Vec<Line> {
+    let mut walls = l_shaped_room();
+    walls.extend(polygon(&[Point::new(4.0, 0.0), Point::new(5.0, 0.0), Point::new(5.0, 1.5), Point::new(4.0, 1.5)]));
+    walls
+}
+
+/// distance from p to the closest point of an axis aligned wall
+pub fn distance_to_wall(wall: &Line, p: Point) -> f64 {
+    let x = p.x.max(wall.start.x.min(wall.end.x)).min(wall.start.x.max(wall.end.x));
+    let y = p.y.max(wall.start.y.min(wall.end.y)).min(wall.start.y.max(wall.end.y));
+    p.dist_to_point(Point::new(x, y))
+}
+
+/// [m] range of the beam at angle [rad] in the frame of the sensor at pose to the walls of
+/// the box [-x_max, x_max] x [-y_max, y_max]
+fn box_range(pose: &Pose, x_max: f64, y_max: f64, angle: f64) -> f64 {
+    let (dx, dy) = ((angle + pose.heading).cos(), (angle + pose.heading).sin());
+    let range_x = ((x_max * dx.signum() - pose.position.x) / dx).abs();
+    let range_y = ((y_max * dy.signum() - pose.position.y) / dy).abs();
+    range_x.min(range_y)
+}
+
+/// scan taken from pose inside the box [-x_max, x_max] x [-y_max, y_max], one beam per degree
+pub fn box_scan(pose: &Pose, x_max: f64, y_max: f64) -> Scan {
+    (0..360)
+        .map(|i| {
+            let angle = (i as f64).to_radians();
+            Measurement::new(angle, box_range(pose, x_max, y_max, angle))
+        })
+        .collect()
+}
+
+/// beam ends of a scan of the 10m x 6m room [-5, 5] x [-3, 3] taken from pose, one beam per
+/// degree starting at angle_offset [deg], in the frame of the sensor
+pub fn room_scan(pose: Pose, angle_offset: f64) -> Vec<Point> {
+    (0..360)
+        .map(|i| {
+            let angle = (i as f64 + angle_offset).to_radians();
+            Measurement::new(angle, box_range(&pose, 5.0, 3.0, angle)).to_point(&Pose::default())
+        })
+        .collect()
+}
@@ -0,0 +1,123 @@
+use fastslam::particlefilter::config::{ConfigError, FastSlamConfig, IcpGate};
+use fastslam::particlefilter::kld_sampling::KldParams;
+use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::particlefilter::probabilistic_models::LikelihoodFieldParams;
+use fastslam::particlefilter::resampling::ResamplingScheme;
+use fastslam::scanmatching::icp::IcpParams;
+use fastslam::simulator::{Robot, Direction};
+use fastslam::geometry::Vector;
+use fastslam::odometry::{Covariance, MotionModel, Twist};
+
+#[test]
+fn test_default_config() {
+    let config = FastSlamConfig::default();
+    assert_eq!(config.validate(), Ok(()));
+    assert_eq!(FastSlamConfig::builder().build(), Ok(config));
+
+    let particle_filter = ParticleFilter::default();
+    assert_eq!(particle_filter.config(), &config);
+    assert_eq!(particle_filter.n_particles(), 25);
+}
+
+#[test]
+fn test_builder() {
+    let config = FastSlamConfig::builder()
+        .n_particles(7)
+        .fixed_dt(None)
+        .max_gain_age(0.1)
+        .icp(IcpParams { max_iterations: 5, ..IcpParams::default() })
+        .icp_gate(IcpGate { max_translation: 0.2, ..IcpGate::default() })
+        .n_pose_samples(10)
+        .sampling_range(0.1)
+        .resampling_threshold(0.3)
+        .resampling(ResamplingScheme::Residual)
+        .kld(Some(KldParams::default()))
+        .motion_noise([0.1, 0.0, 0.1, 0.0, 0.05, 0.05])
+        .odometry_noise([0.02, 0.01, 0.02, 0.01])
+        .gyro_noise([0.02, 0.01, 0.001, 0.0])
+        .likelihood_field(LikelihoodFieldParams { sigma_hit: 0.1, ..LikelihoodFieldParams::default() })
+        .build()
+        .unwrap();
+
+    assert_eq!(config.n_particles, 7);
+    assert_eq!(config.fixed_dt, None);
+    assert_eq!(config.max_gain_age, 0.1);
+    assert_eq!(config.icp.max_iterations, 5);
+    assert_eq!(config.icp_gate.max_translation, 0.2);
+    assert_eq!(config.n_pose_samples, 10);
+    assert_eq!(config.sampling_range, 0.1);
+    assert_eq!(config.resampling_threshold, 0.3);
+    assert_eq!(config.resampling, ResamplingScheme::Residual);
+    assert_eq!(config.kld, Some(KldParams::default()));
+    assert_eq!(config.motion_noise[4], 0.05);
+    assert_eq!(config.odometry_noise[1], 0.01);
+    assert_eq!(config.gyro_noise[2], 0.001);
+    assert_eq!(config.likelihood_field.sigma_hit, 0.1);
+
+    let particle_filter = ParticleFilter::new(config).unwrap();
+    assert_eq!(particle_filter.n_particles(), 7);
+    assert!((particle_filter.weights().iter().sum::<f64>() - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_validation() {
+    let invalid = |config: Result<FastSlamConfig, ConfigError>| config.unwrap_err().parameter;
+
+    assert_eq!(invalid(FastSlamConfig::builder().n_particles(0).build()), "n_particles");
+    assert_eq!(invalid(FastSlamConfig::builder().fixed_dt(Some(0.0)).build()), "fixed_dt");
+    assert_eq!(invalid(FastSlamConfig::builder().fixed_dt(Some(f64::NAN)).build()), "fixed_dt");
+    assert_eq!(invalid(FastSlamConfig::builder().max_gain_age(-1.0).build()), "max_gain_age");
+    assert_eq!(invalid(FastSlamConfig::builder().icp(IcpParams { max_iterations: 0, ..IcpParams::default() }).build()), "icp.max_iterations");
+    assert_eq!(invalid(FastSlamConfig::builder().icp_gate(IcpGate { max_residual: -0.1, ..IcpGate::default() }).build()), "icp_gate.max_residual");
+    assert_eq!(invalid(FastSlamConfig::builder().icp_gate(IcpGate { max_rotation: f64::NAN, ..IcpGate::default() }).build()), "icp_gate.max_rotation");
+    assert_eq!(invalid(FastSlamConfig::builder().n_pose_samples(0).build()), "n_pose_samples");
+    assert_eq!(invalid(FastSlamConfig::builder().sampling_range(-0.1).build()), "sampling_range");
+    assert_eq!(invalid(FastSlamConfig::builder().resampling_threshold(1.5).build()), "resampling_threshold");
+    assert_eq!(invalid(FastSlamConfig::builder().motion_noise([0.01, 0.01, -0.01, 0.01, 0.01, 0.01]).build()), "motion_noise");
+    assert_eq!(invalid(FastSlamConfig::builder().odometry_noise([0.01, f64::NAN, 0.01, 0.01]).build()), "odometry_noise");
+    assert_eq!(invalid(FastSlamConfig::builder().gyro_noise([0.01, 0.01, -1.0, 0.01]).build()), "gyro_noise");
+    assert_eq!(invalid(FastSlamConfig::builder().likelihood_field(LikelihoodFieldParams { z_hit: 1.2, ..LikelihoodFieldParams::default() }).build()), "likelihood_field.z_hit");
+    assert_eq!(invalid(FastSlamConfig::builder().likelihood_field(LikelihoodFieldParams { sigma_hit: 0.0, ..LikelihoodFieldParams::default() }).build()), "likelihood_field.sigma_hit");
+    assert_eq!(invalid(FastSlamConfig::builder().likelihood_field(LikelihoodFieldParams { max_distance: 0.0, ..LikelihoodFieldParams::default() }).build()), "likelihood_field.max_distance");
+    assert_eq!(invalid(FastSlamConfig::builder().kld(Some(KldParams { min_particles: 50, max_particles: 20, ..KldParams::default() })).build()), "kld.max_particles");
+    assert_eq!(invalid(FastSlamConfig::builder().kld(Some(KldParams { delta: 1.0, ..KldParams::default() })).build()), "kld.delta");
+
+    // a configuration changed by hand is checked by the filter
+    let config = FastSlamConfig { n_pose_samples: 0, ..FastSlamConfig::default() };
+    let error = ParticleFilter::new(config).err().unwrap();
+    assert_eq!(error.to_string(), "invalid n_pose_samples: at least one pose sample is needed");
+}
+
+#[test]
+fn test_filter_runs_with_custom_config() {
+    let config = FastSlamConfig::builder()
+        .n_particles(4)
+        .n_pose_samples(5)
+        .resampling(ResamplingScheme::Stratified)
+        .resampling_threshold(1.0)
+        .build()
+        .unwrap();
+    let mut particle_filter = ParticleFilter::new(config).unwrap();
+    let mut robot = Robot::default();
+
+    for _ in 0..3 {
+        robot.move_forward(Some(Direction::Forward));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &[]);
+        particle_filter.cycle(&scan, &robot.latest_gain.clone());
+        assert_eq!(particle_filter.n_particles(), 4);
+    }
+}
+
+#[test]
+fn test_prediction_uses_configured_motion_noise() {
+    let gain = Twist::new(Vector::new(0.5, 0.0), 0.2);
+    let quiet = ParticleFilter::new(FastSlamConfig::builder().motion_noise([0.0; 6]).build().unwrap()).unwrap();
+    let noisy = ParticleFilter::new(FastSlamConfig::builder().motion_noise([0.1; 6]).build().unwrap()).unwrap();
+
+    // all particles start at the same pose, only the motion noise adds covariance
+    assert_eq!(quiet.predict(&gain, 1.0).covariance, Covariance::zeros());
+    let predicted = noisy.predict(&gain, 1.0);
+    assert_eq!(predicted, ParticleFilter::motion_model_velocity_with_covariance_with_noise(&noisy.estimate, &gain, 1.0, &[0.1; 6]));
+    assert!(predicted.covariance.trace() > 0.0);
+    assert_eq!(predicted.pose, quiet.predict(&gain, 1.0).pose);
+}
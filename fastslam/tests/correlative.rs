@@ -0,0 +1,51 @@
+use fastslam::geometry::Point;
+use fastslam::gridmap::grid_map::GridMap;
+use fastslam::odometry::Pose;
+use fastslam::scanmatching::correlative::{correlative_scan_match, CorrelativeParams};
+use fastslam::sensor::laserscanner::Scan;
+
+mod common;
+use common::box_scan;
+
+#[test]
+fn test_correlative_scan_match_in_a_room() {
+    // the walls run through the centres of the cells
+    let mut gridmap = GridMap::new(0.05);
+    gridmap.update(&Pose::default(), &box_scan(&Pose::default(), 5.025, 3.025));
+
+    let true_pose = Pose::new(Point::new(0.4, -0.3), 0.1);
+    let scan = box_scan(&true_pose, 5.025, 3.025);
+    let guess = Pose::new(Point::new(0.55, -0.2), 0.05);
+
+    let result = correlative_scan_match(&gridmap, &scan, &guess, &CorrelativeParams::default());
+
+    assert!(result.pose.position.dist_to_point(true_pose.position) <= 0.05);
+    assert!((result.pose.heading - true_pose.heading).abs() <= 0.01);
+    assert!(result.score.is_finite());
+
+    // the room constrains all directions, the covariance is small and positive definite
+    assert!(result.covariance.cholesky().is_some());
+    assert!((0..3).all(|i| result.covariance[(i, i)] < 0.01));
+}
+
+#[test]
+fn test_correlative_covariance_in_a_corridor() {
+    // a corridor along x that is much longer than the range of the scans
+    let mut gridmap = GridMap::new(0.05);
+    gridmap.update(&Pose::default(), &box_scan(&Pose::default(), 1000.0, 1.0));
+
+    let true_pose = Pose::new(Point::new(0.1, 0.2), 0.0);
+    let scan: Scan = box_scan(&true_pose, 1000.0, 1.0)
+        .iter()
+        .filter(|m| m.distance < 8.0)
+        .cloned()
+        .collect();
+    let guess = Pose::new(Point::new(0.0, 0.0), 0.0);
+    let params = CorrelativeParams { max_range: 8.0, ..CorrelativeParams::default() };
+
+    let result = correlative_scan_match(&gridmap, &scan, &guess, &params);
+
+    // the distance to the walls is found, the position along the corridor is uncertain
+    assert!((result.pose.position.y - true_pose.position.y).abs() <= 0.05);
+    assert!(result.covariance[(0, 0)] > 10.0 * result.covariance[(1, 1)]);
+}
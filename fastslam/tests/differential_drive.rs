@@ -0,0 +1,182 @@
+use fastslam::geometry::{Point, Vector};
+use fastslam::math::scalar::PI;
+use fastslam::odometry::{Covariance, DifferentialDrive, Odometry, Pose, PoseWithCovariance, Twist};
+use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::sensor::encoder::EncoderTicks;
+use fastslam::sensor::noise::gaussian;
+use fastslam::simulator::{Direction, Robot, WheelEncoders};
+
+mod common;
+use common::{distance_to_wall, l_shaped_room};
+
+fn ticks(drive: &DifferentialDrive, left: f64, right: f64) -> EncoderTicks {
+    let distance_per_tick = drive.distance_per_tick();
+    EncoderTicks::new((left / distance_per_tick).round() as i64, (right / distance_per_tick).round() as i64)
+}
+
+#[test]
+fn test_wheel_distances() {
+    let drive = DifferentialDrive::new(0.1, 0.5, 1000);
+    assert!((drive.distance_per_tick() - 0.2 * PI / 1000.0).abs() < 1e-15);
+
+    // one revolution forward with the left wheel, half a revolution back with the right
+    let (left, right) = drive.wheel_distances(&EncoderTicks::new(1000, -500));
+    assert!((left - 0.2 * PI).abs() < 1e-12);
+    assert!((right + 0.1 * PI).abs() < 1e-12);
+
+    let (distance, rotation) = drive.motion(left, right);
+    assert!((distance - 0.05 * PI).abs() < 1e-12);
+    assert!((rotation + 0.6 * PI).abs() < 1e-12);
+
+    let (l, r) = drive.wheel_motion(distance, rotation);
+    assert!((l - left).abs() < 1e-12 && (r - right).abs() < 1e-12);
+
+    assert_eq!(EncoderTicks::new(10, 20).since(&EncoderTicks::new(15, 5)), EncoderTicks::new(-5, 15));
+}
+
+#[test]
+fn test_integrate() {
+    let drive = DifferentialDrive::default();
+    let start = Odometry::new(Pose::new(Point::new(1.0, 2.0), 0.5 * PI), Default::default());
+    let zero = EncoderTicks::default();
+
+    // straight ahead
+    let odom = drive.integrate(&start, &zero, &ticks(&drive, 0.5, 0.5), 2.0);
+    assert!(odom.pose.position.dist_to_point(Point::new(1.0, 2.5)) < 1e-3);
+    assert!((odom.pose.heading - 0.5 * PI).abs() < 1e-12);
+    assert!((odom.vel.velocity.x - 0.25).abs() < 1e-3);
+    assert_eq!(odom.vel.angular, 0.0);
+
+    // on the spot
+    let (left, right) = drive.wheel_motion(0.0, -0.5 * PI);
+    let odom = drive.integrate(&start, &zero, &ticks(&drive, left, right), 1.0);
+    assert!(odom.pose.position.dist_to_point(start.pose.position) < 1e-12);
+    assert!(odom.pose.heading.abs() < 1e-3);
+    assert!((odom.vel.angular + 0.5 * PI).abs() < 1e-3);
+
+    // a quarter circle with radius 1.0 in small steps ends on the circle
+    let (left, right) = drive.wheel_motion(0.5 * PI, 0.5 * PI);
+    let mut odom = Odometry::default();
+    let mut prev = zero;
+    for i in 1..=100 {
+        let f = i as f64 / 100.0;
+        let curr = ticks(&drive, f * left, f * right);
+        odom = drive.integrate(&odom, &prev, &curr, 0.1);
+        prev = curr;
+    }
+    assert!(odom.pose.position.dist_to_point(Point::new(1.0, 1.0)) < 1e-3);
+    assert!((odom.pose.heading - 0.5 * PI).abs() < 1e-3);
+
+    // no time passed
+    assert_eq!(drive.integrate(&start, &zero, &zero, 0.0).vel.velocity.x, 0.0);
+}
+
+#[test]
+fn test_covariance_matches_samples() {
+    let drive = DifferentialDrive::default();
+    let start = PoseWithCovariance::new(Pose::new(Point::new(0.0, 0.0), 0.3), Covariance::zeros());
+    let (left, right) = drive.wheel_motion(1.0, 0.4);
+    let predicted = drive.integrate_with_covariance(&start, &EncoderTicks::default(), &ticks(&drive, left, right));
+
+    // the wheels slip by a random distance with the modelled variance
+    let samples: Vec<Pose> = (0..20000)
+        .map(|_| {
+            let l = gaussian(left, (drive.wheel_noise * left.abs()).sqrt());
+            let r = gaussian(right, (drive.wheel_noise * right.abs()).sqrt());
+            drive.integrate(&Odometry::new(start.pose, Default::default()), &EncoderTicks::default(), &ticks(&drive, l, r), 1.0).pose
+        })
+        .collect();
+    let sampled = PoseWithCovariance::from_weighted_poses(&samples, &vec![1.0; samples.len()]);
+
+    assert!(predicted.pose.position.dist_to_point(sampled.pose.position) < 0.01);
+    for i in 0..3 {
+        let (p, s) = (predicted.covariance[(i, i)], sampled.covariance[(i, i)]);
+        assert!((p - s).abs() < 0.1 * s, "variance {}: predicted {}, sampled {}", i, p, s);
+    }
+
+    // the uncertainty only grows while the wheels turn
+    let still = drive.integrate_with_covariance(&predicted, &EncoderTicks::new(5, 5), &EncoderTicks::new(5, 5));
+    assert_eq!(still, predicted);
+    let further = drive.integrate_with_covariance(&predicted, &EncoderTicks::default(), &ticks(&drive, 1.0, 1.0));
+    assert!(further.covariance.trace() > predicted.covariance.trace());
+}
+
+#[test]
+fn test_simulated_encoders() {
+    use Direction::*;
+
+    let mut robot = Robot::default();
+    let drive = robot.encoders.drive;
+    let mut odom = Odometry::default();
+    let mut prev = robot.encoders.ticks();
+    assert_eq!(prev, EncoderTicks::default());
+
+    for &(dir, steps) in [(Forward, 10), (Left, 10), (Backward, 5), (Right, 3)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+            let curr = robot.encoders.ticks();
+            odom = drive.integrate(&odom, &prev, &curr, 1.0);
+            prev = curr;
+        }
+    }
+
+    // only the fractions of a tick are lost
+    assert!(odom.pose.position.dist_to_point(robot.odom.pose.position) < 0.01);
+    assert!((odom.pose.heading - robot.odom.pose.heading).abs() < 0.01);
+
+    // slipping wheels count a different distance than the one driven. Both wheels can end
+    // on the same tick by chance, but hardly every time
+    let distances: Vec<(f64, f64)> = (0..10)
+        .map(|_| {
+            let mut slipping = WheelEncoders::default();
+            slipping.slip = 0.001;
+            slipping.update(&Twist::new(Vector::new(1.0, 0.0), 0.0), 1.0);
+            slipping.drive.wheel_distances(&slipping.ticks())
+        })
+        .collect();
+    assert!(distances.iter().any(|(left, right)| left != right));
+    assert!(distances.iter().all(|(left, right)| (left - 1.0).abs() < 0.2 && (right - 1.0).abs() < 0.2));
+}
+
+#[test]
+fn test_ticks_to_map() {
+    use Direction::*;
+
+    let objects = l_shaped_room();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    // the calibrated wheels are slightly larger than the real ones
+    let drive = DifferentialDrive { wheel_radius: 1.1 * robot.encoders.drive.wheel_radius, ..robot.encoders.drive };
+    let mut particle_filter = ParticleFilter::default();
+    let mut filter_error = 0.0;
+    let mut odometry_error = 0.0;
+
+    let mut odom = Odometry::default();
+    let mut prev = robot.encoders.ticks();
+    particle_filter.cycle_odometry(&robot.laser_scanner.scan(&robot.odom.pose, &objects), &odom);
+
+    for &(dir, steps) in [(Forward, 8), (Left, 6), (Forward, 8)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+            let curr = robot.encoders.ticks();
+            odom = drive.integrate(&odom, &prev, &curr, 1.0);
+            prev = curr;
+
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+            particle_filter.cycle_odometry(&scan, &odom);
+            filter_error += particle_filter.best_particle.pose.position.dist_to_point(robot.odom.pose.position);
+            odometry_error += odom.pose.position.dist_to_point(robot.odom.pose.position);
+        }
+    }
+
+    // the position error accumulated along the trajectory
+    println!("filter error: {}, odometry error: {}", filter_error, odometry_error);
+    assert!(filter_error < odometry_error);
+
+    // the walls ended up in the map
+    let gridmap = &particle_filter.best_particle.gridmap;
+    let occupied: Vec<Point> = gridmap.get_all_occupied_cells().iter().map(|c| gridmap.map_to_world(c.x as i64, c.y as i64)).collect();
+    assert!(!occupied.is_empty());
+    let on_wall = occupied.iter().filter(|p| objects.iter().any(|l| distance_to_wall(l, **p) < 0.5)).count();
+    assert!(on_wall as f64 > 0.9 * occupied.len() as f64);
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use rand::Rng;
+use fastslam::gridmap::grid_map::{CellState, GridMap};
+use fastslam::gridmap::distance_field::DistanceField;
+use fastslam::odometry::pose::Pose;
+use fastslam::geometry::point::Point;
+use fastslam::math::scalar::PI;
+use fastslam::sensor::laserscanner::{Scan, Measurement};
+
+fn random_map(cell_size: f64) -> GridMap {
+    let mut rng = rand::thread_rng();
+    let mut grid = GridMap::new(cell_size);
+    let pose = Pose {
+        position: Point { x: 0.3, y: -0.7 },
+        heading: 0.0
+    };
+
+    let measurements = (0..72)
+        .map(|i| Measurement { angle: (i as f64) * PI/36.0, distance: rng.gen_range(1.0..6.0) })
+        .collect();
+    grid.update(&pose, &Scan { measurements });
+    grid
+}
+
+#[test]
+fn test_distance_field_matches_brute_force() {
+    let grid = random_map(0.25);
+    let field = DistanceField::new(&grid, 1.5);
+    let occupied = grid.get_all_occupied_cells();
+
+    let bounds = grid.bounds().unwrap();
+    for x in bounds.min_x - 10..=bounds.max_x + 10 {
+        for y in bounds.min_y - 10..=bounds.max_y + 10 {
+            let brute_force = occupied
+                .iter()
+                .map(|c| c.dist_to_point(Point::new(x as f64, y as f64)) * 0.25)
+                .fold(1.5, f64::min);
+
+            assert!((field.distance(x, y) - brute_force).abs() < 1e-9, "cell ({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_distance_field_lookup() {
+    let mut grid = GridMap::new(0.5);
+    let pose = Pose::default();
+    grid.update(&pose, &Scan { measurements: vec![Measurement { angle: 0.0, distance: 5.2 }] });
+    assert_eq!(grid.cell_state(10, 0), CellState::Occupied);
+
+    let field = DistanceField::new(&grid, 2.0);
+    assert_eq!(field.distance_at(Point::new(5.2, 0.1)), 0.0);
+    assert_eq!(field.distance_at(Point::new(5.2, 1.1)), 1.0);
+    assert_eq!(field.distance_at(Point::new(6.2, 1.1)), (2.0f64 * 2.0 + 2.0 * 2.0).sqrt() * 0.5);
+    assert_eq!(field.distance_at(Point::new(6.7, 1.6)), 2.0);
+    assert_eq!(field.distance_at(Point::new(100.0, 0.0)), 2.0);
+
+    let empty = DistanceField::new(&GridMap::new(0.5), 2.0);
+    assert_eq!(empty.distance(0, 0), 2.0);
+}
+
+#[test]
+fn test_distance_field_is_cached_until_map_changes() {
+    let mut grid = random_map(0.25);
+
+    let field = grid.distance_field();
+    assert!(Arc::ptr_eq(&field, &grid.distance_field()));
+
+    // clones share the cache as long as they are unchanged
+    let copy = grid.clone();
+    assert!(Arc::ptr_eq(&field, &copy.distance_field()));
+
+    let pose = Pose::new(Point::new(20.0, 20.0), 0.0);
+    grid.update(&pose, &Scan { measurements: vec![Measurement { angle: 0.0, distance: 0.5 }] });
+    let updated = grid.distance_field();
+    assert!(!Arc::ptr_eq(&field, &updated));
+    assert_eq!(updated.distance_at(Point::new(20.6, 20.1)), 0.0);
+    assert!(Arc::ptr_eq(&field, &copy.distance_field()));
+}
+
+#[test]
+fn test_distance_field_is_cached_per_max_distance() {
+    let grid = random_map(0.25);
+
+    let field = grid.distance_field_up_to(3.0);
+    assert_eq!(field.max_distance(), 3.0);
+    assert!(Arc::ptr_eq(&field, &grid.distance_field_up_to(3.0)));
+
+    // another max distance needs another field
+    let default = grid.distance_field();
+    assert_eq!(default.max_distance(), 2.0);
+    assert!(!Arc::ptr_eq(&field, &default));
+    assert!(Arc::ptr_eq(&default, &grid.distance_field()));
+}
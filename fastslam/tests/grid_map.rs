@@ -1,4 +1,4 @@
-use fastslam::gridmap::grid_map::{CellState, GridMap};
+use fastslam::gridmap::grid_map::{CellState, GridMap, LogOddsParams, MapBounds};
 use fastslam::odometry::pose::Pose;
 use fastslam::geometry::point::Point;
 use fastslam::math::scalar::{Scalar, PI};
@@ -8,8 +8,9 @@ use fastslam::sensor::laserscanner::{Scan, Measurement};
 #[test]
 fn test_initialize_grid() {
     let grid = GridMap::default();
-    let state = grid.cell_state(30, 30).unwrap();
-    assert_eq!(state, &CellState::Void);
+    let state = grid.cell_state(30, 30);
+    assert_eq!(state, CellState::Void);
+    assert_eq!(grid.bounds(), None);
 
     assert_eq!(grid.get_all_occupied_cells(), vec![]);
 }
@@ -17,7 +18,7 @@ fn test_initialize_grid() {
 #[test]
 fn test_updating_grid() {
 
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let pose = Pose {
         position: Point { x: 29.0, y: 29.0 },
@@ -35,27 +36,27 @@ fn test_updating_grid() {
     let mut scan = Scan { measurements };
     grid.update(&pose, &mut scan);
 
-    let mut state = grid.cell_state(99, 79).unwrap();
-    assert_eq!(state, &CellState::Occupied(1));
+    let mut state = grid.cell_state(49, 29);
+    assert_eq!(state, CellState::Occupied);
 
-    state = grid.cell_state(79, 99).unwrap();
-    assert_eq!(state, &CellState::Occupied(1));
+    state = grid.cell_state(29, 49);
+    assert_eq!(state, CellState::Occupied);
 
-    state = grid.cell_state(97, 79).unwrap();
-    assert_eq!(state, &CellState::Freespace);
+    state = grid.cell_state(47, 29);
+    assert_eq!(state, CellState::Freespace);
 
-    state = grid.cell_state(90, 79).unwrap();
-    assert_eq!(state, &CellState::Freespace);
+    state = grid.cell_state(40, 29);
+    assert_eq!(state, CellState::Freespace);
 
-    state = grid.cell_state(80, 80).unwrap();
-    assert_eq!(state, &CellState::Freespace);
+    state = grid.cell_state(30, 30);
+    assert_eq!(state, CellState::Freespace);
 
     println!("occupied: {:?}", grid.get_all_occupied_cells());
 }
 
 #[test]
 fn test_get_all_occupied_cells() {
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let pose = Pose {
         position: Point { x: 0.0, y: 0.0 },
@@ -68,6 +69,196 @@ fn test_get_all_occupied_cells() {
     let mut scan = Scan { measurements: meas};
     grid.update(&pose, &mut scan);
     let occupied_cells = grid.get_all_occupied_cells();
-    assert_eq!(occupied_cells.contains(&Point::new(50.0, 40.0)), true);
-    assert_eq!(occupied_cells.contains(&Point::new(60.0, 50.0)), true);
-}
\ No newline at end of file
+    assert_eq!(occupied_cells.contains(&Point::new(0.0, -10.0)), true);
+    assert_eq!(occupied_cells.contains(&Point::new(10.0, 0.0)), true);
+}
+
+#[test]
+fn test_occupancy_of_unobserved_cell() {
+    let grid = GridMap::new(1.0);
+    assert_eq!(grid.log_odds(10, 10), 0.0);
+    assert_eq!(grid.occupancy(10, 10), 0.5);
+    assert_eq!(grid.occupancy(-1000, 10), 0.5);
+}
+
+#[test]
+fn test_stray_hit_is_cleared_by_freespace() {
+    let mut grid = GridMap::new(1.0);
+
+    let pose = Pose {
+        position: Point { x: 0.0, y: 0.0 },
+        heading: 0.0
+    };
+
+    // a single stray reading makes the cell occupied
+    let stray = Scan { measurements: vec![Measurement { angle: 0.0, distance: 5.0 }] };
+    grid.update(&pose, &stray);
+    assert_eq!(grid.cell_state(5, 0), CellState::Occupied);
+    let p_hit = grid.occupancy(5, 0);
+
+    // beams passing through the cell afterwards lower the belief again
+    let through = Scan { measurements: vec![Measurement { angle: 0.0, distance: 10.0 }] };
+    grid.update(&pose, &through);
+    assert!(grid.occupancy(5, 0) < p_hit);
+
+    for _ in 0..3 {
+        grid.update(&pose, &through);
+    }
+    assert_eq!(grid.cell_state(5, 0), CellState::Freespace);
+    assert_eq!(grid.cell_state(10, 0), CellState::Occupied);
+}
+
+#[test]
+fn test_log_odds_are_clamped() {
+    let params = LogOddsParams::from_probabilities(0.9, 0.3, 0.2, 0.8).unwrap();
+    let mut grid = GridMap::with_params(1.0, params);
+
+    let pose = Pose {
+        position: Point { x: 0.0, y: 0.0 },
+        heading: 0.0
+    };
+    let scan = Scan { measurements: vec![Measurement { angle: 0.0, distance: 10.0 }] };
+    for _ in 0..20 {
+        grid.update(&pose, &scan);
+    }
+
+    assert!((grid.occupancy(10, 0) - 0.8).abs() < 1e-9);
+    assert!((grid.occupancy(5, 0) - 0.2).abs() < 1e-9);
+}
+
+#[test]
+fn test_log_odds_need_probabilities_between_zero_and_one() {
+    assert_eq!(LogOddsParams::from_probabilities(1.0, 0.3, 0.2, 0.8), None);
+    assert_eq!(LogOddsParams::from_probabilities(0.9, 0.0, 0.2, 0.8), None);
+    assert_eq!(LogOddsParams::from_probabilities(0.9, 0.3, -0.2, 0.8), None);
+    assert_eq!(LogOddsParams::from_probabilities(0.9, 0.3, 0.2, f64::NAN), None);
+    assert_eq!(LogOddsParams::from_probabilities(0.9, 0.3, 0.8, 0.2), None);
+
+    let params = LogOddsParams::from_probabilities(0.9, 0.3, 0.2, 0.8).unwrap();
+    assert!([params.hit, params.miss, params.min, params.max].iter().all(|l| l.is_finite()));
+}
+
+#[test]
+fn test_configurable_thresholds() {
+    let params = LogOddsParams::default();
+    assert_eq!(params.with_thresholds(0.5, 0.5), None);
+    assert_eq!(params.with_thresholds(0.7, 0.6), None);
+    assert_eq!(params.with_thresholds(-0.1, 0.6), None);
+    assert_eq!(params.with_thresholds(0.2, f64::NAN), None);
+
+    // a single hit is occupied with a lenient threshold, but not yet with a strict one
+    let pose = Pose {
+        position: Point { x: 0.0, y: 0.0 },
+        heading: 0.0
+    };
+    let scan = Scan { measurements: vec![Measurement { angle: 0.0, distance: 10.0 }] };
+    let mut lenient = GridMap::with_params(1.0, params.with_thresholds(0.4, 0.6).unwrap());
+    let mut strict = GridMap::with_params(1.0, params.with_thresholds(0.1, 0.9).unwrap());
+    lenient.update(&pose, &scan);
+    strict.update(&pose, &scan);
+
+    assert_eq!(lenient.cell_state(10, 0), CellState::Occupied);
+    assert_eq!(lenient.cell_state(5, 0), CellState::Freespace);
+    assert_eq!(strict.cell_state(10, 0), CellState::Void);
+    assert_eq!(strict.cell_state(5, 0), CellState::Void);
+}
+
+#[test]
+fn test_beams_beyond_max_range_are_skipped() {
+    let pose = Pose {
+        position: Point { x: 0.0, y: 0.0 },
+        heading: 0.0
+    };
+    let scan = Scan { measurements: vec![
+        Measurement { angle: 0.0, distance: 1e12 },
+        Measurement { angle: PI/2.0, distance: 10.0 },
+    ] };
+
+    let mut grid = GridMap::new(1.0);
+    grid.update(&pose, &scan);
+    assert_eq!(grid.bounds(), Some(MapBounds { min_x: 0, min_y: 0, max_x: 0, max_y: 10 }));
+    assert_eq!(grid.cell_state(0, 10), CellState::Occupied);
+
+    let mut short = GridMap::with_params(1.0, LogOddsParams::default().with_max_range(5.0).unwrap());
+    short.update(&pose, &scan);
+    assert_eq!(short.bounds(), None);
+
+    assert_eq!(LogOddsParams::default().with_max_range(0.0), None);
+    assert_eq!(LogOddsParams::default().with_max_range(f64::NAN), None);
+}
+
+#[test]
+fn test_map_grows_beyond_initial_area() {
+    let mut grid = GridMap::new(0.25);
+
+    let pose = Pose {
+        position: Point { x: 1000.0, y: -500.0 },
+        heading: PI/2.0
+    };
+    let scan = Scan { measurements: vec![Measurement { angle: 0.0, distance: 30.0 }] };
+    grid.update(&pose, &scan);
+
+    let end = grid.world_to_map(Point::new(1000.0, -470.0)).unwrap();
+    assert_eq!(end, (4000, -1880));
+    assert_eq!(grid.cell_state(end.0, end.1), CellState::Occupied);
+    assert_eq!(grid.cell_state(4000, -1900), CellState::Freespace);
+    assert_eq!(grid.get_all_occupied_cells(), vec![Point::new(4000.0, -1880.0)]);
+}
+
+#[test]
+fn test_bounds_of_explored_area() {
+    let mut grid = GridMap::new(0.5);
+
+    let pose = Pose {
+        position: Point { x: 0.25, y: 0.25 },
+        heading: 0.0
+    };
+    let meas = vec![
+        Measurement { angle: 0.0, distance: 10.0 },
+        Measurement { angle: PI/2.0, distance: 5.0 },
+        Measurement { angle: PI, distance: 20.0 },
+    ];
+    grid.update(&pose, &Scan { measurements: meas });
+
+    let bounds = grid.bounds().unwrap();
+    assert_eq!(bounds, MapBounds { min_x: -40, min_y: 0, max_x: 20, max_y: 10 });
+    assert_eq!(bounds.width(), 61);
+    assert_eq!(bounds.height(), 11);
+    assert!(bounds.contains(0, 0));
+    assert!(!bounds.contains(0, -1));
+
+    let (lower, upper) = grid.world_bounds().unwrap();
+    assert_eq!(lower, Point::new(-20.0, 0.0));
+    assert_eq!(upper, Point::new(10.5, 5.5));
+
+    grid.clear();
+    assert_eq!(grid.bounds(), None);
+}
+
+#[test]
+fn test_cloned_map_shares_tiles_until_written() {
+    let mut grid = GridMap::new(1.0);
+
+    let pose = Pose {
+        position: Point { x: 0.5, y: 0.5 },
+        heading: 0.0
+    };
+    let meas = vec![
+        Measurement { angle: 0.0, distance: 100.0 },
+        Measurement { angle: PI/2.0, distance: 100.0 },
+    ];
+    grid.update(&pose, &Scan { measurements: meas });
+
+    let tiles = grid.tile_count();
+    let mut copy = grid.clone();
+    assert_eq!(copy.shared_tile_count(&grid), tiles);
+
+    // writing into the copy only duplicates the tile that is written to
+    let scan = Scan { measurements: vec![Measurement { angle: 0.0, distance: 3.0 }] };
+    copy.update(&pose, &scan);
+    assert_eq!(copy.shared_tile_count(&grid), tiles - 1);
+
+    // the original map is unaffected by the write
+    assert_eq!(copy.cell_state(3, 0), CellState::Occupied);
+    assert_eq!(grid.cell_state(3, 0), CellState::Freespace);
+}
@@ -0,0 +1,279 @@
+use fastslam::geometry::{Point, Vector};
+use fastslam::math::scalar::PI;
+use fastslam::odometry::{DifferentialDrive, MotionModel, Odometry, Pose, PoseWithCovariance, Twist};
+use fastslam::particlefilter::config::FastSlamConfig;
+use fastslam::particlefilter::particle_filter::{InputError, ParticleFilter};
+use fastslam::particlefilter::probabilistic_models::{log_motion_model_gyro, log_motion_model_gyro_with_noise, log_motion_model_odometry_gyro, motion_model_gyro, motion_model_odometry_gyro};
+use fastslam::sensor::stamped::Stamped;
+use fastslam::simulator::{Direction, Gyro, Robot};
+
+mod common;
+use common::l_shaped_room;
+
+struct Model;
+
+impl MotionModel for Model {}
+
+#[test]
+fn test_drive_gyro() {
+    let start = Pose::new(Point::new(1.0, 2.0), 0.5 * PI);
+    let gain = Twist::new(Vector::new(0.5, 0.0), 0.0);
+
+    // the angular velocity of the gain is ignored, the gyro turns the robot
+    let straight = Model::drive_gyro(&start, &Twist::new(Vector::new(0.5, 0.0), 0.3), 0.0, 2.0);
+    assert!(straight.position.dist_to_point(Point::new(1.0, 3.0)) < 1e-12);
+    assert!((straight.heading - 0.5 * PI).abs() < 1e-12);
+
+    let on_the_spot = Model::drive_gyro(&start, &Twist::default(), -0.25 * PI, 2.0);
+    assert!(on_the_spot.position.dist_to_point(start.position) < 1e-12);
+    assert!(on_the_spot.heading.abs() < 1e-12);
+
+    // moves along the heading halfway through the rotation
+    let arc = Model::drive_gyro(&start, &gain, 0.25 * PI, 2.0);
+    let heading = 0.75 * PI;
+    assert!(arc.position.dist_to_point(Point::new(1.0 + heading.cos(), 2.0 + heading.sin())) < 1e-12);
+    assert!((arc.heading - PI).abs() < 1e-12);
+}
+
+#[test]
+fn test_sample_motion_model_gyro() {
+    let start = Pose::new(Point::new(1.0, 2.0), 0.3);
+    let gain = Twist::new(Vector::new(1.0, 0.0), 0.0);
+    let (yaw_rate, dt) = (0.2, 2.0);
+    let expected = Model::drive_gyro(&start, &gain, yaw_rate, dt);
+
+    let exact = Model::sample_motion_model_gyro_with_noise(&start, &gain, yaw_rate, dt, &[0.0; 4]);
+    assert!(exact.position.dist_to_point(expected.position) < 1e-12);
+    assert!((exact.heading - expected.heading).abs() < 1e-12);
+
+    // the wheels and the gyro have their own noise
+    let alpha = [0.01, 0.0, 0.001, 0.0];
+    let samples: Vec<Pose> = (0..20000).map(|_| Model::sample_motion_model_gyro_with_noise(&start, &gain, yaw_rate, dt, &alpha)).collect();
+    let sampled = PoseWithCovariance::from_weighted_poses(&samples, &vec![1.0; samples.len()]);
+    assert!(sampled.pose.position.dist_to_point(expected.position) < 0.01);
+    assert!((sampled.pose.heading - expected.heading).abs() < 0.01);
+
+    let var_heading = alpha[2] * dt;
+    assert!((sampled.covariance[(2, 2)] - var_heading).abs() < 0.1 * var_heading);
+    // the position spreads by the wheel noise and along the arc by the heading noise
+    let var_position = alpha[0] * (gain.velocity.x * dt).powi(2);
+    let trace = sampled.covariance[(0, 0)] + sampled.covariance[(1, 1)];
+    assert!(trace > 2.0 * var_position && trace < 2.0 * var_position + 4.0 * var_heading);
+}
+
+#[test]
+fn test_motion_model_gyro() {
+    let prev = Pose::new(Point::new(3.0, 3.0), -0.5 * PI);
+    let gain = Twist::new(Vector::new(0.5, 0.0), 0.0);
+    let (yaw_rate, dt) = (0.1, 1.0);
+    let expected = Model::drive_gyro(&prev, &gain, yaw_rate, dt);
+
+    let log_p = |x: f64, y: f64, heading: f64| {
+        let pose = Pose::new(Point::new(expected.position.x + x, expected.position.y + y), expected.heading + heading);
+        log_motion_model_gyro(&pose, &prev, &gain, yaw_rate, dt)
+    };
+    let best = log_p(0.0, 0.0, 0.0);
+    assert!(best.is_finite());
+    for &(x, y, heading) in [(0.02, 0.0, 0.0), (0.0, 0.02, 0.0), (0.0, 0.0, 0.02), (-0.05, 0.05, -0.05)].iter() {
+        assert!(log_p(x, y, heading) < best);
+    }
+    assert!((motion_model_gyro(&expected, &prev, &gain, yaw_rate, dt).ln() - best).abs() < 1e-9);
+
+    // a precise gyro is sure about the heading, the wheels about nothing
+    let alpha = [1.0, 1.0, 1e-6, 0.0];
+    let log_p = |x: f64, heading: f64| {
+        let pose = Pose::new(Point::new(expected.position.x + x, expected.position.y), expected.heading + heading);
+        log_motion_model_gyro_with_noise(&pose, &prev, &gain, yaw_rate, dt, &alpha)
+    };
+    assert!(log_p(0.0, 0.01) < log_p(0.1, 0.0));
+}
+
+#[test]
+fn test_odometry_gyro_motion_model() {
+    let start = Pose::new(Point::new(1.0, 2.0), 0.3);
+    let (yaw_rate, dt) = (0.2, 2.0);
+
+    // the odometry moved 1m forward and thinks it turned by 0.5 rad, only its distance is used
+    let prev_odom = Pose::new(Point::new(5.0, -1.0), 0.0);
+    let curr_odom = Pose::new(Point::new(5.6, -0.2), 0.5);
+    let expected = Model::drive_gyro(&start, &Twist::new(Vector::new(0.5, 0.0), 0.0), yaw_rate, dt);
+    let driven = Model::drive_odometry_gyro(&start, &prev_odom, &curr_odom, yaw_rate, dt);
+    assert!(driven.position.dist_to_point(expected.position) < 1e-12);
+    assert!((driven.heading - expected.heading).abs() < 1e-12);
+
+    let exact = Model::sample_motion_model_odometry_gyro_with_noise(&start, &prev_odom, &curr_odom, yaw_rate, dt, &[0.0; 4]);
+    assert!(exact.position.dist_to_point(expected.position) < 1e-12);
+    assert!((exact.heading - expected.heading).abs() < 1e-12);
+
+    // reversing is a negative distance
+    let reversed = Model::drive_odometry_gyro(&start, &curr_odom, &prev_odom, yaw_rate, dt);
+    let expected_reversed = Model::drive_gyro(&start, &Twist::new(Vector::new(-0.5, 0.0), 0.0), yaw_rate, dt);
+    assert!(reversed.position.dist_to_point(expected_reversed.position) < 1e-12);
+
+    let log_p = |x: f64, heading: f64| {
+        let pose = Pose::new(Point::new(expected.position.x + x, expected.position.y), expected.heading + heading);
+        log_motion_model_odometry_gyro(&pose, &start, &prev_odom, &curr_odom, yaw_rate, dt)
+    };
+    let best = log_p(0.0, 0.0);
+    assert!(best.is_finite());
+    assert!(log_p(0.02, 0.0) < best && log_p(0.0, 0.02) < best);
+    assert!((motion_model_odometry_gyro(&expected, &start, &prev_odom, &curr_odom, yaw_rate, dt).ln() - best).abs() < 1e-9);
+}
+
+#[test]
+fn test_simulated_gyro() {
+    // a constant bias
+    let mut gyro = Gyro { bias: 0.01, bias_walk: 0.0, noise: 0.0 };
+    assert_eq!(gyro.measure(0.5, 1.0), 0.51);
+    assert_eq!(gyro.bias, 0.01);
+
+    // the bias walks away with a variance growing with time
+    let biases: Vec<f64> = (0..2000)
+        .map(|_| {
+            let mut gyro = Gyro { bias: 0.0, bias_walk: 0.01, noise: 0.0 };
+            (0..25).for_each(|_| { gyro.measure(0.0, 4.0); });
+            gyro.bias
+        })
+        .collect();
+    let variance = biases.iter().map(|b| b.powi(2)).sum::<f64>() / biases.len() as f64;
+    let expected = 0.01f64.powi(2) * 100.0;
+    assert!((variance - expected).abs() < 0.1 * expected, "variance {}, expected {}", variance, expected);
+
+    // white noise averages out
+    let mut gyro = Gyro { bias: 0.0, bias_walk: 0.0, noise: 0.01 };
+    let mean = (0..10000).map(|_| gyro.measure(-0.2, 1.0)).sum::<f64>() / 10000.0;
+    assert!((mean + 0.2).abs() < 0.001);
+
+    // the robot reads the gyro on every move
+    let mut robot = Robot::default();
+    robot.gyro = Gyro { bias: 0.001, bias_walk: 0.0, noise: 0.0 };
+    robot.move_forward(Some(Direction::Left));
+    assert!((robot.latest_yaw_rate - robot.latest_gain.angular - 0.001).abs() < 1e-12);
+}
+
+#[test]
+fn test_particle_filter_with_gyro() {
+    use Direction::*;
+
+    let objects = l_shaped_room();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    // pose samples well inside the spread of the motion model, so the proposal follows the scan match
+    let config = FastSlamConfig::builder().sampling_range(0.01).build().unwrap();
+    let mut particle_filter = ParticleFilter::new(config).unwrap();
+    let mut dead_reckoning = Pose::default();
+    let mut filter_error = 0.0;
+    let mut odometry_error = 0.0;
+
+    for &(dir, steps) in [(Forward, 8), (Left, 8), (Forward, 8)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+
+            // the wheels slip while turning and overestimate the rotation
+            let gain = Twist::new(robot.latest_gain.velocity, robot.latest_gain.angular * 1.5);
+            dead_reckoning = Robot::drive(&dead_reckoning, &gain, 1.0);
+
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+            particle_filter.cycle_with_gyro(&scan, &gain, Some(robot.latest_yaw_rate));
+            filter_error += particle_filter.best_particle.pose.position.dist_to_point(robot.odom.pose.position);
+            odometry_error += dead_reckoning.position.dist_to_point(robot.odom.pose.position);
+        }
+    }
+
+    // the position error accumulated along the trajectory
+    println!("filter error: {}, odometry error: {}", filter_error, odometry_error);
+    assert!(filter_error < odometry_error);
+
+    // the heading follows the gyro, not the wheels
+    let heading_error = |pose: &Pose| Robot::wrap_heading(pose.heading - robot.odom.pose.heading).abs();
+    println!("filter heading error: {}, odometry heading error: {}", heading_error(&particle_filter.best_particle.pose), heading_error(&dead_reckoning));
+    assert!(heading_error(&particle_filter.best_particle.pose) < 0.5 * heading_error(&dead_reckoning));
+}
+
+#[test]
+fn test_stamped_gyro() {
+    let robot = Robot::default();
+    let scan = robot.laser_scanner.scan(&robot.odom.pose, &[]);
+    let stamped_scan = |t: f64| Stamped::new(t, scan.clone());
+    let gain = |t: f64| Stamped::new(t, Twist::new(Vector::new(0.1, 0.0), 0.0));
+
+    let mut particle_filter = ParticleFilter::default();
+    assert_eq!(particle_filter.cycle_with_gyro_stamped(&stamped_scan(1.0), &gain(1.0), None), Ok(0.0));
+
+    // the yaw rate has to be measured around the time of the scan, like the gain
+    assert_eq!(
+        particle_filter.cycle_with_gyro_stamped(&stamped_scan(2.0), &gain(2.0), Some(&Stamped::new(1.0, 0.1))),
+        Err(InputError::Stale { input: 1.0, scan: 2.0 })
+    );
+    assert_eq!(
+        particle_filter.cycle_with_gyro_stamped(&stamped_scan(1.0), &gain(1.0), Some(&Stamped::new(1.0, 0.1))),
+        Err(InputError::OutOfOrder { timestamp: 1.0, previous: 1.0 })
+    );
+
+    // the robot turns with the gyro for the time between the scans
+    assert_eq!(particle_filter.cycle_with_gyro_stamped(&stamped_scan(3.0), &gain(3.0), Some(&Stamped::new(2.9, 0.1))), Ok(2.0));
+    assert!((particle_filter.estimate.pose.heading - 0.2).abs() < 0.05);
+}
+
+#[test]
+fn test_particle_filter_with_odometry_and_gyro() {
+    use Direction::*;
+
+    let objects = l_shaped_room();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    // the calibrated track is narrower than the real one, the odometry overestimates every turn
+    let drive = DifferentialDrive { track_width: 0.6 * robot.encoders.drive.track_width, ..robot.encoders.drive };
+    let mut particle_filter = ParticleFilter::default();
+    let mut filter_error = 0.0;
+    let mut odometry_error = 0.0;
+
+    let mut odom = Odometry::default();
+    let mut prev = robot.encoders.ticks();
+    particle_filter.cycle_odometry_with_gyro(&robot.laser_scanner.scan(&robot.odom.pose, &objects), &odom, None);
+
+    for &(dir, steps) in [(Forward, 8), (Left, 8), (Forward, 8)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+            let curr = robot.encoders.ticks();
+            odom = drive.integrate(&odom, &prev, &curr, 1.0);
+            prev = curr;
+
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+            particle_filter.cycle_odometry_with_gyro(&scan, &odom, Some(robot.latest_yaw_rate));
+            filter_error += particle_filter.best_particle.pose.position.dist_to_point(robot.odom.pose.position);
+            odometry_error += odom.pose.position.dist_to_point(robot.odom.pose.position);
+        }
+    }
+
+    // the position error accumulated along the trajectory
+    println!("filter error: {}, odometry error: {}", filter_error, odometry_error);
+    assert!(filter_error < odometry_error);
+
+    // the heading follows the gyro, not the encoders
+    let heading_error = |pose: &Pose| Robot::wrap_heading(pose.heading - robot.odom.pose.heading).abs();
+    println!("filter heading error: {}, odometry heading error: {}", heading_error(&particle_filter.best_particle.pose), heading_error(&odom.pose));
+    assert!(heading_error(&particle_filter.best_particle.pose) < 0.5 * heading_error(&odom.pose));
+}
+
+#[test]
+fn test_stamped_odometry_with_gyro() {
+    let robot = Robot::default();
+    let scan = Stamped::new(1.0, robot.laser_scanner.scan(&robot.odom.pose, &[]));
+    let odometry = |t: f64, x: f64| Stamped::new(t, Odometry::new(Pose::new(Point::new(x, 0.0), 0.0), Twist::default()));
+
+    let mut particle_filter = ParticleFilter::default();
+    assert_eq!(particle_filter.cycle_odometry_with_gyro_stamped(&scan, &odometry(1.0, 0.0), Some(&Stamped::new(1.0, 0.1))), Ok(0.0));
+    // the first reading does not move the robot, not even with the gyro
+    assert!(particle_filter.estimate.pose.heading.abs() < 1e-9);
+
+    assert_eq!(
+        particle_filter.cycle_odometry_with_gyro_stamped(&Stamped::new(3.0, scan.data.clone()), &odometry(3.0, 0.2), Some(&Stamped::new(1.5, 0.1))),
+        Err(InputError::Stale { input: 1.5, scan: 3.0 })
+    );
+
+    // the robot moves the distance of the odometry and turns with the gyro between the scans
+    assert_eq!(particle_filter.cycle_odometry_with_gyro_stamped(&Stamped::new(3.0, scan.data.clone()), &odometry(3.0, 0.2), Some(&Stamped::new(2.9, 0.1))), Ok(2.0));
+    assert!((particle_filter.estimate.pose.heading - 0.2).abs() < 0.05);
+    assert!((particle_filter.estimate.pose.position.x - 0.2).abs() < 0.05);
+}
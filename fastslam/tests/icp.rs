@@ -1,7 +1,7 @@
 use nalgebra as na;
 use na::{MatrixXx2};
 use fastslam::geometry::Point;
-use fastslam::scanmatching::icp::{to_na_homogeneous, icp};
+use fastslam::scanmatching::icp::{to_na_homogeneous, icp, icp_with_guess, correct_pose, IcpParams};
 use fastslam::pointcloud::PointCloud;
 use fastslam::odometry::Pose;
 use fastslam::math::utils::pose_relative_eq;
@@ -43,7 +43,7 @@ fn test_icp_case_0() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&A, &B, 1, 0.000001);
+    let pose_dif = icp(&A, &B, 1, 0.000001).transform;
 }
 
 #[test]
@@ -89,7 +89,7 @@ fn test_icp_case_1() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&A, &B, 100, 0.000001);
+    let pose_dif = icp(&A, &B, 100, 0.000001).transform;
     let pose_dif_expected = Pose::new(
         Point { x: 7.729003210378913, y: 2.9526877942756187 },
         0.011227837265104052 );
@@ -141,7 +141,7 @@ fn test_icp_case_2() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&B, &A, 100, 0.000001);
+    let pose_dif = icp(&B, &A, 100, 0.000001).transform;
     let pose_dif_expected = Pose::new(
         Point { x: 2.8121879293507206, y: 16.15395993803767 },
         -0.04966336822821934);
@@ -191,7 +191,7 @@ fn test_icp_case_3() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&B, &A, 100, 0.000001);
+    let pose_dif = icp(&B, &A, 100, 0.000001).transform;
     let pose_dif_expected = Pose::new(
         Point { x: 20.129471360126942, y: 47.34280242686634 },
         -0.18743341847319145 );
@@ -242,7 +242,7 @@ fn test_icp_case_4() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&A, &B, 100, 0.000000000000001);
+    let pose_dif = icp(&A, &B, 100, 0.000000000000001).transform;
 
     let pose_dif_expected = Pose::new(
         Point { x: -1.8648786600632477, y: -1.8806433339899442 },
@@ -303,7 +303,7 @@ fn test_icp_case_5() {
     let A = PointCloud::new(a);
     let B = PointCloud::new(b);
 
-    let pose_dif = icp(&A, &B, 100, 0.00001);
+    let pose_dif = icp(&A, &B, 100, 0.00001).transform;
 
     let pose_dif_expected = Pose::new(
         Point { x: -176.46623804632895, y: 24.5001641879306 },
@@ -339,11 +339,91 @@ fn test_icp_case_6() {
     let B = PointCloud::new(b);
 
 
-    let pose_dif = icp(&A, &B, 20, 0.00001);
+    let pose_dif = icp(&A, &B, 20, 0.00001).transform;
 
     let pose_dif_expected = Pose::new(
         Point { x: -10.729149492301724, y: 5.939548258818714 },
         0.040479440439105514);
 
     assert_eq!(pose_relative_eq(pose_dif, pose_dif_expected, 1.0e-1), true);
-}
\ No newline at end of file
+}
+
+/// an L-shaped corner in the frame of the sensor, sampled densely so nearest neighbors are
+/// unambiguous
+fn corner() -> PointCloud {
+    PointCloud::new(
+        (0..40)
+            .map(|i| Point::new(i as f64 * 0.1, 0.0))
+            .chain((1..40).map(|i| Point::new(0.0, i as f64 * 0.1)))
+            .collect()
+    )
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_icp_result_diagnostics() {
+    let A = corner();
+    let motion = Pose::new(Point::new(0.03, -0.02), 0.02);
+    let B = A.transform(&motion);
+
+    let result = icp(&A, &B, 50, 1.0e-12);
+    assert!(result.converged);
+    assert!(result.iterations > 1 && result.iterations <= 50);
+    assert_eq!(result.inliers, A.size());
+    assert!(pose_relative_eq(result.transform, motion, 1.0e-3));
+    assert!(result.initial_residual > 0.01);
+    assert!(result.final_residual < 1.0e-3);
+    assert!(result.covariance.iter().all(|v| v.is_finite()));
+    assert!((0..3).all(|i| result.covariance[(i, i)] >= 0.0 && result.covariance[(i, i)] < 1.0e-6));
+
+    // stopping after a single iteration does not count as convergence
+    let result = icp(&A, &B, 1, 1.0e-12);
+    assert_eq!(result.iterations, 1);
+    assert!(!result.converged);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_icp_with_initial_guess() {
+    let A = corner();
+    let pose = Pose::new(Point::new(2.0, -1.0), 0.8);
+    let B = A.transform(&pose);
+    let params = IcpParams { max_iterations: 50, tolerance: 1.0e-12, ..IcpParams::default() };
+
+    // the motion is too large to be found from the identity
+    assert!(!pose_relative_eq(icp(&A, &B, 50, 1.0e-12).transform, pose, 1.0e-3));
+
+    // seeded with odometry, icp only has to find the small remaining correction
+    let guess = Pose::new(Point::new(2.03, -0.98), 0.79);
+    let result = icp_with_guess(&A, &B, &guess, &params);
+    assert!(result.converged);
+    assert!(pose_relative_eq(result.transform, pose, 1.0e-3));
+    assert!(pose_relative_eq(correct_pose(&guess, &result.correction), result.transform, 1.0e-9));
+    assert!(result.correction.position.to_vec().length() < 0.1);
+
+    // a perfect guess needs no correction
+    let result = icp_with_guess(&A, &B, &pose, &params);
+    assert!(pose_relative_eq(result.correction, Pose::default(), 1.0e-9));
+    assert!(result.initial_residual < 1.0e-9);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_icp_correction_far_from_origin() {
+    let A = corner();
+    let pose = Pose::new(Point::new(20.0, 5.0), 0.3);
+    let B = A.transform(&pose);
+    let params = IcpParams { max_iterations: 50, tolerance: 1.0e-12, ..IcpParams::default() };
+
+    // a small heading error of the guess is a small correction in the frame of the guess,
+    // even though the guess is far from the origin
+    let guess = Pose::new(Point::new(20.02, 4.99), 0.35);
+    let result = icp_with_guess(&A, &B, &guess, &params);
+    assert!(result.converged);
+    assert!(pose_relative_eq(result.transform, pose, 1.0e-3));
+    assert!(pose_relative_eq(guess.compose(&result.correction), result.transform, 1.0e-9));
+    assert!(pose_relative_eq(correct_pose(&guess, &result.correction), result.transform, 1.0e-9));
+    assert!(pose_relative_eq(result.correction, pose.relative_to(&guess), 1.0e-3));
+    assert!(result.correction.position.to_vec().length() < 0.05);
+    assert!((result.correction.heading + 0.05).abs() < 1.0e-3);
+}
@@ -0,0 +1,49 @@
+use rand::Rng;
+use fastslam::geometry::Point;
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::kdtree::KdTree;
+
+fn random_pointcloud(n: usize) -> PointCloud {
+    let mut rng = rand::thread_rng();
+    PointCloud::new(
+        (0..n)
+            .map(|_| Point::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0)))
+            .collect()
+    )
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_kdtree_matches_brute_force() {
+    let A = random_pointcloud(500);
+    let B = random_pointcloud(360);
+    let tree = KdTree::new(&B);
+    assert_eq!(tree.size(), 360);
+
+    let (distances, indices) = tree.query(&A);
+    assert_eq!(distances.len(), A.size());
+
+    for (i, p) in A.iter().enumerate() {
+        let brute_force = B
+            .iter()
+            .map(|q| p.dist_to_point(*q))
+            .fold(f64::INFINITY, f64::min);
+
+        assert!((distances[i] - brute_force).abs() < 1e-12);
+        assert!((p.dist_to_point(B.get(indices[i])) - brute_force).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_kdtree_nearest() {
+    let cloud = PointCloud::new(vec![
+        Point::new(0.0, 0.0),
+        Point::new(1.0, 0.0),
+        Point::new(5.0, 5.0),
+    ]);
+    let tree = KdTree::new(&cloud);
+
+    assert_eq!(tree.nearest(Point::new(0.9, 0.2)).map(|(_, i)| i), Some(1));
+    assert_eq!(tree.nearest(Point::new(4.0, 8.0)), Some((10.0f64.sqrt(), 2)));
+    assert_eq!(KdTree::new(&PointCloud::empty()).nearest(Point::new(0.0, 0.0)), None);
+}
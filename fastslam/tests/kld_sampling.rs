@@ -0,0 +1,93 @@
+use fastslam::geometry::Point;
+use fastslam::gridmap::grid_map::GridMap;
+use fastslam::odometry::Pose;
+use fastslam::particlefilter::config::FastSlamConfig;
+use fastslam::particlefilter::kld_sampling::{kld_resample, kld_sample_count, KldParams};
+use fastslam::particlefilter::particle::Particle;
+use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::simulator::{Robot, Direction};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+mod common;
+use common::rectangular_room;
+
+fn particles_at(poses: &[Pose]) -> Vec<Particle> {
+    poses.iter().map(|pose| Particle::new(*pose, 1.0, GridMap::default())).collect()
+}
+
+#[test]
+fn test_kld_sample_count() {
+    assert_eq!(kld_sample_count(0, 0.05, 0.01), 0);
+    assert_eq!(kld_sample_count(1, 0.05, 0.01), 0);
+
+    // (k - 1) / (2 epsilon) * (1 - 2 / (9 (k - 1)) + sqrt(2 / (9 (k - 1))) z)^3 with z = 2.326
+    let expected = 10.0 * (1.0 - 2.0 / 9.0 + (2.0f64 / 9.0).sqrt() * 2.326).powi(3);
+    assert!((kld_sample_count(2, 0.05, 0.01) as f64 - expected).abs() <= 1.0);
+
+    // more bins, a tighter bound or more confidence need more particles
+    let counts: Vec<usize> = (2..100).map(|k| kld_sample_count(k, 0.05, 0.01)).collect();
+    assert!(counts.windows(2).all(|c| c[0] < c[1]));
+    assert!(kld_sample_count(10, 0.01, 0.01) > kld_sample_count(10, 0.05, 0.01));
+    assert!(kld_sample_count(10, 0.05, 0.001) > kld_sample_count(10, 0.05, 0.01));
+
+    // for many bins about (k - 1) / (2 epsilon) particles are needed
+    let k = 10000;
+    let ratio = kld_sample_count(k, 0.05, 0.01) as f64 / ((k - 1) as f64 / 0.1);
+    assert!(ratio > 1.0 && ratio < 1.1);
+}
+
+#[test]
+fn test_kld_resample_shrinks_and_grows() {
+    let params = KldParams::default();
+    let mut rng = StdRng::seed_from_u64(1);
+
+    // all particles in one bin
+    let concentrated = particles_at(&vec![Pose::new(Point::new(1.1, 2.2), 0.3); 100]);
+    let resampled = kld_resample(&concentrated, &params, &mut rng);
+    assert_eq!(resampled.len(), params.min_particles);
+    assert!(resampled.iter().all(|p| (p.weight - 1.0 / params.min_particles as f64).abs() < 1e-12));
+
+    // particles in 5 bins
+    let five: Vec<Pose> = (0..100).map(|i| Pose::new(Point::new((i % 5) as f64, 0.0), 0.0)).collect();
+    let resampled = kld_resample(&particles_at(&five), &params, &mut rng);
+    assert_eq!(resampled.len(), kld_sample_count(5, params.epsilon, params.delta));
+
+    // spread over the whole room, limited by the maximum
+    let spread: Vec<Pose> = (0..1000).map(|i| Pose::new(Point::new((i % 40) as f64, (i / 40) as f64), 0.0)).collect();
+    let resampled = kld_resample(&particles_at(&spread), &params, &mut rng);
+    assert_eq!(resampled.len(), params.max_particles);
+
+    // particles without weight are not drawn and their bins do not count
+    let mut particles = particles_at(&five);
+    particles.iter_mut().filter(|p| p.pose.position.x != 0.0).for_each(|p| p.set_weight(0.0));
+    let resampled = kld_resample(&particles, &params, &mut rng);
+    assert_eq!(resampled.len(), params.min_particles);
+    assert!(resampled.iter().all(|p| p.pose.position.x == 0.0));
+
+    assert!(kld_resample(&[], &params, &mut rng).is_empty());
+}
+
+#[test]
+fn test_particle_filter_with_kld_sampling() {
+    let walls = rectangular_room();
+    let mut robot = Robot::default();
+    // without a spread of the proposal all particles draw the scan-matched pose
+    let config = FastSlamConfig::builder()
+        .kld(Some(KldParams { min_particles: 5, ..KldParams::default() }))
+        .n_pose_samples(1)
+        .sampling_range(0.0)
+        .build()
+        .unwrap();
+    let mut particle_filter = ParticleFilter::new(config).unwrap();
+    assert_eq!(particle_filter.n_particles(), 25);
+
+    // all particles start at the same pose and move the same, a few of them are enough
+    for _ in 0..3 {
+        robot.move_forward(Some(Direction::Forward));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &walls);
+        particle_filter.cycle(&scan, &robot.latest_gain.clone());
+        assert_eq!(particle_filter.n_particles(), 5);
+        assert!((particle_filter.weights().iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+}
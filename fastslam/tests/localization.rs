@@ -0,0 +1,267 @@
+use std::fs;
+use fastslam::geometry::{Point, Vector};
+use fastslam::gridmap::grid_map::{CellState, GridMap};
+use fastslam::gridmap::ros_map::load_ros_map;
+use fastslam::math::scalar::wrap_angle;
+use fastslam::odometry::{Covariance, MotionModel, Odometry, Pose, PoseWithCovariance, Twist};
+use fastslam::particlefilter::config::FastSlamConfig;
+use fastslam::particlefilter::localization::{LocalizationError, MonteCarloLocalization};
+use fastslam::particlefilter::particle_filter::InputError;
+use fastslam::sensor::stamped::Stamped;
+use fastslam::simulator::{Direction, Robot};
+
+mod common;
+use common::{distance_to_wall, l_shaped_room_with_box};
+
+const RESOLUTION: f64 = 0.1;
+const ORIGIN: (f64, f64) = (-5.0, -4.0);
+const WIDTH: u32 = 140;
+const HEIGHT: u32 = 100;
+
+fn inside(p: Point) -> bool {
+    let in_room = (p.x > -4.0 && p.x < 8.0 && p.y > -3.0 && p.y < 3.5) || (p.x > 2.0 && p.x < 8.0 && p.y > -3.0 && p.y < 5.0);
+    let in_box = p.x > 4.0 && p.x < 5.0 && p.y > 0.0 && p.y < 1.5;
+    in_room && !in_box
+}
+
+/// the room as a map_server PNG map, loaded like a map saved by a previous SLAM run
+fn known_map() -> GridMap {
+    let walls = l_shaped_room_with_box();
+    let pixels: Vec<u8> = (0..HEIGHT)
+        .flat_map(|row| (0..WIDTH).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            let p = Point::new(
+                ORIGIN.0 + (col as f64 + 0.5) * RESOLUTION,
+                ORIGIN.1 + ((HEIGHT - 1 - row) as f64 + 0.5) * RESOLUTION
+            );
+            if walls.iter().any(|w| distance_to_wall(w, p) < RESOLUTION) {
+                0
+            } else if inside(p) {
+                254
+            } else {
+                205
+            }
+        })
+        .collect();
+
+    let dir = std::env::temp_dir().join(format!("fastslam_localization_{}_{:?}", std::process::id(), std::thread::current().id()));
+    fs::create_dir_all(&dir).unwrap();
+    image::GrayImage::from_raw(WIDTH, HEIGHT, pixels).unwrap().save(dir.join("room.png")).unwrap();
+    fs::write(
+        dir.join("room.yaml"),
+        format!("image: room.png\nresolution: {}\norigin: [{}, {}, 0.0]\nnegate: 0\noccupied_thresh: 0.65\nfree_thresh: 0.196\n", RESOLUTION, ORIGIN.0, ORIGIN.1)
+    ).unwrap();
+
+    let map = load_ros_map(&dir.join("room.yaml")).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+    map
+}
+
+fn cell_state(map: &GridMap, p: Point) -> CellState {
+    let (x, y) = map.world_to_map(p).unwrap();
+    map.cell_state(x, y)
+}
+
+#[test]
+fn test_initialize_global() {
+    let map = known_map();
+    let config = FastSlamConfig { n_particles: 3000, ..FastSlamConfig::localization() };
+    let mcl = MonteCarloLocalization::new(config, map.clone()).unwrap();
+
+    assert_eq!(mcl.n_particles(), 3000);
+    assert!(mcl.particles().iter().all(|p| cell_state(&map, p.pose.position) == CellState::Freespace));
+    assert!((mcl.weights().iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+    // spread over the whole room, with all headings
+    assert!(mcl.particles().iter().any(|p| p.pose.position.x > 7.0 && p.pose.position.y > 4.0));
+    assert!(mcl.particles().iter().any(|p| p.pose.position.x < -3.0 && p.pose.position.y < -2.0));
+    assert!(mcl.estimate.covariance[(0, 0)] > 5.0);
+    assert!(mcl.particles().iter().any(|p| p.pose.heading > 3.0) && mcl.particles().iter().any(|p| p.pose.heading < -3.0));
+}
+
+#[test]
+fn test_initialize_around_pose() {
+    let config = FastSlamConfig { n_particles: 20000, ..FastSlamConfig::localization() };
+    let covariance = Covariance::new(
+        0.04, 0.01, 0.0,
+        0.01, 0.02, 0.0,
+        0.0, 0.0, 0.0
+    );
+    let initial = PoseWithCovariance::new(Pose::new(Point::new(1.0, -1.0), 0.5), covariance);
+    let mcl = MonteCarloLocalization::with_initial_pose(config, known_map(), &initial).unwrap();
+
+    assert!(mcl.estimate.pose.position.dist_to_point(initial.pose.position) < 0.01);
+    for &(i, j) in [(0, 0), (0, 1), (1, 1)].iter() {
+        assert!((mcl.estimate.covariance[(i, j)] - covariance[(i, j)]).abs() < 0.003, "covariance ({}, {})", i, j);
+    }
+
+    // the heading is known exactly
+    assert!(mcl.particles().iter().all(|p| (p.pose.heading - 0.5).abs() < 1e-9));
+}
+
+#[test]
+fn test_invalid_setup() {
+    // no free space to spread the particles over
+    let error = MonteCarloLocalization::new(FastSlamConfig::localization(), GridMap::default()).err().unwrap();
+    assert_eq!(error, LocalizationError::NoFreeSpace);
+    assert_eq!(error.to_string(), "the map has no free space to place particles in");
+
+    let config = FastSlamConfig { n_particles: 0, ..FastSlamConfig::localization() };
+    match MonteCarloLocalization::new(config, known_map()).err().unwrap() {
+        LocalizationError::Config(error) => assert_eq!(error.parameter, "n_particles"),
+        error => panic!("unexpected error {}", error)
+    }
+}
+
+#[test]
+fn test_initial_pose_needs_no_free_space() {
+    let initial = PoseWithCovariance::new(Pose::new(Point::new(1.0, -1.0), 0.5), Covariance::identity() * 0.01);
+    let mut mcl = MonteCarloLocalization::with_initial_pose(FastSlamConfig::localization(), GridMap::default(), &initial).unwrap();
+    assert_eq!(mcl.n_particles(), FastSlamConfig::localization().n_particles);
+
+    // there is nowhere to spread the particles to, they stay around the initial pose
+    mcl.initialize_global();
+    assert!(mcl.particles().iter().all(|p| p.pose.position.dist_to_point(initial.pose.position) < 1.0));
+
+    let config = FastSlamConfig { n_particles: 0, ..FastSlamConfig::localization() };
+    match MonteCarloLocalization::with_initial_pose(config, GridMap::default(), &initial).err().unwrap() {
+        LocalizationError::Config(error) => assert_eq!(error.parameter, "n_particles"),
+        error => panic!("unexpected error {}", error)
+    }
+}
+
+#[test]
+fn test_tracking() {
+    use Direction::*;
+
+    let map = known_map();
+    let occupied = map.get_all_occupied_cells().len();
+    let walls = l_shaped_room_with_box();
+    let mut robot = Robot::default();
+
+    // the initial pose is a little off
+    let initial = PoseWithCovariance::new(Pose::new(Point::new(0.2, -0.2), 0.1), Covariance::from_diagonal_element(0.05));
+    let config = FastSlamConfig { n_particles: 500, kld: None, ..FastSlamConfig::localization() };
+    let mut mcl = MonteCarloLocalization::with_initial_pose(config, map, &initial).unwrap();
+
+    for &(dir, steps) in [(Forward, 10), (Left, 10), (Forward, 10)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &walls);
+            mcl.cycle(&scan, &robot.latest_gain);
+        }
+    }
+
+    println!("estimate: {:?}, robot: {:?}", mcl.estimate.pose, robot.odom.pose);
+    assert!(mcl.estimate.pose.position.dist_to_point(robot.odom.pose.position) < 0.15);
+    assert!(wrap_angle(mcl.estimate.pose.heading - robot.odom.pose.heading).abs() < 0.05);
+    assert!(mcl.best_particle().pose.position.dist_to_point(robot.odom.pose.position) < 0.2);
+
+    // the map is only read
+    assert_eq!(mcl.map().get_all_occupied_cells().len(), occupied);
+    assert!(mcl.particles().iter().all(|p| p.gridmap.tile_count() == 0));
+}
+
+#[test]
+fn test_tracking_with_odometry_and_gyro() {
+    let walls = l_shaped_room_with_box();
+    let start = Pose::new(Point::new(-2.0, 0.0), 0.0);
+    let initial = PoseWithCovariance::new(start, Covariance::from_diagonal_element(0.01));
+    let config = FastSlamConfig { n_particles: 300, kld: None, ..FastSlamConfig::localization() };
+    let mut with_odometry = MonteCarloLocalization::with_initial_pose(config, known_map(), &initial).unwrap();
+    let mut with_gyro = with_odometry.clone();
+    let mut with_both = with_odometry.clone();
+
+    let laser_scanner = Robot::default().laser_scanner;
+    let gain = Twist::new(Vector::new(0.2, 0.0), 0.1);
+    let mut pose = start;
+    // the odometry has its own frame
+    let mut odometry = Odometry::new(Pose::new(Point::new(5.0, 5.0), 1.0), Twist::default());
+    with_odometry.cycle_odometry(&laser_scanner.scan(&pose, &walls), &odometry);
+    with_both.cycle_odometry_with_gyro(&laser_scanner.scan(&pose, &walls), &odometry, None);
+    for _ in 0..15 {
+        let prev = pose;
+        pose = Robot::drive(&pose, &gain, 1.0);
+        odometry.pose = odometry.pose.compose(&pose.relative_to(&prev));
+
+        let scan = laser_scanner.scan(&pose, &walls);
+        with_odometry.cycle_odometry(&scan, &odometry);
+        with_gyro.cycle_with_gyro(&scan, &gain, Some(gain.angular));
+        with_both.cycle_odometry_with_gyro(&scan, &odometry, Some(gain.angular));
+    }
+
+    for mcl in [with_odometry, with_gyro, with_both].iter() {
+        assert!(mcl.estimate.pose.position.dist_to_point(pose.position) < 0.15);
+        assert!(wrap_angle(mcl.estimate.pose.heading - pose.heading).abs() < 0.05);
+    }
+}
+
+#[test]
+fn test_stamped_tracking() {
+    let walls = l_shaped_room_with_box();
+    let start = Pose::new(Point::new(-2.0, 0.0), 0.0);
+    let initial = PoseWithCovariance::new(start, Covariance::from_diagonal_element(0.01));
+    // the time between the scans comes from the timestamps, not from fixed_dt
+    let config = FastSlamConfig { n_particles: 300, kld: None, fixed_dt: None, ..FastSlamConfig::localization() };
+    let mut with_gain = MonteCarloLocalization::with_initial_pose(config, known_map(), &initial).unwrap();
+    let mut with_odometry = with_gain.clone();
+
+    let laser_scanner = Robot::default().laser_scanner;
+    let gain = Twist::new(Vector::new(0.1, 0.0), 0.05);
+    let mut pose = start;
+    let mut odometry = Odometry::default();
+    let mut t = 10.0;
+    let scan = Stamped::new(t, laser_scanner.scan(&pose, &walls));
+    assert_eq!(with_gain.cycle_stamped(&scan, &Stamped::new(t, gain.clone())), Ok(0.0));
+    assert_eq!(with_odometry.cycle_odometry_with_gyro_stamped(&scan, &Stamped::new(t, odometry.clone()), None), Ok(0.0));
+
+    // rejected inputs leave the particles where they are
+    let particles: Vec<Pose> = with_gain.particles().iter().map(|p| p.pose).collect();
+    assert_eq!(with_gain.cycle_stamped(&scan, &Stamped::new(t, gain.clone())), Err(InputError::OutOfOrder { timestamp: t, previous: t }));
+    assert_eq!(with_gain.cycle_stamped(&Stamped::new(t + 2.0, scan.data.clone()), &Stamped::new(t, gain.clone())), Err(InputError::Stale { input: t, scan: t + 2.0 }));
+    assert!(with_gain.particles().iter().zip(particles.iter()).all(|(p, q)| p.pose == *q));
+
+    for _ in 0..15 {
+        // the scans come every 2s
+        t += 2.0;
+        let prev = pose;
+        pose = Robot::drive(&pose, &gain, 2.0);
+        odometry.pose = odometry.pose.compose(&pose.relative_to(&prev));
+
+        let scan = Stamped::new(t, laser_scanner.scan(&pose, &walls));
+        assert_eq!(with_gain.cycle_stamped(&scan, &Stamped::new(t - 0.1, gain.clone())), Ok(2.0));
+        let yaw_rate = Stamped::new(t, gain.angular);
+        assert_eq!(with_odometry.cycle_odometry_with_gyro_stamped(&scan, &Stamped::new(t, odometry.clone()), Some(&yaw_rate)), Ok(2.0));
+    }
+
+    for mcl in [with_gain, with_odometry].iter() {
+        assert!(mcl.estimate.pose.position.dist_to_point(pose.position) < 0.15);
+        assert!(wrap_angle(mcl.estimate.pose.heading - pose.heading).abs() < 0.05);
+    }
+}
+
+#[test]
+fn test_global_localization() {
+    use Direction::*;
+
+    let walls = l_shaped_room_with_box();
+    let mut robot = Robot::default();
+    robot.odom.pose = Pose::new(Point::new(0.0, -1.5), 0.0);
+    let mut mcl = MonteCarloLocalization::new(FastSlamConfig::localization(), known_map()).unwrap();
+    let initial_particles = mcl.n_particles();
+
+    for &(dir, steps) in [(Forward, 10), (Left, 10), (Forward, 10), (Right, 10), (Forward, 5)].iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &walls);
+            mcl.cycle(&scan, &robot.latest_gain);
+        }
+    }
+
+    println!("estimate: {:?}, robot: {:?}, particles: {}", mcl.estimate.pose, robot.odom.pose, mcl.n_particles());
+    assert!(mcl.estimate.pose.position.dist_to_point(robot.odom.pose.position) < 0.5);
+    assert!(wrap_angle(mcl.estimate.pose.heading - robot.odom.pose.heading).abs() < 0.1);
+
+    // KLD-sampling needs far fewer particles once they agree
+    assert!(mcl.n_particles() < initial_particles / 2);
+}
@@ -0,0 +1,70 @@
+use fastslam::geometry::Point;
+use fastslam::gridmap::grid_map::GridMap;
+use fastslam::odometry::Pose;
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::ndt::{ndt, NdtGrid, NdtParams};
+
+mod common;
+use common::box_scan;
+
+#[test]
+#[allow(non_snake_case)]
+fn test_ndt_in_a_room() {
+    let motion = Pose::new(Point::new(0.08, -0.06), 0.02);
+    let grid = NdtGrid::new(&box_scan(&Pose::default(), 5.2, 3.3).to_pointcloud(&Pose::default()), 1.0);
+    assert!(grid.size() > 0);
+
+    // a sparse scan, one beam every 10 degrees
+    let A = PointCloud::new(box_scan(&motion, 5.2, 3.3).iter().step_by(10).map(|m| m.to_point(&Pose::default())).collect());
+    let result = ndt(&A, &grid, &NdtParams::default());
+
+    assert!(result.converged);
+    assert!(result.transform.position.dist_to_point(motion.position) < 0.01);
+    assert!((result.transform.heading - motion.heading).abs() < 0.005);
+    assert!(result.score > 0.5);
+
+    // the room constrains all directions
+    assert!(result.covariance.cholesky().is_some());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_ndt_in_a_corridor() {
+    // only the long walls of the corridor are seen, sliding along it does not change the score
+    let corridor = |pose: Pose| PointCloud::new(
+        box_scan(&pose, 50.0, 1.2).to_pointcloud(&Pose::default())
+            .iter()
+            .filter(|p| p.x.abs() < 8.0)
+            .cloned()
+            .collect()
+    );
+    let grid = NdtGrid::new(&corridor(Pose::default()), 1.0);
+    let A = corridor(Pose::new(Point::new(0.0, 0.1), 0.0));
+    let result = ndt(&A, &grid, &NdtParams::default());
+
+    assert!((result.transform.position.y - 0.1).abs() < 0.01);
+    assert!(result.covariance[(0, 0)] > 100.0 * result.covariance[(1, 1)]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_ndt_against_a_gridmap() {
+    let scan = box_scan(&Pose::default(), 5.025, 3.025);
+    let mut gridmap = GridMap::new(0.05);
+    gridmap.update(&Pose::default(), &scan);
+    let grid = NdtGrid::from_gridmap(&gridmap, 1.0);
+
+    let motion = Pose::new(Point::new(-0.05, 0.05), -0.015);
+    // every second beam
+    let A = PointCloud::new(box_scan(&motion, 5.025, 3.025).iter().step_by(2).map(|m| m.to_point(&Pose::default())).collect());
+    let result = ndt(&A, &grid, &NdtParams::default());
+
+    assert!(result.converged);
+    assert!(result.transform.position.dist_to_point(motion.position) < 0.03);
+    assert!((result.transform.heading - motion.heading).abs() < 0.01);
+
+    // nothing to register against
+    let empty = ndt(&A, &NdtGrid::new(&PointCloud::empty(), 1.0), &NdtParams::default());
+    assert!(!empty.converged);
+    assert_eq!(empty.score, 0.0);
+}
@@ -0,0 +1,147 @@
+use fastslam::geometry::Point;
+use fastslam::math::scalar::PI;
+use fastslam::odometry::{MotionModel, Odometry, Pose, PoseWithCovariance, Twist};
+use fastslam::odometry::motion_model::odometry_delta;
+use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::particlefilter::probabilistic_models::{log_motion_model_odometry, log_motion_model_odometry_with_noise, motion_model_odometry};
+use fastslam::simulator::{Robot, Direction};
+
+mod common;
+use common::l_shaped_room;
+
+struct Model;
+
+impl MotionModel for Model {}
+
+#[test]
+fn test_odometry_delta() {
+    let prev = Pose::new(Point::new(1.0, 1.0), 0.5 * PI);
+
+    // forward and to the left, then turn further
+    let (rot1, trans, rot2) = odometry_delta(&prev, &Pose::new(Point::new(0.0, 2.0), PI));
+    assert!((rot1 - 0.25 * PI).abs() < 1e-12);
+    assert!((trans - 2.0f64.sqrt()).abs() < 1e-12);
+    assert!((rot2 - 0.25 * PI).abs() < 1e-12);
+
+    // turning on the spot
+    assert_eq!(odometry_delta(&prev, &Pose::new(Point::new(1.0, 1.0), 0.0)), (0.0, 0.0, -0.5 * PI));
+
+    // reversing is a negative translation, not a half turn
+    let (rot1, trans, rot2) = odometry_delta(&prev, &Pose::new(Point::new(1.0, 0.5), 0.5 * PI));
+    assert!(rot1.abs() < 1e-12 && rot2.abs() < 1e-12);
+    assert!((trans + 0.5).abs() < 1e-12);
+
+    // the parts add up to the motion
+    let curr = Pose::new(Point::new(-2.0, 3.0), -2.5);
+    let (rot1, trans, rot2) = odometry_delta(&prev, &curr);
+    let heading = prev.heading + rot1;
+    let rebuilt = Pose::new(Point::new(prev.position.x + trans * heading.cos(), prev.position.y + trans * heading.sin()), heading + rot2);
+    assert!(rebuilt.position.dist_to_point(curr.position) < 1e-12);
+    assert!((Model::wrap_heading(rebuilt.heading - curr.heading)).abs() < 1e-12);
+}
+
+#[test]
+fn test_drive_odometry() {
+    // odometry and particle use different frames, only the relative motion counts
+    let prev_odom = Pose::new(Point::new(10.0, 5.0), 1.0);
+    let curr_odom = prev_odom.compose(&Pose::new(Point::new(0.5, 0.1), 0.2));
+    let pose = Pose::new(Point::new(0.0, 0.0), 0.5 * PI);
+
+    let driven = Model::drive_odometry(&pose, &prev_odom, &curr_odom);
+    assert!(driven.position.dist_to_point(Point::new(-0.1, 0.5)) < 1e-12);
+    assert!((driven.heading - (0.5 * PI + 0.2)).abs() < 1e-12);
+}
+
+#[test]
+fn test_sample_motion_model_odometry() {
+    let prev_odom = Pose::new(Point::new(10.0, 5.0), 1.0);
+    let curr_odom = prev_odom.compose(&Pose::new(Point::new(1.0, 0.2), 0.3));
+    let pose = Pose::new(Point::new(-1.0, 2.0), -0.4);
+    let expected = Model::drive_odometry(&pose, &prev_odom, &curr_odom);
+
+    // without noise the sample is the noise free motion
+    let exact = Model::sample_motion_model_odometry_with_noise(&pose, &prev_odom, &curr_odom, &[0.0; 4]);
+    assert!(exact.position.dist_to_point(expected.position) < 1e-12);
+    assert!((exact.heading - expected.heading).abs() < 1e-12);
+
+    // the samples scatter around it
+    let samples: Vec<Pose> = (0..20000).map(|_| Model::sample_motion_model_odometry(&pose, &prev_odom, &curr_odom)).collect();
+    let sampled = PoseWithCovariance::from_weighted_poses(&samples, &vec![1.0; samples.len()]);
+    assert!(sampled.pose.position.dist_to_point(expected.position) < 0.01);
+    assert!((sampled.pose.heading - expected.heading).abs() < 0.01);
+    assert!(sampled.covariance[(0, 0)] > 0.0 && sampled.covariance[(2, 2)] > 0.0);
+
+    // standing still
+    let still = Model::sample_motion_model_odometry(&pose, &prev_odom, &prev_odom);
+    assert_eq!(still.position, pose.position);
+    assert!((still.heading - pose.heading).abs() < 1e-12);
+}
+
+#[test]
+fn test_motion_model_odometry() {
+    let prev_odom = Pose::new(Point::new(0.0, 0.0), 0.0);
+    let curr_odom = Pose::new(Point::new(1.0, 0.0), 0.1);
+    let prev_pose = Pose::new(Point::new(3.0, 3.0), 0.5 * PI);
+    let expected = Model::drive_odometry(&prev_pose, &prev_odom, &curr_odom);
+
+    // the noise free motion is the most likely, the likelihood drops away from it
+    let log_p = |x: f64, y: f64, heading: f64| {
+        let pose = Pose::new(Point::new(expected.position.x + x, expected.position.y + y), expected.heading + heading);
+        log_motion_model_odometry(&pose, &prev_pose, &prev_odom, &curr_odom)
+    };
+    let best = log_p(0.0, 0.0, 0.0);
+    assert!(best.is_finite());
+    for &(x, y, heading) in [(0.05, 0.0, 0.0), (0.0, 0.05, 0.0), (0.0, 0.0, 0.05), (-0.1, 0.1, -0.1)].iter() {
+        assert!(log_p(x, y, heading) < best);
+    }
+    assert!(log_p(0.0, 0.1, 0.0) < log_p(0.0, 0.05, 0.0));
+
+    let p = motion_model_odometry(&expected, &prev_pose, &prev_odom, &curr_odom);
+    assert!((p.ln() - best).abs() < 1e-9);
+
+    // more noise spreads the probability
+    let noisy = log_motion_model_odometry_with_noise(&expected, &prev_pose, &prev_odom, &curr_odom, &[0.1; 4]);
+    assert!(noisy < best);
+
+    // reversing by the same distance the odometry reports
+    let back_odom = Pose::new(Point::new(-1.0, 0.0), 0.0);
+    let reversed = Model::drive_odometry(&prev_pose, &prev_odom, &back_odom);
+    assert!(reversed.position.dist_to_point(Point::new(3.0, 2.0)) < 1e-12);
+    assert!(log_motion_model_odometry(&reversed, &prev_pose, &prev_odom, &back_odom).is_finite());
+}
+
+#[test]
+fn test_particle_filter_with_odometry() {
+    use Direction::*;
+
+    let objects = l_shaped_room();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    let mut particle_filter = ParticleFilter::default();
+
+    // wheel odometry that starts in its own frame and overestimates the distance driven
+    let start = Pose::new(Point::new(20.0, -7.0), 2.0);
+    let mut odometry = Odometry::new(start, Twist::default());
+
+    particle_filter.cycle_odometry(&robot.laser_scanner.scan(&robot.odom.pose, &objects), &odometry);
+    assert_eq!(particle_filter.best_particle.pose, Pose::default());
+
+    for &(dir, steps) in [(Forward, 8), (Left, 6), (Forward, 8)].iter() {
+        for _ in 0..steps {
+            let before = robot.odom.pose;
+            robot.move_forward(Some(dir));
+            let mut delta = robot.odom.pose.relative_to(&before);
+            delta.position = delta.position * 1.1;
+            odometry.pose = odometry.pose.compose(&delta);
+
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+            particle_filter.cycle_odometry(&scan, &odometry);
+        }
+    }
+
+    let dead_reckoning = start.inverse().compose(&odometry.pose);
+    let filter_error = particle_filter.best_particle.pose.position.dist_to_point(robot.odom.pose.position);
+    let odometry_error = dead_reckoning.position.dist_to_point(robot.odom.pose.position);
+    println!("filter error: {}, odometry error: {}", filter_error, odometry_error);
+    assert!(filter_error < odometry_error);
+}
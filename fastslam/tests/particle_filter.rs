@@ -1,6 +1,16 @@
 use fastslam::odometry::Pose;
 use fastslam::geometry::Point;
 use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::particlefilter::particle::Particle;
+use fastslam::particlefilter::config::{FastSlamConfig, IcpGate};
+use fastslam::gridmap::grid_map::GridMap;
+use fastslam::odometry::{Twist, MotionModel};
+use fastslam::simulator::{Robot, Direction};
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::icp::{icp_with_guess, IcpParams};
+
+mod common;
+use common::l_shaped_room_with_box;
 
 #[test]
 fn test_sample_distribution() {
@@ -13,4 +23,146 @@ fn test_sample_distribution() {
 
     let pose_samples: Vec<Pose> = ParticleFilter::sample_distribution(&init_pose, std_dev_sampling, 10);
     println!("pose samples: {:?}", pose_samples);
-}
\ No newline at end of file
+}
+
+fn position_error(estimate: &Pose, truth: &Pose) -> f64 {
+    estimate.position.dist_to_point(truth.position)
+}
+
+#[test]
+fn test_scan_matching_beats_odometry() {
+    use Direction::*;
+
+    let objects = l_shaped_room_with_box();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    // pose samples well inside the spread of the motion model, so the proposal follows the scan match
+    let config = FastSlamConfig::builder().sampling_range(0.01).build().unwrap();
+    let mut particle_filter = ParticleFilter::new(config).unwrap();
+    let mut dead_reckoning = Pose::default();
+    let mut filter_error = 0.0;
+    let mut odometry_error = 0.0;
+
+    let path = [(Forward, 10), (Left, 8), (Forward, 10)];
+    for &(dir, steps) in path.iter() {
+        for _ in 0..steps {
+            robot.move_forward(Some(dir));
+
+            // odometry overestimates both translation and rotation
+            let gain = Twist::new(robot.latest_gain.velocity * 1.1, robot.latest_gain.angular * 1.2);
+            dead_reckoning = Robot::drive(&dead_reckoning, &gain, 1.0);
+
+            let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+            particle_filter.cycle(&scan, &gain);
+            filter_error += position_error(&particle_filter.best_particle.pose, &robot.odom.pose);
+            odometry_error += position_error(&dead_reckoning, &robot.odom.pose);
+        }
+    }
+
+    // the position error accumulated along the trajectory
+    println!("filter error: {}, odometry error: {}", filter_error, odometry_error);
+    assert!(filter_error < odometry_error);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_accept_scan_match_far_from_origin() {
+    // a corner of the room in the frame of the sensor, seen from 20m away from the origin
+    let corner: Vec<Point> = (0..200)
+        .map(|i| Point::new(2.0, -2.0 + i as f64 * 0.02))
+        .chain((1..150).map(|i| Point::new(2.0 - i as f64 * 0.02, 2.0)))
+        .collect();
+    let A = PointCloud::new(corner);
+    let pose = Pose::new(Point::new(20.0, 0.0), 0.0);
+    let B = A.transform(&pose);
+
+    // the motion model is off by a small heading error, which moves the pose by about 1m
+    // when applied in the world frame
+    let motion_model_pose = Pose::new(Point::new(20.02, 0.01), 0.05);
+    assert!(pose.compose(&motion_model_pose.inverse()).position.to_vec().length() > 0.9);
+
+    let params = IcpParams { max_iterations: 50, tolerance: 1.0e-12, ..IcpParams::default() };
+    let scan_match = icp_with_guess(&A, &B, &motion_model_pose, &params);
+    assert!(scan_match.transform.position.dist_to_point(pose.position) < 0.01);
+    assert!(scan_match.correction.position.to_vec().length() < 0.1);
+    assert!(ParticleFilter::accept_scan_match(&scan_match, &FastSlamConfig::default()));
+
+    // a tighter gate does not trust the heading correction
+    let strict = FastSlamConfig::builder().icp_gate(IcpGate { max_rotation: 0.01, ..IcpGate::default() }).build().unwrap();
+    assert!(!ParticleFilter::accept_scan_match(&scan_match, &strict));
+}
+
+#[test]
+fn test_normalize_weights() {
+    // weights far below the smallest f64, only their logarithms are representable
+    let mut particles: Vec<Particle> = (0..3).map(|_| Particle::new(Pose::default(), 1.0, GridMap::default())).collect();
+    for (i, p) in particles.iter_mut().enumerate() {
+        p.log_weight = -1000.0 - i as f64;
+    }
+
+    ParticleFilter::normalize_weights(&mut particles);
+    let weights: Vec<f64> = particles.iter().map(|p| p.weight).collect();
+    assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    assert!((weights[0] / weights[1] - 1.0f64.exp()).abs() < 1e-9);
+    assert!(particles.iter().all(|p| (p.log_weight - p.weight.ln()).abs() < 1e-12));
+
+    // all weights zero
+    particles.iter_mut().for_each(|p| p.log_weight = f64::NEG_INFINITY);
+    ParticleFilter::normalize_weights(&mut particles);
+    assert!(particles.iter().all(|p| (p.weight - 1.0 / 3.0).abs() < 1e-12));
+
+    // neff does not depend on the scale of the weights
+    let mut particles: Vec<Particle> = [1.0, 1.0, 2.0].iter().map(|&w| Particle::new(Pose::default(), w, GridMap::default())).collect();
+    let neff = ParticleFilter::compute_neff(&particles);
+    ParticleFilter::normalize_weights(&mut particles);
+    assert!((ParticleFilter::compute_neff(&particles) - neff).abs() < 1e-12);
+    assert!((neff - 16.0 / 6.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_weights_stay_normalized() {
+    let objects = l_shaped_room_with_box();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    let mut particle_filter = ParticleFilter::default();
+    let n = particle_filter.weights().len() as f64;
+    assert!((particle_filter.neff - n).abs() < 1e-9);
+
+    for _ in 0..5 {
+        robot.move_forward(Some(Direction::Forward));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+        particle_filter.cycle(&scan, &robot.latest_gain.clone());
+
+        let weights = particle_filter.weights();
+        assert!(weights.iter().all(|w| w.is_finite() && *w >= 0.0));
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(particle_filter.neff >= 1.0 - 1e-9 && particle_filter.neff <= n + 1e-9);
+    }
+}
+
+#[test]
+fn test_particles_spread() {
+    use Direction::*;
+
+    let objects = l_shaped_room_with_box();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    // pose samples well inside the spread of the motion model across the direction of travel,
+    // so the scan match rather than the odometry decides where the proposal is
+    let config = FastSlamConfig::builder().resampling_threshold(0.0).sampling_range(0.01).build().unwrap();
+    let mut particle_filter = ParticleFilter::new(config).unwrap();
+
+    for &dir in [Forward, Forward, Left, Forward, Forward].iter() {
+        robot.move_forward(Some(dir));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+        particle_filter.cycle(&scan, &robot.latest_gain.clone());
+    }
+
+    // every particle draws its own pose from the proposal, without resampling they do not
+    // collapse on to the same scan-matched pose
+    let particles = particle_filter.particles();
+    for (i, p) in particles.iter().enumerate() {
+        assert!(particles[..i].iter().all(|q| q.pose.position != p.pose.position));
+    }
+    assert!(particle_filter.estimate.pose.position.dist_to_point(robot.odom.pose.position) < 0.1);
+}
@@ -0,0 +1,75 @@
+use fastslam::geometry::{Point, Vector};
+use fastslam::odometry::Pose;
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::icp::{icp_with_params, IcpMethod, IcpParams};
+use fastslam::scanmatching::kdtree::KdTree;
+use fastslam::scanmatching::point_to_line::{estimate_normals, point_to_line_transform};
+
+mod common;
+use common::room_scan;
+
+fn translation_error(result: Pose, expected: Pose) -> f64 {
+    result.position.dist_to_point(expected.position)
+}
+
+#[test]
+fn test_normals_of_a_wall() {
+    let wall = PointCloud::new((0..10).map(|i| Point::new(2.0, i as f64 * 0.1)).collect());
+    let normals = estimate_normals(&wall, &KdTree::new(&wall));
+
+    assert_eq!(normals.len(), wall.size());
+    for normal in normals {
+        let normal = normal.unwrap();
+        assert!((normal.x.abs() - 1.0).abs() < 1e-9 && normal.y.abs() < 1e-9);
+    }
+
+    let single = PointCloud::new(vec![Point::new(1.0, 1.0); 3]);
+    assert!(estimate_normals(&single, &KdTree::new(&single))[0].is_none());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_point_to_line_transform_slides_along_lines() {
+    // points of a single wall only constrain the distance to that wall
+    let A = PointCloud::new((0..10).map(|i| Point::new(1.9, i as f64 * 0.1)).collect());
+    let B = PointCloud::new((0..10).map(|i| Point::new(2.0, i as f64 * 0.1 + 0.05)).collect());
+    let normals = vec![Some(Vector::new(1.0, 0.0)); 10];
+    assert!(point_to_line_transform(&A, &B, &normals).is_none());
+
+    // two perpendicular walls
+    let A = PointCloud::new(vec![
+        Point::new(1.9, 0.0), Point::new(1.9, 0.5), Point::new(1.9, 1.0),
+        Point::new(0.0, 2.8), Point::new(0.5, 2.8), Point::new(1.0, 2.8),
+    ]);
+    let B = PointCloud::new(vec![
+        Point::new(2.0, 0.3), Point::new(2.0, 0.6), Point::new(2.0, 0.9),
+        Point::new(0.2, 3.0), Point::new(0.6, 3.0), Point::new(0.9, 3.0),
+    ]);
+    let normals = vec![
+        Some(Vector::new(1.0, 0.0)), Some(Vector::new(1.0, 0.0)), Some(Vector::new(1.0, 0.0)),
+        Some(Vector::new(0.0, 1.0)), Some(Vector::new(0.0, 1.0)), Some(Vector::new(0.0, 1.0)),
+    ];
+    let step = point_to_line_transform(&A, &B, &normals).unwrap();
+    assert!(step.position.dist_to_point(Point::new(0.1, 0.2)) < 1e-9);
+    assert!(step.heading.abs() < 1e-9);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_point_to_line_icp_in_a_room() {
+    let motion = Pose::new(Point::new(0.12, -0.07), 0.03);
+    let B = PointCloud::new(room_scan(Pose::default(), 0.0));
+    let A = PointCloud::new(room_scan(motion, 0.5));
+
+    let point_to_line = IcpParams { method: IcpMethod::PointToLine, max_iterations: 50, ..IcpParams::default() };
+    let point_to_point = IcpParams { method: IcpMethod::PointToPoint, max_iterations: 50, ..IcpParams::default() };
+    let pl = icp_with_params(&A, &B, &point_to_line);
+    let pp = icp_with_params(&A, &B, &point_to_point);
+
+    assert!(pl.converged);
+    assert!(translation_error(pl.transform, motion) < 0.005);
+    assert!((pl.transform.heading - motion.heading).abs() < 0.002);
+    assert!(translation_error(pl.transform, motion) < translation_error(pp.transform, motion));
+    assert!(pl.iterations < pp.iterations);
+    assert!(pl.final_residual < pl.initial_residual);
+}
@@ -1,5 +1,8 @@
-use fastslam::odometry::Pose;
-use fastslam::geometry::Point;
+use fastslam::odometry::{Pose, Twist};
+use fastslam::geometry::{Point, Vector};
+use fastslam::math::scalar::{wrap_angle, PI};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 #[test]
 fn test_pose_add_assign() {
@@ -20,3 +23,148 @@ fn test_pose_add_assign_negative() {
 
     assert_eq!(p1, p2.clone() * 0.0);
 }
+
+#[test]
+fn test_pose_operators_wrap_heading() {
+    let a = Pose::new(Point::new(1.0, 2.0), PI - 0.1);
+    let b = Pose::new(Point::new(0.5, -1.0), -PI + 0.2);
+
+    assert!(pose_eq(a + a, Pose::new(Point::new(2.0, 4.0), -0.2)));
+    assert!(pose_eq(a - b, Pose::new(Point::new(0.5, 3.0), -0.3)));
+    assert!(pose_eq(a * 3.0, Pose::new(Point::new(3.0, 6.0), PI - 0.3)));
+    assert!(pose_eq(Pose::new(Point::new(1.0, 2.0), 3.0 * PI) / 2.0, Pose::new(Point::new(0.5, 1.0), -PI / 2.0)));
+
+    let mut c = a;
+    c += a;
+    assert_eq!(c, a + a);
+    for pose in &[a + a, a - b, b - a, a * 3.0, c] {
+        assert!(is_wrapped(*pose));
+    }
+}
+
+/// random poses with positions in [-10, 10] and headings in [-PI, PI]
+fn random_poses(seed: u64, n: usize) -> Vec<Pose> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| Pose::new(Point::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0)), rng.gen_range(-PI..PI)))
+        .collect()
+}
+
+/// poses are equal up to epsilon, headings that differ by a full turn are the same
+fn pose_eq(u: Pose, v: Pose) -> bool {
+    let epsilon = 1.0e-9;
+    u.position.dist_to_point(v.position) < epsilon && wrap_angle(u.heading - v.heading).abs() < epsilon
+}
+
+fn is_wrapped(pose: Pose) -> bool {
+    -PI < pose.heading && pose.heading <= PI
+}
+
+#[test]
+fn test_wrap_angle() {
+    assert_eq!(wrap_angle(0.0), 0.0);
+    assert_eq!(wrap_angle(PI), PI);
+    assert_eq!(wrap_angle(-PI), PI);
+    assert!((wrap_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1.0e-12);
+    assert!((wrap_angle(-5.0 * PI / 2.0) + PI / 2.0).abs() < 1.0e-12);
+    assert!((wrap_angle(20.0 * PI + 0.1) - 0.1).abs() < 1.0e-12);
+}
+
+#[test]
+fn test_pose_compose() {
+    // move 1m forward while facing along y, then turn left
+    let a = Pose::new(Point::new(1.0, 2.0), PI / 2.0);
+    let b = Pose::new(Point::new(1.0, 0.0), PI / 2.0);
+    assert!(pose_eq(a.compose(&b), Pose::new(Point::new(1.0, 3.0), PI)));
+
+    // the headings wrap around
+    let c = Pose::new(Point::new(0.0, 0.0), 3.0);
+    assert!((c.compose(&c).heading - (6.0 - 2.0 * PI)).abs() < 1.0e-12);
+}
+
+#[test]
+fn test_pose_compose_is_associative() {
+    let poses = random_poses(1, 300);
+    for abc in poses.chunks(3) {
+        let (a, b, c) = (abc[0], abc[1], abc[2]);
+        assert!(pose_eq(a.compose(&b).compose(&c), a.compose(&b.compose(&c))));
+        assert!(is_wrapped(a.compose(&b)));
+    }
+}
+
+#[test]
+fn test_pose_inverse() {
+    let identity = Pose::default();
+    for a in random_poses(2, 100) {
+        assert!(pose_eq(a.compose(&a.inverse()), identity));
+        assert!(pose_eq(a.inverse().compose(&a), identity));
+        assert!(pose_eq(a.inverse().inverse(), a));
+        assert!(pose_eq(a.compose(&identity), a));
+        assert!(is_wrapped(a.inverse()));
+    }
+}
+
+#[test]
+fn test_pose_relative_to() {
+    let poses = random_poses(3, 200);
+    for ab in poses.chunks(2) {
+        let (a, b) = (ab[0], ab[1]);
+        assert!(pose_eq(b.compose(&a.relative_to(&b)), a));
+        assert!(pose_eq(a.relative_to(&b), b.relative_to(&a).inverse()));
+        assert!(pose_eq(a.relative_to(&a), Pose::default()));
+    }
+}
+
+#[test]
+fn test_pose_transform_point() {
+    let a = Pose::new(Point::new(1.0, 2.0), PI / 2.0);
+    assert!(a.transform_point(Point::new(1.0, 0.0)).dist_to_point(Point::new(1.0, 3.0)) < 1.0e-12);
+
+    let poses = random_poses(4, 200);
+    for ab in poses.chunks(2) {
+        let (a, b) = (ab[0], ab[1]);
+        let p = b.position;
+        assert!(a.compose(&b).transform_point(p).dist_to_point(a.transform_point(b.transform_point(p))) < 1.0e-9);
+        assert!(a.inverse().transform_point(a.transform_point(p)).dist_to_point(p) < 1.0e-9);
+    }
+}
+
+#[test]
+fn test_pose_exp_and_log() {
+    // driving a quarter circle with radius 1m
+    let quarter = Pose::exp(&Twist::new(Vector::new(PI / 2.0, 0.0), PI / 2.0));
+    assert!(pose_eq(quarter, Pose::new(Point::new(1.0, 1.0), PI / 2.0)));
+
+    // straight ahead and on the spot
+    assert!(pose_eq(Pose::exp(&Twist::new(Vector::new(2.0, -1.0), 0.0)), Pose::new(Point::new(2.0, -1.0), 0.0)));
+    assert!(pose_eq(Pose::exp(&Twist::new(Vector::new(0.0, 0.0), 1.0)), Pose::new(Point::new(0.0, 0.0), 1.0)));
+
+    for a in random_poses(5, 100).into_iter().chain(random_poses(6, 100).into_iter().map(|p| Pose::new(p.position, p.heading * 1.0e-8))) {
+        assert!(pose_eq(Pose::exp(&a.log()), a));
+        assert!(is_wrapped(Pose::exp(&a.log())));
+    }
+}
+
+#[test]
+fn test_pose_interpolate() {
+    let a = Pose::new(Point::new(0.0, 0.0), 0.0);
+    let b = Pose::new(Point::new(1.0, 1.0), PI / 2.0);
+    assert!(pose_eq(a.interpolate(&b, 0.5), Pose::exp(&Twist::new(Vector::new(PI / 4.0, 0.0), PI / 4.0))));
+
+    // the heading turns the short way across +-PI
+    let c = Pose::new(Point::new(0.0, 0.0), PI - 0.1);
+    let d = Pose::new(Point::new(0.0, 0.0), -PI + 0.1);
+    assert!(pose_eq(c.interpolate(&d, 0.5), Pose::new(Point::new(0.0, 0.0), PI)));
+
+    let poses = random_poses(7, 200);
+    for ab in poses.chunks(2) {
+        let (a, b) = (ab[0], ab[1]);
+        assert!(pose_eq(a.interpolate(&b, 0.0), a));
+        assert!(pose_eq(a.interpolate(&b, 1.0), b));
+
+        // halfway to the middle is a quarter of the way
+        let middle = a.interpolate(&b, 0.5);
+        assert!(pose_eq(a.interpolate(&middle, 0.5), a.interpolate(&b, 0.25)));
+        assert!(is_wrapped(a.interpolate(&b, 0.3)));
+    }
+}
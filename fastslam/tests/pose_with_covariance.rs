@@ -0,0 +1,121 @@
+use nalgebra as na;
+use fastslam::geometry::{Point, Vector};
+use fastslam::math::scalar::PI;
+use fastslam::odometry::{Covariance, MotionModel, Pose, PoseWithCovariance, Twist};
+use fastslam::odometry::motion_model::DEFAULT_ALPHA;
+use fastslam::particlefilter::particle::Particle;
+use fastslam::particlefilter::particle_filter::ParticleFilter;
+use fastslam::gridmap::grid_map::GridMap;
+
+struct Model;
+
+impl MotionModel for Model {}
+
+#[test]
+fn test_weighted_mean_and_covariance() {
+    let poses = vec![
+        Pose::new(Point::new(1.0, 0.0), 0.1),
+        Pose::new(Point::new(3.0, 2.0), 0.3),
+    ];
+
+    let estimate = PoseWithCovariance::from_weighted_poses(&poses, &[1.0, 1.0]);
+    assert!(estimate.pose.position.dist_to_point(Point::new(2.0, 1.0)) < 1e-12);
+    assert!((estimate.pose.heading - 0.2).abs() < 1e-12);
+    assert!((estimate.covariance[(0, 0)] - 1.0).abs() < 1e-12);
+    assert!((estimate.covariance[(0, 1)] - 1.0).abs() < 1e-12);
+    assert!((estimate.covariance[(2, 2)] - 0.01).abs() < 1e-12);
+
+    // the weights are normalized, a pose with weight zero does not count
+    let estimate = PoseWithCovariance::from_weighted_poses(&poses, &[0.0, 5.0]);
+    assert_eq!(estimate.pose, poses[1]);
+    assert_eq!(estimate.covariance, Covariance::zeros());
+}
+
+#[test]
+fn test_mean_heading_wraps_around() {
+    // headings just either side of +-PI average to PI, not to 0
+    let poses = vec![
+        Pose::new(Point::new(0.0, 0.0), PI - 0.1),
+        Pose::new(Point::new(0.0, 0.0), -PI + 0.1),
+    ];
+
+    let estimate = PoseWithCovariance::from_weighted_poses(&poses, &[1.0, 1.0]);
+    assert!((estimate.pose.heading.abs() - PI).abs() < 1e-12);
+    assert!((estimate.covariance[(2, 2)] - 0.01).abs() < 1e-12);
+}
+
+#[test]
+fn test_particle_filter_estimate() {
+    let particles = vec![
+        Particle::new(Pose::new(Point::new(0.0, 0.0), 0.0), 1.0, GridMap::default()),
+        Particle::new(Pose::new(Point::new(1.0, 0.0), 0.0), 3.0, GridMap::default()),
+    ];
+
+    let estimate = ParticleFilter::compute_estimate(&particles);
+    assert!(estimate.pose.position.dist_to_point(Point::new(0.75, 0.0)) < 1e-12);
+    assert!((estimate.covariance[(0, 0)] - 0.1875).abs() < 1e-12);
+
+    // all particles start at the origin
+    assert_eq!(ParticleFilter::default().estimate, PoseWithCovariance::default());
+}
+
+#[test]
+fn test_propagated_covariance_matches_samples() {
+    let start = Pose::new(Point::new(1.0, -1.0), 0.5);
+    let dt = 1.0;
+
+    let gains = [Twist::new(Vector::new(1.0, 0.0), 0.3), Twist::new(Vector::new(1.0, 0.0), 0.0)];
+    let alphas = [DEFAULT_ALPHA, [0.05, 0.02, 0.03, 0.01, 0.02, 0.01]];
+    for (gain, alpha) in gains.iter().flat_map(|g| alphas.iter().map(move |a| (g, a))) {
+        let predicted = Model::motion_model_velocity_with_covariance_with_noise(&PoseWithCovariance::new(start, Covariance::zeros()), gain, dt, alpha);
+
+        let samples: Vec<Pose> = (0..20000).map(|_| Model::sample_motion_model_velocity_with_noise(&start, gain, dt, alpha)).collect();
+        let sampled = PoseWithCovariance::from_weighted_poses(&samples, &vec![1.0; samples.len()]);
+
+        assert!(predicted.pose.position.dist_to_point(sampled.pose.position) < 0.01);
+        assert!((predicted.pose.heading - sampled.pose.heading).abs() < 0.01);
+        for i in 0..3 {
+            let (p, s) = (predicted.covariance[(i, i)], sampled.covariance[(i, i)]);
+            assert!((p - s).abs() < 0.15 * s, "variance {}: predicted {}, sampled {}", i, p, s);
+        }
+    }
+}
+
+#[test]
+fn test_covariance_grows_with_motion() {
+    let start = PoseWithCovariance::new(Pose::default(), Covariance::from_diagonal_element(0.01));
+    let gain = Twist::new(Vector::new(0.5, 0.0), 0.1);
+
+    let mut driven = start;
+    let mut predicted = start;
+    for _ in 0..10 {
+        let next_driven = Model::drive_with_covariance(&driven, &gain, 1.0);
+        let next_predicted = Model::motion_model_velocity_with_covariance(&predicted, &gain, 1.0);
+        assert!(next_driven.covariance.trace() > driven.covariance.trace());
+        assert!(next_predicted.covariance.trace() > predicted.covariance.trace());
+        driven = next_driven;
+        predicted = next_predicted;
+    }
+    assert_eq!(driven.pose, (0..10).fold(Pose::default(), |pose, _| Model::drive(&pose, &gain, 1.0)));
+
+    // an uncertain heading turns into an uncertain position across the direction of travel
+    let heading_only = PoseWithCovariance::new(Pose::default(), Covariance::from_diagonal(&na::Vector3::new(0.0, 0.0, 0.01)));
+    let straight = Twist::new(Vector::new(2.0, 0.0), 0.0);
+    let propagated = Model::drive_with_covariance(&heading_only, &straight, 1.0).covariance
+        - Model::drive_with_covariance(&PoseWithCovariance::default(), &straight, 1.0).covariance;
+    assert!(propagated[(0, 0)].abs() < 1e-12);
+    assert!((propagated[(1, 1)] - 0.04).abs() < 1e-12);
+    assert!((propagated[(1, 2)] - 0.02).abs() < 1e-12);
+
+    // standing still keeps the covariance
+    let still = Model::motion_model_velocity_with_covariance(&start, &Twist::default(), 1.0);
+    assert_eq!(still.covariance, start.covariance);
+    assert_eq!(still.pose, start.pose);
+
+    // the default motion noise is DEFAULT_ALPHA, more noise grows the covariance faster
+    let noisy = [0.1; 6];
+    assert_eq!(Model::drive_with_covariance(&start, &gain, 1.0), Model::drive_with_covariance_with_noise(&start, &gain, 1.0, &DEFAULT_ALPHA));
+    assert_eq!(Model::motion_model_velocity_with_covariance(&start, &gain, 1.0), Model::motion_model_velocity_with_covariance_with_noise(&start, &gain, 1.0, &DEFAULT_ALPHA));
+    assert!(Model::drive_with_covariance_with_noise(&start, &gain, 1.0, &noisy).covariance.trace() > Model::drive_with_covariance(&start, &gain, 1.0).covariance.trace());
+    assert!(Model::motion_model_velocity_with_covariance_with_noise(&start, &gain, 1.0, &noisy).covariance.trace() > Model::motion_model_velocity_with_covariance(&start, &gain, 1.0).covariance.trace());
+}
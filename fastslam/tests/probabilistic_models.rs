@@ -1,4 +1,4 @@
-use fastslam::particlefilter::probabilistic_models::{likelihood_field_range_finder_model, motion_model_velocity};
+use fastslam::particlefilter::probabilistic_models::{likelihood_field_range_finder_model, motion_model_velocity, log_likelihood_field_range_finder_model, log_likelihood_field_range_finder_model_with_params, log_motion_model_velocity, LikelihoodFieldParams};
 use fastslam::sensor::laserscanner::{Scan, Measurement};
 use fastslam::math::scalar::PI;
 use fastslam::odometry::{Pose, Twist};
@@ -7,7 +7,7 @@ use fastslam::geometry::{Point, Vector};
 
 #[test]
 fn test_likelihood_range_finder_empty_map_zero_prob() {
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let pose = Pose {
         position: Point { x: 29.0, y: 29.0 },
@@ -34,7 +34,7 @@ fn test_likelihood_range_finder_empty_map_zero_prob() {
 
 #[test]
 fn test_likelihood_range_finder_same_scan_twice() {
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let mut pose = Pose {
         position: Point { x: 29.0, y: 29.0 },
@@ -90,7 +90,7 @@ fn test_likelihood_range_finder_same_scan_twice() {
 
 #[test]
 fn test_likelihood_range_finder_unlikely_second_scan() {
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let mut pose = Pose {
         position: Point { x: 29.0, y: 0.0 },
@@ -147,7 +147,7 @@ fn test_likelihood_range_finder_unlikely_second_scan() {
 
 #[test]
 fn test_likelihood_range_finder_likely_second_scan() {
-    let mut grid = GridMap::new(100, 1.0);
+    let mut grid = GridMap::new(1.0);
 
     let mut pose = Pose {
         position: Point { x: 29.0, y: 0.0 },
@@ -261,4 +261,63 @@ fn test_motion_model_velocity_2() {
     println!("overcompensated: {}", prob_sample_overcompensated);
     // assert!(prob_sample_accurate > prob_sample_undercompensated);
     // assert!(prob_sample_undercompensated > prob_sample_overcompensated);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_log_likelihood_range_finder_does_not_overflow() {
+    let mut grid = GridMap::new(0.1);
+    let pose = Pose::new(Point::new(0.0, 0.0), 0.0);
+
+    // a round room seen with many beams, every beam is very likely
+    let scan = Scan {
+        measurements: (0..720).map(|i| Measurement { angle: (i as f64) * PI / 360.0, distance: 5.0 }).collect()
+    };
+    grid.update(&pose, &scan);
+
+    let prob = likelihood_field_range_finder_model(&scan, &pose, &grid);
+    let log_prob = log_likelihood_field_range_finder_model(&scan, &pose, &grid);
+    assert!(prob.is_infinite());
+    assert!(log_prob.is_finite() && log_prob > 0.0);
+
+    // a few beams, where the product is in range
+    let few = Scan { measurements: scan.measurements[..10].to_vec() };
+    let prob = likelihood_field_range_finder_model(&few, &pose, &grid);
+    assert!((log_likelihood_field_range_finder_model(&few, &pose, &grid) - prob.ln()).abs() < 1e-9);
+}
+
+#[test]
+fn test_log_motion_model_velocity() {
+    let prev_pose = Pose::new(Point::new(0.0, 0.0), 0.0);
+    let gain = Twist::new(Vector::new(1.0, 0.0), 0.1);
+    let curr_pose = Pose::new(Point::new(0.99, 0.05), 0.1);
+
+    let prob = motion_model_velocity(&curr_pose, &prev_pose, &gain, 1.0);
+    let log_prob = log_motion_model_velocity(&curr_pose, &prev_pose, &gain, 1.0);
+    assert!((log_prob - prob.ln()).abs() < 1e-9);
+
+    // far from the predicted pose the probability underflows, its logarithm does not
+    let far_pose = Pose::new(Point::new(-20.0, 30.0), 2.0);
+    assert_eq!(motion_model_velocity(&far_pose, &prev_pose, &gain, 1.0), 0.0);
+    assert!(log_motion_model_velocity(&far_pose, &prev_pose, &gain, 1.0).is_finite());
+}
+
+#[test]
+fn test_likelihood_field_max_distance() {
+    let mut grid = GridMap::new(0.1);
+    grid.set_log_odds(0, 0, 10.0);
+    let pose = Pose::new(Point::new(0.05, 0.05), 0.0);
+    let beam = |distance: f64| Scan { measurements: vec![Measurement { angle: 0.0, distance }] };
+    let wide = LikelihoodFieldParams { sigma_hit: 2.0, ..LikelihoodFieldParams::default() };
+
+    // beyond the default max distance every beam end is equally unlikely
+    let near = log_likelihood_field_range_finder_model_with_params(&beam(2.5), &pose, &grid, &wide);
+    let far = log_likelihood_field_range_finder_model_with_params(&beam(3.5), &pose, &grid, &wide);
+    assert_eq!(near, far);
+
+    // a larger max distance still tells them apart
+    let wider = LikelihoodFieldParams { max_distance: 5.0, ..wide };
+    let near = log_likelihood_field_range_finder_model_with_params(&beam(2.5), &pose, &grid, &wider);
+    let far = log_likelihood_field_range_finder_model_with_params(&beam(3.5), &pose, &grid, &wider);
+    assert!(near > far);
+    assert_eq!(grid.distance_field_up_to(5.0).max_distance(), 5.0);
+}
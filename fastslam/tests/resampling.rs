@@ -1,8 +1,11 @@
 use fastslam::particlefilter::particle::Particle;
 use fastslam::odometry::Pose;
 use fastslam::gridmap::grid_map::GridMap;
-use fastslam::particlefilter::resampling::low_variance_sampler;
+use fastslam::particlefilter::resampling::{low_variance_sampler, Multinomial, Residual, Resampler, ResamplingScheme, Stratified, Systematic};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use std::slice;
+use fastslam::sensor::laserscanner::{Measurement, Scan};
 
 #[test]
 fn test_resampling() {
@@ -25,4 +28,115 @@ fn test_resampling() {
     let resampled_particles = low_variance_sampler(&particles);
     let resampled_weights: Vec<f64> = resampled_particles.iter().map(|p| p.weight).collect();
     println!("resampled: {:?}", resampled_weights);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_resampled_particles_share_map_storage() {
+    let pose = Pose::default();
+    let scan = Scan { measurements: vec![Measurement { angle: 0.0, distance: 50.0 }] };
+    let mut gridmap = GridMap::default();
+    gridmap.update(&pose, &scan);
+
+    let particles: Vec<Particle> = vec![
+        Particle::new(pose, 0.5, gridmap.clone()),
+        Particle::new(pose, 0.5, gridmap.clone()),
+    ];
+
+    let resampled_particles = low_variance_sampler(&particles);
+    for p in resampled_particles.iter() {
+        assert_eq!(p.gridmap.shared_tile_count(&gridmap), gridmap.tile_count());
+    }
+}
+
+const SCHEMES: [ResamplingScheme; 4] = [
+    ResamplingScheme::Multinomial,
+    ResamplingScheme::Stratified,
+    ResamplingScheme::Systematic,
+    ResamplingScheme::Residual,
+];
+
+#[test]
+fn test_resampling_is_unbiased() {
+    // unnormalized, with a particle that must never be drawn
+    let weights = [0.7, 0.0, 2.1, 0.35, 3.85, 0.0, 7.0];
+    let total: f64 = weights.iter().sum();
+    let (n, trials) = (10, 20000);
+
+    for scheme in SCHEMES.iter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = vec![0usize; weights.len()];
+        for _ in 0..trials {
+            let indices = scheme.indices(&weights, n, &mut rng);
+            assert_eq!(indices.len(), n);
+            indices.iter().for_each(|&i| counts[i] += 1);
+        }
+
+        for (i, w) in weights.iter().enumerate() {
+            // at most the standard deviation of multinomial resampling, the worst scheme
+            let p = w / total;
+            let expected = n as f64 * p;
+            let std_dev = (n as f64 * p * (1.0 - p) / trials as f64).sqrt();
+            let mean = counts[i] as f64 / trials as f64;
+            assert!((mean - expected).abs() <= 4.0 * std_dev + 1e-12, "{:?}: particle {} drawn {} times on average, expected {}", scheme, i, mean, expected);
+        }
+    }
+}
+
+#[test]
+fn test_low_variance_schemes_stay_close_to_expected_counts() {
+    let weights = [0.7, 0.0, 2.1, 0.35, 3.85, 0.0, 7.0];
+    let total: f64 = weights.iter().sum();
+    let n = 10;
+    let mut rng = StdRng::seed_from_u64(7);
+
+    for _ in 0..1000 {
+        let systematic = Systematic.indices(&weights, n, &mut rng);
+        let residual = Residual.indices(&weights, n, &mut rng);
+        for (i, w) in weights.iter().enumerate() {
+            let expected = n as f64 * w / total;
+            let count = |indices: &[usize]| indices.iter().filter(|&&j| j == i).count() as f64;
+
+            // a systematic draw is off by less than one particle, residual keeps the integer part
+            assert!((count(&systematic) - expected).abs() < 1.0);
+            assert!(count(&residual) >= expected.floor());
+        }
+    }
+}
+
+#[test]
+fn test_resampling_degenerate_weights() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for scheme in SCHEMES.iter() {
+        // no weights
+        assert!(scheme.indices(&[], 5, &mut rng).is_empty());
+
+        // all zero, NaN or infinite: every particle is equally likely
+        for weights in [[0.0; 4], [f64::NAN; 4], [f64::INFINITY; 4]].iter() {
+            let indices = scheme.indices(weights, 4000, &mut rng);
+            for i in 0..4 {
+                let count = indices.iter().filter(|&&j| j == i).count();
+                assert!(count > 800 && count < 1200, "{:?}: {:?} drew particle {} {} times", scheme, weights, i, count);
+            }
+        }
+
+        // a single particle with weight
+        let indices = scheme.indices(&[0.0, 0.0, 1e-300, 0.0], 8, &mut rng);
+        assert_eq!(indices, vec![2; 8]);
+    }
+}
+
+#[test]
+fn test_resample_particles() {
+    let particles: Vec<Particle> = [1.0, 3.0, 0.0]
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| Particle::new(Pose::new(fastslam::geometry::Point::new(i as f64, 0.0), 0.0), w, GridMap::default()))
+        .collect();
+
+    for resampled in [Multinomial.resample(&particles), Stratified.resample(&particles), Residual.resample(&particles)].iter() {
+        assert_eq!(resampled.len(), 3);
+        assert!(resampled.iter().all(|p| (p.weight - 1.0 / 3.0).abs() < 1e-12));
+        assert!(resampled.iter().all(|p| (p.log_weight - (1.0f64 / 3.0).ln()).abs() < 1e-12));
+        assert!(resampled.iter().all(|p| p.pose.position.x != 2.0));
+    }
+}
@@ -0,0 +1,90 @@
+use fastslam::geometry::Point;
+use fastslam::odometry::Pose;
+use fastslam::pointcloud::PointCloud;
+use fastslam::scanmatching::icp::{icp_with_params, IcpMethod, IcpParams};
+use fastslam::scanmatching::robust::{OutlierRejection, RobustKernel};
+
+mod common;
+use common::room_scan;
+
+#[test]
+fn test_kernel_weights() {
+    assert_eq!(RobustKernel::Quadratic.weight(10.0), 1.0);
+
+    assert_eq!(RobustKernel::Huber(0.1).weight(0.05), 1.0);
+    assert!((RobustKernel::Huber(0.1).weight(-0.4) - 0.25).abs() < 1e-12);
+
+    assert_eq!(RobustKernel::Cauchy(0.1).weight(0.0), 1.0);
+    assert!((RobustKernel::Cauchy(0.1).weight(0.1) - 0.5).abs() < 1e-12);
+
+    assert_eq!(RobustKernel::Tukey(0.1).weight(0.0), 1.0);
+    assert!((RobustKernel::Tukey(0.1).weight(0.05) - 0.5625).abs() < 1e-12);
+    assert_eq!(RobustKernel::Tukey(0.1).weight(0.2), 0.0);
+
+    // weights never increase with the residual
+    for kernel in &[RobustKernel::Huber(0.1), RobustKernel::Cauchy(0.1), RobustKernel::Tukey(0.1)] {
+        let weights: Vec<f64> = (0..50).map(|i| kernel.weight(i as f64 * 0.01)).collect();
+        assert!(weights.windows(2).all(|w| w[1] <= w[0]));
+    }
+}
+
+#[test]
+fn test_outlier_rejection() {
+    let distances = [0.1, 0.5, 0.05, 2.0, 0.2];
+    let reciprocal = [true, true, false, true, true];
+
+    assert_eq!(OutlierRejection::default().select(&distances, &reciprocal), vec![0, 1, 2, 3, 4]);
+
+    let max_distance = OutlierRejection { max_distance: 0.3, ..OutlierRejection::default() };
+    assert_eq!(max_distance.select(&distances, &reciprocal), vec![0, 2, 4]);
+
+    let trimmed = OutlierRejection { trim_ratio: 0.4, ..OutlierRejection::default() };
+    assert_eq!(trimmed.select(&distances, &reciprocal), vec![0, 2, 4]);
+
+    let reciprocal_only = OutlierRejection { reciprocal: true, ..OutlierRejection::default() };
+    assert_eq!(reciprocal_only.select(&distances, &reciprocal), vec![0, 1, 3, 4]);
+
+    // residuals of NaN points are discarded, also when trimming
+    let with_nan = [0.1, f64::NAN, 0.05, f64::INFINITY, 0.2];
+    assert_eq!(OutlierRejection::default().select(&with_nan, &reciprocal), vec![0, 2, 4]);
+    let trimmed = OutlierRejection { trim_ratio: 0.4, ..OutlierRejection::default() };
+    assert_eq!(trimmed.select(&with_nan, &reciprocal), vec![0, 2]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_icp_with_dynamic_objects() {
+    let motion = Pose::new(Point::new(0.15, 0.1), 0.04);
+    let B = PointCloud::new(room_scan(Pose::default(), 0.0));
+
+    // a person standing in front of the robot in the current scan only, and a part of the
+    // room the reference scan did not see
+    let mut a = room_scan(motion, 0.0);
+    for p in a.iter_mut().take(30) {
+        *p = Point::new(0.8 + 0.01 * p.y, 0.2 + 0.01 * p.x);
+    }
+    for p in a.iter_mut().skip(150).take(40) {
+        *p = *p * 1.3;
+    }
+    let A = PointCloud::new(a);
+
+    let error = |params: &IcpParams| {
+        let result = icp_with_params(&A, &B, params);
+        result.transform.position.dist_to_point(motion.position)
+    };
+
+    let rejection = OutlierRejection { max_distance: 0.5, trim_ratio: 0.1, reciprocal: false };
+    for &method in &[IcpMethod::PointToPoint, IcpMethod::PointToLine] {
+        let plain = IcpParams { method, max_iterations: 50, ..IcpParams::default() };
+        assert!(error(&plain) > 0.3);
+
+        assert!(error(&IcpParams { rejection, ..plain }) < 0.05);
+        assert!(error(&IcpParams { rejection: OutlierRejection { reciprocal: true, ..rejection }, ..plain }) < 0.05);
+        assert!(error(&IcpParams { kernel: RobustKernel::Tukey(0.2), ..plain }) < 0.05);
+    }
+
+    let point_to_line = IcpParams { method: IcpMethod::PointToLine, max_iterations: 50, rejection, ..IcpParams::default() };
+    for &kernel in &[RobustKernel::Huber(0.2), RobustKernel::Cauchy(0.2), RobustKernel::Tukey(0.5)] {
+        assert!(error(&IcpParams { kernel, ..point_to_line }) < 0.005);
+    }
+}
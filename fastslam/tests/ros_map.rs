@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::PathBuf;
+use fastslam::gridmap::grid_map::{CellState, GridMap};
+use fastslam::gridmap::ros_map::{save_ros_map, load_ros_map, MapMetadata};
+use fastslam::odometry::pose::Pose;
+use fastslam::geometry::point::Point;
+use fastslam::math::scalar::PI;
+use fastslam::sensor::laserscanner::{Scan, Measurement};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fastslam_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let mut grid = GridMap::new(0.5);
+
+    let pose = Pose {
+        position: Point { x: -3.2, y: 1.7 },
+        heading: 0.3
+    };
+
+    let measurements = (0..90)
+        .map(|i| Measurement { angle: (i as f64) * PI/45.0, distance: 4.0 + (i % 7) as f64 })
+        .collect();
+    grid.update(&pose, &Scan { measurements });
+
+    let dir = temp_dir("round_trip");
+    let yaml_path = dir.join("map.yaml");
+    save_ros_map(&grid, &yaml_path).unwrap();
+    assert!(dir.join("map.pgm").exists());
+
+    let loaded = load_ros_map(&yaml_path).unwrap();
+    assert_eq!(loaded.cell_size(), grid.cell_size());
+
+    let bounds = grid.bounds().unwrap();
+    let mut occupied = 0;
+    for x in bounds.min_x..=bounds.max_x {
+        for y in bounds.min_y..=bounds.max_y {
+            assert_eq!(loaded.cell_state(x, y), grid.cell_state(x, y), "cell ({}, {})", x, y);
+            if grid.cell_state(x, y) == CellState::Occupied {
+                occupied += 1;
+            }
+        }
+    }
+    assert!(occupied > 0);
+    assert_eq!(loaded.get_all_occupied_cells().len(), occupied);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_yaml_metadata() {
+    let yaml = "image: office.pgm\n\
+                resolution: 0.050000\n\
+                origin: [-10.0, -5.5, 0.0]  # lower left pixel\n\
+                negate: 0\n\
+                occupied_thresh: 0.65\n\
+                free_thresh: 0.196\n";
+
+    let metadata = MapMetadata::from_yaml(yaml).unwrap();
+    assert_eq!(metadata.image, PathBuf::from("office.pgm"));
+    assert_eq!(metadata.resolution, 0.05);
+    assert_eq!(metadata.origin, [-10.0, -5.5, 0.0]);
+    assert_eq!(metadata.negate, false);
+    assert_eq!(MapMetadata::from_yaml(&metadata.to_yaml()).unwrap(), metadata);
+
+    assert!(MapMetadata::from_yaml("image: office.pgm\nresolution: 0.05\n").is_err());
+}
+
+#[test]
+fn test_load_plain_pgm_with_negate() {
+    let dir = temp_dir("negate");
+    fs::write(dir.join("small.pgm"), "P2\n# 3x2 map\n3 2\n255\n255 0 128\n0 0 255\n").unwrap();
+    fs::write(
+        dir.join("small.yaml"),
+        "image: small.pgm\nresolution: 1.0\norigin: [2.0, -1.0, 0.0]\nnegate: 1\noccupied_thresh: 0.65\nfree_thresh: 0.196\n"
+    ).unwrap();
+
+    let grid = load_ros_map(&dir.join("small.yaml")).unwrap();
+
+    // with negate, white pixels are occupied; the last image row is y = origin
+    assert_eq!(grid.cell_state(2, 0), CellState::Occupied);
+    assert_eq!(grid.cell_state(3, 0), CellState::Freespace);
+    assert_eq!(grid.cell_state(4, 0), CellState::Void);
+    assert_eq!(grid.cell_state(2, -1), CellState::Freespace);
+    assert_eq!(grid.cell_state(4, -1), CellState::Occupied);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_png() {
+    let dir = temp_dir("png");
+    let pixels = vec![0, 254, 205, 254, 254, 0];
+    image::GrayImage::from_raw(3, 2, pixels).unwrap().save(dir.join("small.png")).unwrap();
+    fs::write(
+        dir.join("small.yaml"),
+        "image: small.png\nresolution: 0.5\norigin: [-1.0, 0.0, 0.0]\nnegate: 0\noccupied_thresh: 0.65\nfree_thresh: 0.196\n"
+    ).unwrap();
+
+    let grid = load_ros_map(&dir.join("small.yaml")).unwrap();
+    assert_eq!(grid.cell_size(), 0.5);
+    assert_eq!(grid.cell_state(-2, 1), CellState::Occupied);
+    assert_eq!(grid.cell_state(-1, 1), CellState::Freespace);
+    assert_eq!(grid.cell_state(0, 1), CellState::Void);
+    assert_eq!(grid.cell_state(-2, 0), CellState::Freespace);
+    assert_eq!(grid.cell_state(0, 0), CellState::Occupied);
+    assert_eq!(grid.get_all_free_cells().len(), 3);
+
+    // not an image
+    fs::write(dir.join("small.png"), "not a png").unwrap();
+    assert!(load_ros_map(&dir.join("small.yaml")).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
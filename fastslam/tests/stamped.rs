@@ -0,0 +1,132 @@
+use fastslam::odometry::{Pose, Twist};
+use fastslam::particlefilter::config::FastSlamConfig;
+use fastslam::particlefilter::particle_filter::{InputError, ParticleFilter};
+use fastslam::sensor::stamped::Stamped;
+use fastslam::simulator::{Robot, Direction};
+
+mod common;
+use common::rectangular_room;
+
+#[test]
+fn test_dt_from_timestamps() {
+    use Direction::*;
+
+    let objects = rectangular_room();
+    let mut robot = Robot::default();
+    // without a spread of the proposal the particles draw the scan-matched pose, so the
+    // filters are deterministic
+    let deterministic = FastSlamConfig::builder().n_pose_samples(1).sampling_range(0.0);
+    let mut fixed = ParticleFilter::new(deterministic.clone().build().unwrap()).unwrap();
+    let mut stamped = ParticleFilter::new(deterministic.fixed_dt(None).build().unwrap()).unwrap();
+
+    // both start with a scan before the robot moves
+    let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+    fixed.cycle(&scan, &Twist::default());
+    assert_eq!(stamped.cycle_stamped(&Stamped::new(100.0, scan), &Stamped::new(100.0, Twist::default())), Ok(0.0));
+
+    // recorded every 2.0 s at half the speed of the simulated robot, which moves for 1.0 s
+    // per step, so both filters see the same motion
+    let mut t = 100.0;
+    for &dir in [Forward, Forward, Left, Forward, Right].iter() {
+        t += 2.0;
+        robot.move_forward(Some(dir));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+        let gain = robot.latest_gain.clone();
+        fixed.cycle(&scan, &gain);
+
+        let half_gain = Twist::new(gain.velocity * 0.5, gain.angular * 0.5);
+        let dt = stamped.cycle_stamped(&Stamped::new(t, scan), &Stamped::new(t - 0.01, half_gain)).unwrap();
+        assert_eq!(dt, 2.0);
+
+        let distance = fixed.best_particle.pose.position.dist_to_point(stamped.best_particle.pose.position);
+        assert!(distance < 1e-9);
+        assert!((fixed.best_particle.pose.heading - stamped.best_particle.pose.heading).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_rejected_inputs() {
+    let objects = rectangular_room();
+    let robot = Robot::default();
+    let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+    let stamped_scan = |t: f64| Stamped::new(t, scan.clone());
+    let gain = |t: f64| Stamped::new(t, Twist::default());
+
+    let mut particle_filter = ParticleFilter::default();
+    assert_eq!(particle_filter.cycle_stamped(&stamped_scan(5.0), &gain(5.0)), Ok(0.0));
+
+    // scans must be newer than the previous one
+    assert_eq!(
+        particle_filter.cycle_stamped(&stamped_scan(5.0), &gain(5.0)),
+        Err(InputError::OutOfOrder { timestamp: 5.0, previous: 5.0 })
+    );
+    assert_eq!(
+        particle_filter.cycle_stamped(&stamped_scan(4.0), &gain(4.0)),
+        Err(InputError::OutOfOrder { timestamp: 4.0, previous: 5.0 })
+    );
+
+    // the gain has to be measured around the time of the scan
+    assert_eq!(
+        particle_filter.cycle_stamped(&stamped_scan(7.0), &gain(5.5)),
+        Err(InputError::Stale { input: 5.5, scan: 7.0 })
+    );
+    assert_eq!(
+        particle_filter.cycle_stamped(&stamped_scan(7.0), &gain(8.0)),
+        Err(InputError::Stale { input: 8.0, scan: 7.0 })
+    );
+    assert!(matches!(
+        particle_filter.cycle_stamped(&stamped_scan(f64::NAN), &gain(7.0)),
+        Err(InputError::InvalidTimestamp(_))
+    ));
+
+    // rejected inputs do not change the time of the filter
+    assert_eq!(particle_filter.cycle_stamped(&stamped_scan(7.0), &gain(6.8)), Ok(2.0));
+    assert_eq!(particle_filter.best_particle.pose, Pose::default());
+}
+
+#[test]
+fn test_stamped_odometry() {
+    let objects = rectangular_room();
+    let mut robot = Robot::default();
+    robot.laser_scanner.num_columns = 360;
+    let mut particle_filter = ParticleFilter::default();
+
+    let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+    assert_eq!(particle_filter.cycle_odometry_stamped(&Stamped::new(10.0, scan), &Stamped::new(10.0, robot.odom.clone())), Ok(0.0));
+
+    let mut t = 10.0;
+    for _ in 0..3 {
+        t += 0.5;
+        robot.move_forward(Some(Direction::Forward));
+        let scan = robot.laser_scanner.scan(&robot.odom.pose, &objects);
+
+        // a stale or out of order reading is rejected before the filter moves
+        assert_eq!(
+            particle_filter.cycle_odometry_stamped(&Stamped::new(t, scan.clone()), &Stamped::new(t - 0.6, robot.odom.clone())),
+            Err(InputError::Stale { input: t - 0.6, scan: t })
+        );
+        assert_eq!(
+            particle_filter.cycle_odometry_stamped(&Stamped::new(t - 0.5, scan.clone()), &Stamped::new(t - 0.5, robot.odom.clone())),
+            Err(InputError::OutOfOrder { timestamp: t - 0.5, previous: t - 0.5 })
+        );
+
+        let dt = particle_filter.cycle_odometry_stamped(&Stamped::new(t, scan), &Stamped::new(t - 0.05, robot.odom.clone())).unwrap();
+        assert!((dt - 0.5).abs() < 1e-9);
+    }
+
+    // the filter followed the odometry of the accepted readings only
+    assert!(particle_filter.estimate.pose.position.dist_to_point(robot.odom.pose.position) < 0.1);
+}
+
+#[test]
+fn test_input_error_messages() {
+    assert_eq!(
+        InputError::OutOfOrder { timestamp: 4.0, previous: 5.0 }.to_string(),
+        "scan at 4 s is not newer than the previous scan at 5 s"
+    );
+    assert_eq!(
+        InputError::Stale { input: 5.5, scan: 7.0 }.to_string(),
+        "control input at 5.5 s is 1.5 s away from the scan at 7 s"
+    );
+    assert_eq!(InputError::InvalidTimestamp(f64::INFINITY).to_string(), "invalid timestamp inf");
+}
@@ -1,4 +1,4 @@
-use fastslam::math::utils::sigmoid;
+use fastslam::math::utils::{sigmoid, log_sum_exp, normal_quantile};
 
 #[test]
 fn test_sigmoid() {
@@ -9,4 +9,27 @@ fn test_sigmoid() {
 
     assert!(s_max > 0.99);
     assert!(s_min < 0.01)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_log_sum_exp() {
+    assert!((log_sum_exp(&[1.0f64.ln(), 2.0f64.ln(), 3.0f64.ln()]) - 6.0f64.ln()).abs() < 1e-12);
+
+    // probabilities that underflow to 0.0 and overflow to infinity
+    assert!((log_sum_exp(&[-1000.0, -1000.0]) - (-1000.0 + 2.0f64.ln())).abs() < 1e-9);
+    assert!((log_sum_exp(&[1000.0, 0.0]) - 1000.0).abs() < 1e-9);
+
+    assert_eq!(log_sum_exp(&[]), f64::NEG_INFINITY);
+    assert_eq!(log_sum_exp(&[f64::NEG_INFINITY, f64::NEG_INFINITY]), f64::NEG_INFINITY);
+}
+
+#[test]
+fn test_normal_quantile() {
+    assert!(normal_quantile(0.5).abs() < 1e-3);
+    assert!((normal_quantile(0.975) - 1.959964).abs() < 4.5e-4);
+    assert!((normal_quantile(0.99) - 2.326348).abs() < 4.5e-4);
+    assert!((normal_quantile(0.001) + 3.090232).abs() < 4.5e-4);
+    for p in [0.01, 0.2, 0.4].iter() {
+        assert!((normal_quantile(*p) + normal_quantile(1.0 - p)).abs() < 1e-12);
+    }
+}